@@ -0,0 +1,40 @@
+use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion};
+use std::hint::black_box;
+use gaudi::colormath::{PaletteMatcher, ANSI_COLORS};
+use gaudi::ColorDistance;
+
+/// A pseudo-random sample of the RGB cube, the same on every run
+fn sample_colors(count: usize) -> Vec<(u8, u8, u8)> {
+    let mut seed: u32 = 0x2545_F491;
+    (0..count)
+        .map(|_| {
+            seed = seed.wrapping_mul(1_664_525).wrapping_add(1_013_904_223);
+            ((seed >> 24) as u8, (seed >> 16) as u8, (seed >> 8) as u8)
+        })
+        .collect()
+}
+
+fn palette_matching(c: &mut Criterion) {
+    let colors = sample_colors(64 * 1024);
+    let mut group = c.benchmark_group("256 colour palette");
+
+    for distance in [ColorDistance::Rgb, ColorDistance::Redmean, ColorDistance::Cielab76, ColorDistance::Ciede2000, ColorDistance::Oklab] {
+        let matcher = PaletteMatcher::new(&ANSI_COLORS, distance);
+        // fill the lookup table first, so that only the lookups themselves are measured
+        for color in &colors {
+            matcher.closest_index(*color);
+        }
+
+        group.bench_with_input(BenchmarkId::new("exhaustive", format!("{:?}", distance)), &colors, |b, colors| {
+            b.iter(|| colors.iter().map(|color| matcher.closest_index_exhaustive(black_box(*color))).sum::<usize>())
+        });
+        group.bench_with_input(BenchmarkId::new("lookup table", format!("{:?}", distance)), &colors, |b, colors| {
+            b.iter(|| colors.iter().map(|color| matcher.closest_index(black_box(*color))).sum::<usize>())
+        });
+    }
+
+    group.finish();
+}
+
+criterion_group!(benches, palette_matching);
+criterion_main!(benches);
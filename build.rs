@@ -0,0 +1,36 @@
+use std::env;
+use std::fmt::Write;
+use std::fs;
+use std::path::Path;
+
+/// Compiles ansi256_table.json into the default 256 colour table, so the binary does not have to
+/// find or parse it at runtime.
+fn main() {
+    println!("cargo:rerun-if-changed=ansi256_table.json");
+
+    let json = fs::read_to_string("ansi256_table.json").expect("could not read ansi256_table.json");
+    let mut table: [Option<(u8, u8, u8)>; 256] = [None; 256];
+
+    // every entry is a `"NNN": "#rrggbb"` pair, in no particular order
+    let mut strings = json.split('"').skip(1).step_by(2);
+    while let (Some(key), Some(value)) = (strings.next(), strings.next()) {
+        let index: usize = key.parse().unwrap_or_else(|_| panic!("ansi256_table.json: invalid index {:?}", key));
+        let hex = value.strip_prefix('#').filter(|hex| hex.len() == 6)
+            .unwrap_or_else(|| panic!("ansi256_table.json: invalid colour {:?}", value));
+        let channel = |range| u8::from_str_radix(&hex[range], 16)
+            .unwrap_or_else(|_| panic!("ansi256_table.json: invalid colour {:?}", value));
+        let entry = table.get_mut(index).unwrap_or_else(|| panic!("ansi256_table.json: index {} out of range", index));
+        assert!(entry.is_none(), "ansi256_table.json: index {} defined twice", index);
+        *entry = Some((channel(0..2), channel(2..4), channel(4..6)));
+    }
+
+    let mut generated = String::from("[\n");
+    for (index, entry) in table.iter().enumerate() {
+        let (r, g, b) = entry.unwrap_or_else(|| panic!("ansi256_table.json: index {} is missing", index));
+        writeln!(generated, "    (0x{:02x}, 0x{:02x}, 0x{:02x}),", r, g, b).unwrap();
+    }
+    generated.push(']');
+
+    let out_dir = env::var("OUT_DIR").unwrap();
+    fs::write(Path::new(&out_dir).join("ansi256_table.rs"), generated).unwrap();
+}
@@ -0,0 +1,41 @@
+use std::time::Duration;
+use image::DynamicImage;
+
+/// One image of an animation, and how long it is shown before the next one.
+#[derive(Debug, Clone)]
+pub struct Frame {
+    pub image: DynamicImage,
+    pub delay: Duration,
+}
+
+/// How often an animation is played.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum Repeat {
+    Times(u32),
+    /// Until interrupted with Ctrl-C
+    Forever,
+}
+
+impl Default for Repeat {
+    fn default() -> Self {
+        Repeat::Times(1)
+    }
+}
+
+/// Delays up to this long are played as [DEFAULT_DELAY], as browsers do. Many GIFs rely on it.
+pub const MIN_DELAY: Duration = Duration::from_millis(10);
+pub const DEFAULT_DELAY: Duration = Duration::from_millis(100);
+
+/// The delay of the frame as it is played
+pub fn effective_delay(delay: Duration) -> Duration {
+    if delay <= MIN_DELAY {
+        DEFAULT_DELAY
+    } else {
+        delay
+    }
+}
+
+/// The delay in seconds, as `sleep` takes it
+pub fn seconds(delay: Duration) -> String {
+    format!("{}.{:03}", delay.as_secs(), delay.subsec_millis())
+}
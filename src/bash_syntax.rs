@@ -1,32 +1,185 @@
 use std::fmt;
-use std::fmt::{Display};
-use ansi_term::ANSIGenericString;
+use std::time::Duration;
+use ansi_term::{ANSIGenericString, Colour, Style};
+use crate::animation::Repeat;
+use crate::renderer::BrightStrategy;
+use crate::shell_syntax::{mode_chain, write_chain, write_sh_animation, write_sh_terminal_dispatch, ShellSyntax, TerminalCheck};
 
-pub fn write_with_minimal_control_sequences(spans: Vec<ANSIGenericString<'static, str>>, fmt: &mut fmt::Formatter) -> fmt::Result {
+/// How colours are written into the SGR control sequences.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ColorEncoding {
+    /// The way ansi_term writes them: the named colours as 30–37, `Fixed` as 256 colour codes and
+    /// `RGB` as 24 bit codes
+    Extended,
+    /// `Fixed(0)` to `Fixed(15)` as 16 colour codes, the bright ones according to the strategy
+    Ansi16(BrightStrategy),
+}
+
+/// The parts of a [Style] gaudi emits, after the bright strategy has been applied
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+struct Sgr {
+    bold: bool,
+    background: Option<Colour>,
+    foreground: Option<Colour>,
+}
+
+impl Sgr {
+    fn new(style: &Style, encoding: ColorEncoding) -> Self {
+        let is_bright = |colour: Option<Colour>| matches!(colour, Some(Colour::Fixed(8..=15)));
+        Sgr {
+            bold: style.is_bold
+                || (encoding == ColorEncoding::Ansi16(BrightStrategy::Bold) && is_bright(style.foreground)),
+            background: style.background,
+            foreground: style.foreground,
+        }
+    }
+
+    fn is_plain(&self) -> bool {
+        *self == Sgr::default()
+    }
+
+    /// Writes the sequence that turns the plain style into this one
+    fn write_prefix(&self, encoding: ColorEncoding, fmt: &mut fmt::Formatter) -> fmt::Result {
+        if self.is_plain() {
+            return Ok(());
+        }
+
+        let mut codes = Vec::with_capacity(3);
+        if self.bold {
+            codes.push("1".to_string());
+        }
+        if let Some(background) = self.background {
+            codes.push(colour_code(background, 40, encoding));
+        }
+        if let Some(foreground) = self.foreground {
+            codes.push(colour_code(foreground, 30, encoding));
+        }
+        write!(fmt, "\x1b[{}m", codes.join(";"))
+    }
+
+    /// Writes the shortest sequence that turns this style into `next`: the changed parts only,
+    /// unless something has to be switched off, which takes a full reset
+    fn write_infix(&self, next: &Sgr, encoding: ColorEncoding, fmt: &mut fmt::Formatter) -> fmt::Result {
+        if self == next {
+            return Ok(());
+        }
+
+        let switches_off = (self.bold && !next.bold)
+            || (self.background.is_some() && next.background.is_none())
+            || (self.foreground.is_some() && next.foreground.is_none());
+        if switches_off {
+            fmt.write_str(RESET)?;
+            return next.write_prefix(encoding, fmt);
+        }
+
+        Sgr {
+            bold: next.bold && !self.bold,
+            background: next.background.filter(|_| next.background != self.background),
+            foreground: next.foreground.filter(|_| next.foreground != self.foreground),
+        }.write_prefix(encoding, fmt)
+    }
+}
+
+const RESET: &str = "\x1b[0m";
+
+/// `base` is 30 for foreground and 40 for background colours
+fn colour_code(colour: Colour, base: u8, encoding: ColorEncoding) -> String {
+    let basic = |index: u8| (base + index).to_string();
+    match (colour, encoding) {
+        (Colour::Black, _) => basic(0),
+        (Colour::Red, _) => basic(1),
+        (Colour::Green, _) => basic(2),
+        (Colour::Yellow, _) => basic(3),
+        (Colour::Blue, _) => basic(4),
+        (Colour::Purple, _) => basic(5),
+        (Colour::Cyan, _) => basic(6),
+        (Colour::White, _) => basic(7),
+        (Colour::Fixed(index @ 0..=7), ColorEncoding::Ansi16(_)) => basic(index),
+        (Colour::Fixed(index @ 8..=15), ColorEncoding::Ansi16(BrightStrategy::Aixterm)) => (base + 60 + index - 8).to_string(),
+        // the bold attribute that goes with it is set by Sgr
+        (Colour::Fixed(index @ 8..=15), ColorEncoding::Ansi16(BrightStrategy::Bold)) => basic(index - 8),
+        (Colour::Fixed(index), _) => format!("{};5;{}", base + 8, index),
+        (Colour::RGB(r, g, b), _) => format!("{};2;{};{};{}", base + 8, r, g, b),
+    }
+}
+
+pub fn write_with_minimal_control_sequences(
+    spans: &[ANSIGenericString<'static, str>],
+    encoding: ColorEncoding,
+    fmt: &mut fmt::Formatter,
+) -> fmt::Result {
     if spans.is_empty() {
         return Ok(())
     }
 
-    let mut previous_style = spans.first().unwrap().style_ref().clone();
-    Display::fmt(&previous_style.prefix(), fmt)?;
+    let mut previous = Sgr::new(spans.first().unwrap().style_ref(), encoding);
+    previous.write_prefix(encoding, fmt)?;
 
-    for escape in &spans {
-        let next_style = escape.style_ref().clone();
-        Display::fmt(&previous_style.infix(next_style), fmt)?;
-        fmt.write_str(&escape)?;
-        previous_style = next_style
+    for escape in spans {
+        let next = Sgr::new(escape.style_ref(), encoding);
+        previous.write_infix(&next, encoding, fmt)?;
+        fmt.write_str(escape)?;
+        previous = next;
     }
 
-    Display::fmt(&(spans.last().unwrap().style_ref().suffix()), fmt)
+    if previous.is_plain() {
+        Ok(())
+    } else {
+        fmt.write_str(RESET)
+    }
 }
 
-pub fn escape_for_string_content(payload: &String) -> String {
+/// Sets `$gaudi_mode` to the colour mode the terminal supports, or to `plain` if colours are
+/// disabled or would end up somewhere else than on a terminal.
+pub const AUTO_DETECTION_PREAMBLE: &str = r#"gaudi_mode=ansi
+if [[ -n "${NO_COLOR-}" || "${TERM-}" == "dumb" || ! -t 1 ]]; then
+    gaudi_mode=plain
+elif [[ "${COLORTERM-}" == "truecolor" || "${COLORTERM-}" == "24bit" || "${TERM-}" == *-direct ]]; then
+    gaudi_mode=truecolor
+elif [[ "${TERM-}" == "linux" ]]; then
+    gaudi_mode=8
+else
+    if command -v tput >/dev/null 2>&1; then
+        gaudi_colors="$(tput colors 2>/dev/null)"
+    else
+        case "${TERM-}" in
+            *-256color) gaudi_colors=256 ;;
+            *) gaudi_colors=8 ;;
+        esac
+    fi
+    if [[ "$gaudi_colors" =~ ^[0-9]+$ ]]; then
+        if (( gaudi_colors >= 16777216 )); then
+            gaudi_mode=truecolor
+        elif (( gaudi_colors >= 256 )); then
+            gaudi_mode=256
+        elif (( gaudi_colors < 8 )); then
+            gaudi_mode=mono
+        fi
+    else
+        gaudi_mode=mono
+    fi
+fi
+"#;
+
+/// Sets `$gaudi_columns` to the width of the terminal, or to `$COLUMNS` where tput can't tell.
+pub const WIDTH_DETECTION_PREAMBLE: &str = r#"gaudi_columns="$(tput cols 2>/dev/null)"
+if [[ ! "$gaudi_columns" =~ ^[0-9]+$ ]]; then
+    gaudi_columns="${COLUMNS:-80}"
+fi
+"#;
+
+pub fn escape_for_string_content(payload: &str) -> String {
     let mut out = String::with_capacity(payload.len());
 
     for char in payload.chars() {
         match char {
-            '\\' | '"' => {
-                out.push_str("\\")
+            '\\' => {
+                // one level for the double quotes, one for echo -e
+                out.push_str("\\\\\\\\");
+                continue;
+            }
+            '"' | '$' | '`' => {
+                out.push('\\')
             },
             '\u{1b}' => {
                 out.push_str("\\e");
@@ -36,11 +189,16 @@ pub fn escape_for_string_content(payload: &String) -> String {
                 out.push_str("\\n");
                 continue;
             }
-            '\u{1d}' => {
+            '\r' => {
                 out.push_str("\\r");
                 continue;
             }
             _ => {
+                if (char as u32) > 0xFFFF {
+                    // outside the BMP, \u only takes four digits
+                    out.push_str(&format!("\\U{:08x}", char as u32));
+                    continue;
+                }
                 if !char.is_ascii() || char.is_control() {
                     out.push_str(&format!("\\u{:04x}", char as u32));
                     continue;
@@ -52,4 +210,125 @@ pub fn escape_for_string_content(payload: &String) -> String {
     }
 
     out
-}
\ No newline at end of file
+}
+
+pub struct BashSyntax;
+impl ShellSyntax for BashSyntax {
+    fn write_print(&self, escapes: &str, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "echo -e -n \"{}\"", escape_for_string_content(escapes))
+    }
+
+    fn auto_detection_preamble(&self) -> &'static str {
+        AUTO_DETECTION_PREAMBLE
+    }
+
+    fn write_dispatch(&self, variants: &[(&str, String)], fallback: &str, f: &mut fmt::Formatter) -> fmt::Result {
+        for (index, (name, escapes)) in variants.iter().enumerate() {
+            let keyword = if index == 0 { "if" } else { "elif" };
+            write!(f, "{} [[ \"$gaudi_mode\" == \"{}\" ]]; then\n    ", keyword, name)?;
+            self.write_print(escapes, f)?;
+            f.write_str("\n")?;
+        }
+        f.write_str("else\n    ")?;
+        self.write_print(fallback, f)?;
+        f.write_str("\nfi\nunset gaudi_mode gaudi_colors\n")
+    }
+
+    fn write_snippet_dispatch(&self, variants: &[(&str, String)], fallback: &str, f: &mut fmt::Formatter) -> fmt::Result {
+        write_chain(
+            &mode_chain(variants, fallback),
+            ["if {}; then", "elif {}; then", "else", "fi"],
+            &|mode| format!("[[ \"$gaudi_mode\" == \"{}\" ]]", mode),
+            f,
+        )?;
+        f.write_str("unset gaudi_mode gaudi_colors\n")
+    }
+
+    fn write_width_dispatch(&self, variants: &[(u32, String)], f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(WIDTH_DETECTION_PREAMBLE)?;
+        write_chain(
+            variants,
+            ["if {}; then", "elif {}; then", "else", "fi"],
+            &|columns| format!("(( gaudi_columns >= {} ))", columns),
+            f,
+        )?;
+        f.write_str("unset gaudi_columns\n")
+    }
+
+    fn write_terminal_dispatch(&self, check: TerminalCheck, escapes: &str, fallback: &str, f: &mut fmt::Formatter) -> fmt::Result {
+        write_sh_terminal_dispatch(self, check, escapes, fallback, f)
+    }
+
+    fn write_animation(&self, start: &str, frames: &[(String, Duration)], end: &str, repeat: Repeat, f: &mut fmt::Formatter) -> fmt::Result {
+        write_sh_animation(self, "trap -p INT", start, frames, end, repeat, f)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::process::Command;
+    use super::*;
+
+    struct Minimal<'a>(&'a [ANSIGenericString<'static, str>], ColorEncoding);
+
+    impl fmt::Display for Minimal<'_> {
+        fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+            write_with_minimal_control_sequences(self.0, self.1, f)
+        }
+    }
+
+    fn written(spans: &[ANSIGenericString<'static, str>], strategy: BrightStrategy) -> String {
+        Minimal(spans, ColorEncoding::Ansi16(strategy)).to_string()
+    }
+
+    #[test]
+    fn bright_foreground_under_bold_sets_bold() {
+        let spans = [Colour::Fixed(9).paint("x")];
+        assert_eq!(written(&spans, BrightStrategy::Bold), "\x1b[1;31mx\x1b[0m");
+    }
+
+    #[test]
+    fn bright_foreground_under_aixterm_uses_the_bright_codes() {
+        let spans = [Colour::Fixed(9).paint("x")];
+        assert_eq!(written(&spans, BrightStrategy::Aixterm), "\x1b[91mx\x1b[0m");
+        let spans = [Colour::Fixed(0).on(Colour::Fixed(12)).paint("x")];
+        assert_eq!(written(&spans, BrightStrategy::Aixterm), "\x1b[104;30mx\x1b[0m");
+    }
+
+    #[test]
+    fn bright_background_under_bold_falls_back_to_the_basic_colour() {
+        // bold only brightens the foreground
+        let spans = [Colour::Fixed(1).on(Colour::Fixed(12)).paint("x")];
+        assert_eq!(written(&spans, BrightStrategy::Bold), "\x1b[44;31mx\x1b[0m");
+    }
+
+    #[test]
+    fn infix_only_writes_what_changed() {
+        let spans = [Colour::Fixed(1).paint("a"), Colour::Fixed(1).on(Colour::Fixed(4)).paint("b")];
+        assert_eq!(written(&spans, BrightStrategy::Bold), "\x1b[31ma\x1b[44mb\x1b[0m");
+    }
+
+    #[test]
+    fn infix_resets_when_bold_switches_off() {
+        let spans = [Colour::Fixed(9).paint("a"), Colour::Fixed(1).paint("b")];
+        assert_eq!(written(&spans, BrightStrategy::Bold), "\x1b[1;31ma\x1b[0m\x1b[31mb\x1b[0m");
+    }
+
+    #[test]
+    fn escapes_round_trip_through_echo() {
+        let payload = "\x1b[31mred\x1b[0m\r\n\u{1d}\t\\n \"$HOME\" `true` é █ \u{1CD00}";
+        let output = Command::new("bash")
+            .arg("-c")
+            .arg(format!("echo -e -n \"{}\"", escape_for_string_content(payload)))
+            .env("LC_ALL", "C.UTF-8")
+            .output()
+            .expect("bash should run");
+        assert_eq!(String::from_utf8(output.stdout).unwrap(), payload);
+    }
+
+    #[test]
+    fn carriage_returns_are_escaped_as_such() {
+        assert_eq!(escape_for_string_content("\r"), "\\r");
+        assert_eq!(escape_for_string_content("\u{1d}"), "\\u001d");
+    }
+}
@@ -0,0 +1,414 @@
+/// How the distance between two colours is measured when picking the closest palette entry.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Default)]
+pub enum ColorDistance {
+    /// Euclidean distance of the raw sRGB bytes
+    #[default]
+    Rgb,
+    /// sRGB distance weighted by the mean red level, see <https://www.compuphase.com/cmetric.htm>
+    Redmean,
+    /// Euclidean distance in CIELAB (D65), a.k.a. ΔE*76
+    Cielab76,
+    /// ΔE*00 in CIELAB (D65). Its hue and chroma terms can't be bounded within a box of colours,
+    /// so palettes are searched exhaustively.
+    Ciede2000,
+    /// Euclidean distance in Oklab
+    Oklab,
+}
+
+impl ColorDistance {
+    /// Converts an sRGB colour into the coordinate space this metric operates in. Palettes do this
+    /// once per entry so that matching a pixel only has to convert the pixel.
+    pub fn to_coordinates(self, rgb: (u8, u8, u8)) -> [f32; 3] {
+        match self {
+            ColorDistance::Rgb | ColorDistance::Redmean => [rgb.0 as f32, rgb.1 as f32, rgb.2 as f32],
+            ColorDistance::Cielab76 | ColorDistance::Ciede2000 => srgb_to_lab(rgb),
+            ColorDistance::Oklab => srgb_to_oklab(rgb),
+        }
+    }
+
+    /// The distance between two colours given in the coordinates returned by [ColorDistance::to_coordinates].
+    /// Only suitable for comparing distances with each other; not necessarily a metric in the
+    /// mathematical sense.
+    pub fn distance(self, a: &[f32; 3], b: &[f32; 3]) -> f32 {
+        match self {
+            ColorDistance::Rgb | ColorDistance::Cielab76 | ColorDistance::Oklab => squared_euclidean(a, b),
+            ColorDistance::Redmean => redmean(a, b),
+            ColorDistance::Ciede2000 => ciede2000(a, b),
+        }
+    }
+}
+
+impl ColorDistance {
+    /// A box in the coordinate space of this metric that contains the coordinates of every colour
+    /// between `low` and `high`, channel by channel. This works because the conversions are a
+    /// monotonic step between linear maps with non-negative coefficients, followed by an
+    /// arbitrary linear map.
+    pub fn coordinate_bounds(self, low: (u8, u8, u8), high: (u8, u8, u8)) -> ([f32; 3], [f32; 3]) {
+        let (low, high) = match self {
+            ColorDistance::Rgb | ColorDistance::Redmean => (
+                [low.0 as f64, low.1 as f64, low.2 as f64],
+                [high.0 as f64, high.1 as f64, high.2 as f64],
+            ),
+            ColorDistance::Cielab76 | ColorDistance::Ciede2000 => {
+                let (low, high) = multiply_interval(&LAB_FROM_F, linear_to_lab_f(linear_rgb(low)), linear_to_lab_f(linear_rgb(high)));
+                (
+                    [low[0] + LAB_L_OFFSET, low[1], low[2]],
+                    [high[0] + LAB_L_OFFSET, high[1], high[2]],
+                )
+            }
+            ColorDistance::Oklab => multiply_interval(
+                &OKLAB_LMS_TO_LAB,
+                linear_to_oklab_lms(linear_rgb(low)),
+                linear_to_oklab_lms(linear_rgb(high)),
+            ),
+        };
+
+        (low.map(|c| c as f32), high.map(|c| c as f32))
+    }
+
+    /// The smallest and largest [ColorDistance::distance] between `point` and any coordinates in
+    /// the box from `low` to `high`, or None if this metric can't bound them.
+    pub fn distance_bounds(self, low: &[f32; 3], high: &[f32; 3], point: &[f32; 3]) -> Option<(f32, f32)> {
+        let mut near = [0.0f32; 3];
+        let mut far = [0.0f32; 3];
+        for channel in 0..3 {
+            near[channel] = (low[channel] - point[channel]).max(point[channel] - high[channel]).max(0.0);
+            far[channel] = (point[channel] - low[channel]).abs().max((high[channel] - point[channel]).abs());
+        }
+        let weighted = |d: &[f32; 3], weights: [f32; 3]| weights[0] * d[0] * d[0] + weights[1] * d[1] * d[1] + weights[2] * d[2] * d[2];
+
+        match self {
+            ColorDistance::Rgb | ColorDistance::Cielab76 | ColorDistance::Oklab => {
+                Some((weighted(&near, [1.0; 3]), weighted(&far, [1.0; 3])))
+            }
+            // the red and blue weights range from 2 to 3, depending on the mean red level
+            ColorDistance::Redmean => Some((weighted(&near, [2.0, 4.0, 2.0]), weighted(&far, [3.0, 4.0, 3.0]))),
+            ColorDistance::Ciede2000 => None,
+        }
+    }
+}
+
+fn squared_euclidean(a: &[f32; 3], b: &[f32; 3]) -> f32 {
+    (a[0] - b[0]).powi(2) + (a[1] - b[1]).powi(2) + (a[2] - b[2]).powi(2)
+}
+
+fn redmean(a: &[f32; 3], b: &[f32; 3]) -> f32 {
+    let mean_red = (a[0] + b[0]) / 2.0;
+    (2.0 + mean_red / 256.0) * (a[0] - b[0]).powi(2)
+        + 4.0 * (a[1] - b[1]).powi(2)
+        + (2.0 + (255.0 - mean_red) / 256.0) * (a[2] - b[2]).powi(2)
+}
+
+pub fn srgb_to_linear(channel: u8) -> f64 {
+    let c = channel as f64 / 255.0;
+    if c <= 0.04045 {
+        c / 12.92
+    } else {
+        ((c + 0.055) / 1.055).powf(2.4)
+    }
+}
+
+const SRGB_TO_XYZ: [[f64; 3]; 3] = [
+    [0.4124564, 0.3575761, 0.1804375],
+    [0.2126729, 0.7151522, 0.0721750],
+    [0.0193339, 0.1191920, 0.9503041],
+];
+const LAB_WHITE: [f64; 3] = [0.95047, 1.0, 1.08883];
+const LAB_FROM_F: [[f64; 3]; 3] = [
+    [0.0, 116.0, 0.0],
+    [500.0, -500.0, 0.0],
+    [0.0, 200.0, -200.0],
+];
+const LAB_L_OFFSET: f64 = -16.0;
+
+const OKLAB_LINEAR_TO_LMS: [[f64; 3]; 3] = [
+    [0.4122214708, 0.5363325363, 0.0514459929],
+    [0.2119034982, 0.6806995451, 0.1073969566],
+    [0.0883024619, 0.2817188376, 0.6299787005],
+];
+const OKLAB_LMS_TO_LAB: [[f64; 3]; 3] = [
+    [0.2104542553, 0.7936177850, -0.0040720468],
+    [1.9779984951, -2.4285922050, 0.4505937099],
+    [0.0259040371, 0.7827717662, -0.8086757660],
+];
+
+fn multiply(matrix: &[[f64; 3]; 3], v: [f64; 3]) -> [f64; 3] {
+    matrix.map(|row| row[0] * v[0] + row[1] * v[1] + row[2] * v[2])
+}
+
+/// The range of `matrix * v` for all `v` between `low` and `high`
+fn multiply_interval(matrix: &[[f64; 3]; 3], low: [f64; 3], high: [f64; 3]) -> ([f64; 3], [f64; 3]) {
+    let pick = |row: &[f64; 3], towards_low: bool| {
+        let mut sum = 0.0;
+        for channel in 0..3 {
+            let use_low = (row[channel] >= 0.0) == towards_low;
+            sum += row[channel] * if use_low { low[channel] } else { high[channel] };
+        }
+        sum
+    };
+    (matrix.map(|row| pick(&row, true)), matrix.map(|row| pick(&row, false)))
+}
+
+fn linear_rgb(rgb: (u8, u8, u8)) -> [f64; 3] {
+    [srgb_to_linear(rgb.0), srgb_to_linear(rgb.1), srgb_to_linear(rgb.2)]
+}
+
+fn lab_f(t: f64) -> f64 {
+    const DELTA: f64 = 6.0 / 29.0;
+    if t > DELTA * DELTA * DELTA {
+        t.cbrt()
+    } else {
+        t / (3.0 * DELTA * DELTA) + 4.0 / 29.0
+    }
+}
+
+/// The non-linear step of CIELAB, from linear RGB; monotonic in every channel
+fn linear_to_lab_f(linear: [f64; 3]) -> [f64; 3] {
+    let xyz = multiply(&SRGB_TO_XYZ, linear);
+    [lab_f(xyz[0] / LAB_WHITE[0]), lab_f(xyz[1] / LAB_WHITE[1]), lab_f(xyz[2] / LAB_WHITE[2])]
+}
+
+pub fn srgb_to_lab(rgb: (u8, u8, u8)) -> [f32; 3] {
+    let [l, a, b] = multiply(&LAB_FROM_F, linear_to_lab_f(linear_rgb(rgb)));
+    [(l + LAB_L_OFFSET) as f32, a as f32, b as f32]
+}
+
+/// The non-linear step of Oklab, from linear RGB; monotonic in every channel
+fn linear_to_oklab_lms(linear: [f64; 3]) -> [f64; 3] {
+    multiply(&OKLAB_LINEAR_TO_LMS, linear).map(f64::cbrt)
+}
+
+pub fn srgb_to_oklab(rgb: (u8, u8, u8)) -> [f32; 3] {
+    multiply(&OKLAB_LMS_TO_LAB, linear_to_oklab_lms(linear_rgb(rgb))).map(|c| c as f32)
+}
+
+/// ΔE*00 as specified in CIE 142-2001, with k_L = k_C = k_H = 1. Returns the squared difference
+/// so that it ranks the same way as the other metrics without an extra square root.
+fn ciede2000(lab1: &[f32; 3], lab2: &[f32; 3]) -> f32 {
+    use std::f32::consts::PI;
+
+    let [l1, a1, b1] = *lab1;
+    let [l2, a2, b2] = *lab2;
+
+    let c1 = (a1 * a1 + b1 * b1).sqrt();
+    let c2 = (a2 * a2 + b2 * b2).sqrt();
+    let c_mean = (c1 + c2) / 2.0;
+    let c_mean_pow7 = c_mean.powi(7);
+    let g = 0.5 * (1.0 - (c_mean_pow7 / (c_mean_pow7 + 25f32.powi(7))).sqrt());
+
+    let a1p = (1.0 + g) * a1;
+    let a2p = (1.0 + g) * a2;
+    let c1p = (a1p * a1p + b1 * b1).sqrt();
+    let c2p = (a2p * a2p + b2 * b2).sqrt();
+
+    let hue = |b: f32, ap: f32| {
+        if b == 0.0 && ap == 0.0 {
+            0.0
+        } else {
+            let h = b.atan2(ap);
+            if h < 0.0 { h + 2.0 * PI } else { h }
+        }
+    };
+    let h1p = hue(b1, a1p);
+    let h2p = hue(b2, a2p);
+
+    let delta_lp = l2 - l1;
+    let delta_cp = c2p - c1p;
+    let delta_hp = if c1p * c2p == 0.0 {
+        0.0
+    } else if (h2p - h1p).abs() <= PI {
+        h2p - h1p
+    } else if h2p - h1p > PI {
+        h2p - h1p - 2.0 * PI
+    } else {
+        h2p - h1p + 2.0 * PI
+    };
+    let delta_big_hp = 2.0 * (c1p * c2p).sqrt() * (delta_hp / 2.0).sin();
+
+    let l_mean_p = (l1 + l2) / 2.0;
+    let c_mean_p = (c1p + c2p) / 2.0;
+    let h_mean_p = if c1p * c2p == 0.0 {
+        h1p + h2p
+    } else if (h1p - h2p).abs() <= PI {
+        (h1p + h2p) / 2.0
+    } else if h1p + h2p < 2.0 * PI {
+        (h1p + h2p + 2.0 * PI) / 2.0
+    } else {
+        (h1p + h2p - 2.0 * PI) / 2.0
+    };
+
+    let t = 1.0 - 0.17 * (h_mean_p - 30f32.to_radians()).cos()
+        + 0.24 * (2.0 * h_mean_p).cos()
+        + 0.32 * (3.0 * h_mean_p + 6f32.to_radians()).cos()
+        - 0.20 * (4.0 * h_mean_p - 63f32.to_radians()).cos();
+    let delta_theta = 30f32.to_radians() * (-((h_mean_p.to_degrees() - 275.0) / 25.0).powi(2)).exp();
+    let c_mean_p_pow7 = c_mean_p.powi(7);
+    let r_c = 2.0 * (c_mean_p_pow7 / (c_mean_p_pow7 + 25f32.powi(7))).sqrt();
+    let l_offset = (l_mean_p - 50.0).powi(2);
+    let s_l = 1.0 + (0.015 * l_offset) / (20.0 + l_offset).sqrt();
+    let s_c = 1.0 + 0.045 * c_mean_p;
+    let s_h = 1.0 + 0.015 * c_mean_p * t;
+    let r_t = -(2.0 * delta_theta).sin() * r_c;
+
+    let lightness = delta_lp / s_l;
+    let chroma = delta_cp / s_c;
+    let hue = delta_big_hp / s_h;
+
+    lightness * lightness + chroma * chroma + hue * hue + r_t * chroma * hue
+}
+
+#[cfg(test)]
+mod tests {
+    use ansi_term::Colour;
+    use image::Rgba;
+    use crate::colormath::{color_mapping_256, ColorMappers};
+    use super::*;
+
+    fn assert_close(actual: [f32; 3], expected: [f32; 3], tolerance: f32) {
+        for channel in 0..3 {
+            assert!((actual[channel] - expected[channel]).abs() <= tolerance, "{:?} instead of {:?}", actual, expected);
+        }
+    }
+
+    fn delta_e00(lab1: [f32; 3], lab2: [f32; 3]) -> f32 {
+        ColorDistance::Ciede2000.distance(&lab1, &lab2).sqrt()
+    }
+
+    #[test]
+    fn white_and_black_are_the_ends_of_the_lightness_axes() {
+        assert_close(srgb_to_lab((255, 255, 255)), [100.0, 0.0, 0.0], 1e-3);
+        assert_close(srgb_to_lab((0, 0, 0)), [0.0, 0.0, 0.0], 1e-6);
+        // the lightness of Oklab goes from 0 to 1
+        assert_close(srgb_to_oklab((255, 255, 255)), [1.0, 0.0, 0.0], 1e-4);
+        assert_close(srgb_to_oklab((0, 0, 0)), [0.0, 0.0, 0.0], 1e-6);
+    }
+
+    #[test]
+    fn primaries_have_their_published_coordinates() {
+        assert_close(srgb_to_lab((255, 0, 0)), [53.2408, 80.0925, 67.2032], 2e-3);
+        assert_close(srgb_to_lab((0, 0, 255)), [32.2970, 79.1875, -107.8602], 2e-3);
+        assert_close(srgb_to_oklab((255, 0, 0)), [0.627955, 0.224863, 0.125846], 1e-4);
+        assert_close(srgb_to_oklab((0, 0, 255)), [0.452014, -0.032457, -0.311528], 1e-4);
+    }
+
+    #[test]
+    fn redmean_weighs_red_and_blue_by_the_mean_red_level() {
+        let distance = |a: (u8, u8, u8), b: (u8, u8, u8)| {
+            let metric = ColorDistance::Redmean;
+            metric.distance(&metric.to_coordinates(a), &metric.to_coordinates(b))
+        };
+        assert_eq!(distance((0, 0, 0), (0, 10, 0)), 400.0);
+        // among dark colours red differences count less than blue ones, among light ones more
+        assert!(distance((0, 0, 0), (10, 0, 0)) < distance((0, 0, 0), (0, 0, 10)));
+        assert!(distance((245, 0, 0), (255, 0, 0)) > distance((255, 0, 0), (255, 0, 10)));
+    }
+
+    #[test]
+    fn cielab76_is_the_squared_euclidean_distance_in_lab() {
+        let metric = ColorDistance::Cielab76;
+        let (a, b) = (metric.to_coordinates((255, 0, 0)), metric.to_coordinates((0, 0, 255)));
+        let expected = (a[0] - b[0]).powi(2) + (a[1] - b[1]).powi(2) + (a[2] - b[2]).powi(2);
+        assert_eq!(metric.distance(&a, &b), expected);
+        assert_eq!(metric.distance(&a, &a), 0.0);
+    }
+
+    /// The test data of Sharma, Wu and Dalal, "The CIEDE2000 color-difference formula:
+    /// implementation notes, supplementary test data, and mathematical observations" (2005)
+    #[test]
+    fn ciede2000_matches_the_reference_pairs() {
+        const PAIRS: [([f32; 3], [f32; 3], f32); 34] = [
+            ([50.0, 2.6772, -79.7751], [50.0, 0.0, -82.7485], 2.0425),
+            ([50.0, 3.1571, -77.2803], [50.0, 0.0, -82.7485], 2.8615),
+            ([50.0, 2.8361, -74.0200], [50.0, 0.0, -82.7485], 3.4412),
+            ([50.0, -1.3802, -84.2814], [50.0, 0.0, -82.7485], 1.0000),
+            ([50.0, -1.1848, -84.8006], [50.0, 0.0, -82.7485], 1.0000),
+            ([50.0, -0.9009, -85.5211], [50.0, 0.0, -82.7485], 1.0000),
+            ([50.0, 0.0, 0.0], [50.0, -1.0, 2.0], 2.3669),
+            ([50.0, -1.0, 2.0], [50.0, 0.0, 0.0], 2.3669),
+            ([50.0, 2.4900, -0.0010], [50.0, -2.4900, 0.0009], 7.1792),
+            ([50.0, 2.4900, -0.0010], [50.0, -2.4900, 0.0010], 7.1792),
+            ([50.0, 2.4900, -0.0010], [50.0, -2.4900, 0.0011], 7.2195),
+            ([50.0, 2.4900, -0.0010], [50.0, -2.4900, 0.0012], 7.2195),
+            ([50.0, -0.0010, 2.4900], [50.0, 0.0009, -2.4900], 4.8045),
+            ([50.0, -0.0010, 2.4900], [50.0, 0.0010, -2.4900], 4.8045),
+            ([50.0, -0.0010, 2.4900], [50.0, 0.0011, -2.4900], 4.7461),
+            ([50.0, 2.5000, 0.0000], [50.0, 0.0000, -2.5000], 4.3065),
+            ([50.0, 2.5000, 0.0000], [73.0, 25.0000, -18.0000], 27.1492),
+            ([50.0, 2.5000, 0.0000], [61.0, -5.0000, 29.0000], 22.8977),
+            ([50.0, 2.5000, 0.0000], [56.0, -27.0000, -3.0000], 31.9030),
+            ([50.0, 2.5000, 0.0000], [58.0, 24.0000, 15.0000], 19.4535),
+            ([50.0, 2.5000, 0.0000], [50.0, 3.1736, 0.5854], 1.0000),
+            ([50.0, 2.5000, 0.0000], [50.0, 3.2972, 0.0000], 1.0000),
+            ([50.0, 2.5000, 0.0000], [50.0, 1.8634, 0.5757], 1.0000),
+            ([50.0, 2.5000, 0.0000], [50.0, 3.2592, 0.3350], 1.0000),
+            ([60.2574, -34.0099, 36.2677], [60.4626, -34.1751, 39.4387], 1.2644),
+            ([63.0109, -31.0961, -5.8663], [62.8187, -29.7946, -4.0864], 1.2630),
+            ([61.2901, 3.7196, -5.3901], [61.4292, 2.2480, -4.9620], 1.8731),
+            ([35.0831, -44.1164, 3.7933], [35.0232, -40.0716, 1.5901], 1.8645),
+            ([22.7233, 20.0904, -46.6940], [23.0331, 14.9730, -42.5619], 2.0373),
+            ([36.4612, 47.8580, 18.3852], [36.2715, 50.5065, 21.2231], 1.4146),
+            ([90.8027, -2.0831, 1.4410], [91.1528, -1.6435, 0.0447], 1.4441),
+            ([90.9257, -0.5406, -0.9208], [88.6381, -0.8985, -0.7239], 1.5381),
+            ([6.7747, -0.2908, -2.4247], [5.8714, -0.0985, -2.2286], 0.6377),
+            ([2.0776, 0.0795, -1.1350], [0.9033, -0.0636, -0.5514], 0.9082),
+        ];
+        // the hue angles of pairs 10 and 14 are exactly 180° apart, where the mean hue switches
+        // sides; in f32 they can end up on either, which gives the value of pair 11 or 15
+        let on_either_side = |pair: usize| match pair {
+            10 => Some(7.2195),
+            14 => Some(4.7461),
+            _ => None,
+        };
+        for (index, (lab1, lab2, expected)) in PAIRS.into_iter().enumerate() {
+            let matches = |actual: f32| {
+                (actual - expected).abs() < 1e-3 || on_either_side(index + 1).is_some_and(|other| (actual - other).abs() < 1e-3)
+            };
+            let actual = delta_e00(lab1, lab2);
+            assert!(matches(actual), "pair {}: {} instead of {}", index + 1, actual, expected);
+            assert!(matches(delta_e00(lab2, lab1)), "pair {} swapped", index + 1);
+        }
+    }
+
+    #[test]
+    fn ciede2000_keeps_dark_blues_blue() {
+        let dark_blue = Rgba([24, 28, 72, 255]);
+        let by_rgb = color_mapping_256(&dark_blue);
+        let by_ciede2000 = ColorMappers::new(ColorDistance::Ciede2000, &Default::default(), &Default::default()).ansi256(&dark_blue);
+        // the raw bytes are closer to a dark grey than to the darkest blue of the colour cube
+        assert_eq!(by_rgb, Colour::Fixed(235));
+        assert_eq!(by_ciede2000, Colour::Fixed(17));
+
+        let (target, navy, grey) = (srgb_to_lab((24, 28, 72)), srgb_to_lab((0, 0, 95)), srgb_to_lab((38, 38, 38)));
+        assert!(delta_e00(target, navy) < delta_e00(target, grey));
+        assert!(target[2] < -20.0 && navy[2] < -20.0 && grey[2].abs() < 1.0, "{:?} {:?} {:?}", target, navy, grey);
+    }
+
+    #[test]
+    fn bounds_contain_the_coordinates_and_distances_of_every_colour_in_the_box() {
+        let (low, high) = ((40, 100, 200), (47, 107, 207));
+        let point_rgb = (180, 20, 90);
+        for metric in [ColorDistance::Rgb, ColorDistance::Redmean, ColorDistance::Cielab76, ColorDistance::Oklab] {
+            let (box_low, box_high) = metric.coordinate_bounds(low, high);
+            let point = metric.to_coordinates(point_rgb);
+            let (near, far) = metric.distance_bounds(&box_low, &box_high, &point).unwrap();
+            for r in low.0..=high.0 {
+                for g in low.1..=high.1 {
+                    for b in low.2..=high.2 {
+                        let coordinates = metric.to_coordinates((r, g, b));
+                        for channel in 0..3 {
+                            assert!(
+                                box_low[channel] - 1e-4 <= coordinates[channel] && coordinates[channel] <= box_high[channel] + 1e-4,
+                                "{:?}: {:?} outside of {:?}..{:?}", metric, (r, g, b), box_low, box_high,
+                            );
+                        }
+                        let distance = metric.distance(&coordinates, &point);
+                        assert!(near * 0.999 <= distance && distance <= far * 1.001, "{:?}: {:?}", metric, (r, g, b));
+                    }
+                }
+            }
+        }
+        let ciede2000 = ColorDistance::Ciede2000;
+        let (box_low, box_high) = ciede2000.coordinate_bounds(low, high);
+        assert_eq!(ciede2000.distance_bounds(&box_low, &box_high, &ciede2000.to_coordinates(point_rgb)), None);
+    }
+}
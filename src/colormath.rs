@@ -1,624 +1,429 @@
+use std::sync::{LazyLock, OnceLock};
 use ansi_term::Colour;
-use colored::Color::{Black, Blue, Cyan, Green, Magenta, Red, TrueColor, White, Yellow};
-use image::Rgba;
+use image::{DynamicImage, Rgba};
+use crate::color_distance::ColorDistance;
+use crate::palette::{Ansi256Palette, TerminalPalette};
+
+pub type ColorMapper<'a> = dyn Fn(&Rgba<u8>) -> Colour + 'a;
 
 pub fn color_mapping_truecolor(pixel: &Rgba<u8>) -> Colour {
     Colour::RGB(pixel[0], pixel[1], pixel[2])
 }
 
 pub fn color_mapping_ansi(pixel: &Rgba<u8>) -> Colour {
-    let colored_v = TrueColor {
-        r: pixel[0],
-        g: pixel[1],
-        b: pixel[2],
-    };
-
-    let closest_colored_v = pick_closest_from(
-        colored_v,
-        &[
-            Black,
-            Red,
-            Green,
-            Yellow,
-            Blue,
-            Magenta,
-            Cyan,
-            White,
-        ],
-        into_truecolor,
-    ).unwrap();
-
-    match closest_colored_v {
-        Black => Colour::Black,
-        Red => Colour::Red,
-        Green => Colour::Red,
-        Yellow => Colour::Red,
-        Blue => Colour::Red,
-        Magenta => Colour::Red,
-        Cyan => Colour::Red,
-        White => Colour::Red,
-        _ => panic!()
-    }
+    DEFAULT_MAPPERS.ansi(pixel)
 }
 
 pub fn color_mapping_256(pixel: &Rgba<u8>) -> Colour {
-    let colored_v = TrueColor {
-        r: pixel[0],
-        g: pixel[1],
-        b: pixel[2],
-    };
+    DEFAULT_MAPPERS.ansi256(pixel)
+}
 
-    *pick_closest_from(colored_v, &ANSI_COLORS, |c| {
-        if let Colour::Fixed(index) = *c {
-            ANSI_COLOR_TO_TRUECOLOR[index as usize]
-        } else {
-            panic!("Not a fixed color");
-        }
-    }).unwrap()
+/// Rec. 601 luma of the pixel composited onto black, in the range 0 to 255
+pub fn luminance(pixel: &Rgba<u8>) -> f32 {
+    let luma = 0.299 * pixel[0] as f32 + 0.587 * pixel[1] as f32 + 0.114 * pixel[2] as f32;
+    luma * pixel[3] as f32 / 255.0
+}
+
+/// The pixel with its colour replaced by the grey of the same Rec. 601 luma, alpha untouched
+pub fn to_grayscale(pixel: &Rgba<u8>) -> Rgba<u8> {
+    let luma = (0.299 * pixel[0] as f32 + 0.587 * pixel[1] as f32 + 0.114 * pixel[2] as f32).round() as u8;
+    Rgba([luma, luma, luma, pixel[3]])
 }
 
-pub static ANSI_COLORS: [Colour; 256] = [
-    Colour::Fixed(0x00),
-    Colour::Fixed(0x01),
-    Colour::Fixed(0x02),
-    Colour::Fixed(0x03),
-    Colour::Fixed(0x04),
-    Colour::Fixed(0x05),
-    Colour::Fixed(0x06),
-    Colour::Fixed(0x07),
-    Colour::Fixed(0x08),
-    Colour::Fixed(0x09),
-    Colour::Fixed(0x0A),
-    Colour::Fixed(0x0B),
-    Colour::Fixed(0x0C),
-    Colour::Fixed(0x0D),
-    Colour::Fixed(0x0E),
-    Colour::Fixed(0x0F),
-    Colour::Fixed(0x10),
-    Colour::Fixed(0x11),
-    Colour::Fixed(0x12),
-    Colour::Fixed(0x13),
-    Colour::Fixed(0x14),
-    Colour::Fixed(0x15),
-    Colour::Fixed(0x16),
-    Colour::Fixed(0x17),
-    Colour::Fixed(0x18),
-    Colour::Fixed(0x19),
-    Colour::Fixed(0x1A),
-    Colour::Fixed(0x1B),
-    Colour::Fixed(0x1C),
-    Colour::Fixed(0x1D),
-    Colour::Fixed(0x1E),
-    Colour::Fixed(0x1F),
-    Colour::Fixed(0x20),
-    Colour::Fixed(0x21),
-    Colour::Fixed(0x22),
-    Colour::Fixed(0x23),
-    Colour::Fixed(0x24),
-    Colour::Fixed(0x25),
-    Colour::Fixed(0x26),
-    Colour::Fixed(0x27),
-    Colour::Fixed(0x28),
-    Colour::Fixed(0x29),
-    Colour::Fixed(0x2A),
-    Colour::Fixed(0x2B),
-    Colour::Fixed(0x2C),
-    Colour::Fixed(0x2D),
-    Colour::Fixed(0x2E),
-    Colour::Fixed(0x2F),
-    Colour::Fixed(0x30),
-    Colour::Fixed(0x31),
-    Colour::Fixed(0x32),
-    Colour::Fixed(0x33),
-    Colour::Fixed(0x34),
-    Colour::Fixed(0x35),
-    Colour::Fixed(0x36),
-    Colour::Fixed(0x37),
-    Colour::Fixed(0x38),
-    Colour::Fixed(0x39),
-    Colour::Fixed(0x3A),
-    Colour::Fixed(0x3B),
-    Colour::Fixed(0x3C),
-    Colour::Fixed(0x3D),
-    Colour::Fixed(0x3E),
-    Colour::Fixed(0x3F),
-    Colour::Fixed(0x40),
-    Colour::Fixed(0x41),
-    Colour::Fixed(0x42),
-    Colour::Fixed(0x43),
-    Colour::Fixed(0x44),
-    Colour::Fixed(0x45),
-    Colour::Fixed(0x46),
-    Colour::Fixed(0x47),
-    Colour::Fixed(0x48),
-    Colour::Fixed(0x49),
-    Colour::Fixed(0x4A),
-    Colour::Fixed(0x4B),
-    Colour::Fixed(0x4C),
-    Colour::Fixed(0x4D),
-    Colour::Fixed(0x4E),
-    Colour::Fixed(0x4F),
-    Colour::Fixed(0x50),
-    Colour::Fixed(0x51),
-    Colour::Fixed(0x52),
-    Colour::Fixed(0x53),
-    Colour::Fixed(0x54),
-    Colour::Fixed(0x55),
-    Colour::Fixed(0x56),
-    Colour::Fixed(0x57),
-    Colour::Fixed(0x58),
-    Colour::Fixed(0x59),
-    Colour::Fixed(0x5A),
-    Colour::Fixed(0x5B),
-    Colour::Fixed(0x5C),
-    Colour::Fixed(0x5D),
-    Colour::Fixed(0x5E),
-    Colour::Fixed(0x5F),
-    Colour::Fixed(0x60),
-    Colour::Fixed(0x61),
-    Colour::Fixed(0x62),
-    Colour::Fixed(0x63),
-    Colour::Fixed(0x64),
-    Colour::Fixed(0x65),
-    Colour::Fixed(0x66),
-    Colour::Fixed(0x67),
-    Colour::Fixed(0x68),
-    Colour::Fixed(0x69),
-    Colour::Fixed(0x6A),
-    Colour::Fixed(0x6B),
-    Colour::Fixed(0x6C),
-    Colour::Fixed(0x6D),
-    Colour::Fixed(0x6E),
-    Colour::Fixed(0x6F),
-    Colour::Fixed(0x70),
-    Colour::Fixed(0x71),
-    Colour::Fixed(0x72),
-    Colour::Fixed(0x73),
-    Colour::Fixed(0x74),
-    Colour::Fixed(0x75),
-    Colour::Fixed(0x76),
-    Colour::Fixed(0x77),
-    Colour::Fixed(0x78),
-    Colour::Fixed(0x79),
-    Colour::Fixed(0x7A),
-    Colour::Fixed(0x7B),
-    Colour::Fixed(0x7C),
-    Colour::Fixed(0x7D),
-    Colour::Fixed(0x7E),
-    Colour::Fixed(0x7F),
-    Colour::Fixed(0x80),
-    Colour::Fixed(0x81),
-    Colour::Fixed(0x82),
-    Colour::Fixed(0x83),
-    Colour::Fixed(0x84),
-    Colour::Fixed(0x85),
-    Colour::Fixed(0x86),
-    Colour::Fixed(0x87),
-    Colour::Fixed(0x88),
-    Colour::Fixed(0x89),
-    Colour::Fixed(0x8A),
-    Colour::Fixed(0x8B),
-    Colour::Fixed(0x8C),
-    Colour::Fixed(0x8D),
-    Colour::Fixed(0x8E),
-    Colour::Fixed(0x8F),
-    Colour::Fixed(0x90),
-    Colour::Fixed(0x91),
-    Colour::Fixed(0x92),
-    Colour::Fixed(0x93),
-    Colour::Fixed(0x94),
-    Colour::Fixed(0x95),
-    Colour::Fixed(0x96),
-    Colour::Fixed(0x97),
-    Colour::Fixed(0x98),
-    Colour::Fixed(0x99),
-    Colour::Fixed(0x9A),
-    Colour::Fixed(0x9B),
-    Colour::Fixed(0x9C),
-    Colour::Fixed(0x9D),
-    Colour::Fixed(0x9E),
-    Colour::Fixed(0x9F),
-    Colour::Fixed(0xA0),
-    Colour::Fixed(0xA1),
-    Colour::Fixed(0xA2),
-    Colour::Fixed(0xA3),
-    Colour::Fixed(0xA4),
-    Colour::Fixed(0xA5),
-    Colour::Fixed(0xA6),
-    Colour::Fixed(0xA7),
-    Colour::Fixed(0xA8),
-    Colour::Fixed(0xA9),
-    Colour::Fixed(0xAA),
-    Colour::Fixed(0xAB),
-    Colour::Fixed(0xAC),
-    Colour::Fixed(0xAD),
-    Colour::Fixed(0xAE),
-    Colour::Fixed(0xAF),
-    Colour::Fixed(0xB0),
-    Colour::Fixed(0xB1),
-    Colour::Fixed(0xB2),
-    Colour::Fixed(0xB3),
-    Colour::Fixed(0xB4),
-    Colour::Fixed(0xB5),
-    Colour::Fixed(0xB6),
-    Colour::Fixed(0xB7),
-    Colour::Fixed(0xB8),
-    Colour::Fixed(0xB9),
-    Colour::Fixed(0xBA),
-    Colour::Fixed(0xBB),
-    Colour::Fixed(0xBC),
-    Colour::Fixed(0xBD),
-    Colour::Fixed(0xBE),
-    Colour::Fixed(0xBF),
-    Colour::Fixed(0xC0),
-    Colour::Fixed(0xC1),
-    Colour::Fixed(0xC2),
-    Colour::Fixed(0xC3),
-    Colour::Fixed(0xC4),
-    Colour::Fixed(0xC5),
-    Colour::Fixed(0xC6),
-    Colour::Fixed(0xC7),
-    Colour::Fixed(0xC8),
-    Colour::Fixed(0xC9),
-    Colour::Fixed(0xCA),
-    Colour::Fixed(0xCB),
-    Colour::Fixed(0xCC),
-    Colour::Fixed(0xCD),
-    Colour::Fixed(0xCE),
-    Colour::Fixed(0xCF),
-    Colour::Fixed(0xD0),
-    Colour::Fixed(0xD1),
-    Colour::Fixed(0xD2),
-    Colour::Fixed(0xD3),
-    Colour::Fixed(0xD4),
-    Colour::Fixed(0xD5),
-    Colour::Fixed(0xD6),
-    Colour::Fixed(0xD7),
-    Colour::Fixed(0xD8),
-    Colour::Fixed(0xD9),
-    Colour::Fixed(0xDA),
-    Colour::Fixed(0xDB),
-    Colour::Fixed(0xDC),
-    Colour::Fixed(0xDD),
-    Colour::Fixed(0xDE),
-    Colour::Fixed(0xDF),
-    Colour::Fixed(0xE0),
-    Colour::Fixed(0xE1),
-    Colour::Fixed(0xE2),
-    Colour::Fixed(0xE3),
-    Colour::Fixed(0xE4),
-    Colour::Fixed(0xE5),
-    Colour::Fixed(0xE6),
-    Colour::Fixed(0xE7),
-    Colour::Fixed(0xE8),
-    Colour::Fixed(0xE9),
-    Colour::Fixed(0xEA),
-    Colour::Fixed(0xEB),
-    Colour::Fixed(0xEC),
-    Colour::Fixed(0xED),
-    Colour::Fixed(0xEE),
-    Colour::Fixed(0xEF),
-    Colour::Fixed(0xF0),
-    Colour::Fixed(0xF1),
-    Colour::Fixed(0xF2),
-    Colour::Fixed(0xF3),
-    Colour::Fixed(0xF4),
-    Colour::Fixed(0xF5),
-    Colour::Fixed(0xF6),
-    Colour::Fixed(0xF7),
-    Colour::Fixed(0xF8),
-    Colour::Fixed(0xF9),
-    Colour::Fixed(0xFA),
-    Colour::Fixed(0xFB),
-    Colour::Fixed(0xFC),
-    Colour::Fixed(0xFD),
-    Colour::Fixed(0xFE),
-    Colour::Fixed(0xFF),
+pub fn grayscale_image(image: &DynamicImage) -> DynamicImage {
+    let mut gray = image.to_rgba8();
+    for pixel in gray.pixels_mut() {
+        *pixel = to_grayscale(pixel);
+    }
+    DynamicImage::ImageRgba8(gray)
+}
+
+static DEFAULT_MAPPERS: LazyLock<ColorMappers> = LazyLock::new(|| ColorMappers::new(ColorDistance::default(), &TerminalPalette::XTERM, &Ansi256Palette::default()));
+
+pub static ANSI_8_COLORS: [Colour; 8] = [
+    Colour::Black,
+    Colour::Red,
+    Colour::Green,
+    Colour::Yellow,
+    Colour::Blue,
+    Colour::Purple,
+    Colour::Cyan,
+    Colour::White,
 ];
 
-static ANSI_COLOR_TO_TRUECOLOR: [(u8, u8, u8); 256] = [
-    (0x00, 0x00, 0x00),
-    (0x80, 0x00, 0x00),
-    (0x00, 0x80, 0x00),
-    (0x80, 0x80, 0x00),
-    (0x00, 0x00, 0x80),
-    (0x80, 0x00, 0x80),
-    (0x00, 0x80, 0x80),
-    (0xc0, 0xc0, 0xc0),
-    (0x80, 0x80, 0x80),
-    (0xff, 0x00, 0x00),
-    (0x00, 0xff, 0x00),
-    (0xff, 0xff, 0x00),
-    (0x00, 0x00, 0xff),
-    (0xff, 0x00, 0xff),
-    (0x00, 0xff, 0xff),
-    (0xff, 0xff, 0xff),
-    (0x00, 0x00, 0x00),
-    (0x00, 0x00, 0x5f),
-    (0x00, 0x00, 0x87),
-    (0x00, 0x00, 0xaf),
-    (0x00, 0x00, 0xd7),
-    (0x00, 0x00, 0xff),
-    (0x00, 0x5f, 0x00),
-    (0x00, 0x5f, 0x5f),
-    (0x00, 0x5f, 0x87),
-    (0x00, 0x5f, 0xaf),
-    (0x00, 0x5f, 0xd7),
-    (0x00, 0x5f, 0xff),
-    (0x00, 0x87, 0x00),
-    (0x00, 0x87, 0x5f),
-    (0x00, 0x87, 0x87),
-    (0x00, 0x87, 0xaf),
-    (0x00, 0x87, 0xd7),
-    (0x00, 0x87, 0xff),
-    (0x00, 0xaf, 0x00),
-    (0x00, 0xaf, 0x5f),
-    (0x00, 0xaf, 0x87),
-    (0x00, 0xaf, 0xaf),
-    (0x00, 0xaf, 0xd7),
-    (0x00, 0xaf, 0xff),
-    (0x00, 0xd7, 0x00),
-    (0x00, 0xd7, 0x5f),
-    (0x00, 0xd7, 0x87),
-    (0x00, 0xd7, 0xaf),
-    (0x00, 0xd7, 0xd7),
-    (0x00, 0xd7, 0xff),
-    (0x00, 0xff, 0x00),
-    (0x00, 0xff, 0x5f),
-    (0x00, 0xff, 0x87),
-    (0x00, 0xff, 0xaf),
-    (0x00, 0xff, 0xd7),
-    (0x00, 0xff, 0xff),
-    (0x5f, 0x00, 0x00),
-    (0x5f, 0x00, 0x5f),
-    (0x5f, 0x00, 0x87),
-    (0x5f, 0x00, 0xaf),
-    (0x5f, 0x00, 0xd7),
-    (0x5f, 0x00, 0xff),
-    (0x5f, 0x5f, 0x00),
-    (0x5f, 0x5f, 0x5f),
-    (0x5f, 0x5f, 0x87),
-    (0x5f, 0x5f, 0xaf),
-    (0x5f, 0x5f, 0xd7),
-    (0x5f, 0x5f, 0xff),
-    (0x5f, 0x87, 0x00),
-    (0x5f, 0x87, 0x5f),
-    (0x5f, 0x87, 0x87),
-    (0x5f, 0x87, 0xaf),
-    (0x5f, 0x87, 0xd7),
-    (0x5f, 0x87, 0xff),
-    (0x5f, 0xaf, 0x00),
-    (0x5f, 0xaf, 0x5f),
-    (0x5f, 0xaf, 0x87),
-    (0x5f, 0xaf, 0xaf),
-    (0x5f, 0xaf, 0xd7),
-    (0x5f, 0xaf, 0xff),
-    (0x5f, 0xd7, 0x00),
-    (0x5f, 0xd7, 0x5f),
-    (0x5f, 0xd7, 0x87),
-    (0x5f, 0xd7, 0xaf),
-    (0x5f, 0xd7, 0xd7),
-    (0x5f, 0xd7, 0xff),
-    (0x5f, 0xff, 0x00),
-    (0x5f, 0xff, 0x5f),
-    (0x5f, 0xff, 0x87),
-    (0x5f, 0xff, 0xaf),
-    (0x5f, 0xff, 0xd7),
-    (0x5f, 0xff, 0xff),
-    (0x87, 0x00, 0x00),
-    (0x87, 0x00, 0x5f),
-    (0x87, 0x00, 0x87),
-    (0x87, 0x00, 0xaf),
-    (0x87, 0x00, 0xd7),
-    (0x87, 0x00, 0xff),
-    (0x87, 0x5f, 0x00),
-    (0x87, 0x5f, 0x5f),
-    (0x87, 0x5f, 0x87),
-    (0x87, 0x5f, 0xaf),
-    (0x87, 0x5f, 0xd7),
-    (0x87, 0x5f, 0xff),
-    (0x87, 0x87, 0x00),
-    (0x87, 0x87, 0x5f),
-    (0x87, 0x87, 0x87),
-    (0x87, 0x87, 0xaf),
-    (0x87, 0x87, 0xd7),
-    (0x87, 0x87, 0xff),
-    (0x87, 0xaf, 0x00),
-    (0x87, 0xaf, 0x5f),
-    (0x87, 0xaf, 0x87),
-    (0x87, 0xaf, 0xaf),
-    (0x87, 0xaf, 0xd7),
-    (0x87, 0xaf, 0xff),
-    (0x87, 0xd7, 0x00),
-    (0x87, 0xd7, 0x5f),
-    (0x87, 0xd7, 0x87),
-    (0x87, 0xd7, 0xaf),
-    (0x87, 0xd7, 0xd7),
-    (0x87, 0xd7, 0xff),
-    (0x87, 0xff, 0x00),
-    (0x87, 0xff, 0x5f),
-    (0x87, 0xff, 0x87),
-    (0x87, 0xff, 0xaf),
-    (0x87, 0xff, 0xd7),
-    (0x87, 0xff, 0xff),
-    (0xaf, 0x00, 0x00),
-    (0xaf, 0x00, 0x5f),
-    (0xaf, 0x00, 0x87),
-    (0xaf, 0x00, 0xaf),
-    (0xaf, 0x00, 0xd7),
-    (0xaf, 0x00, 0xff),
-    (0xaf, 0x5f, 0x00),
-    (0xaf, 0x5f, 0x5f),
-    (0xaf, 0x5f, 0x87),
-    (0xaf, 0x5f, 0xaf),
-    (0xaf, 0x5f, 0xd7),
-    (0xaf, 0x5f, 0xff),
-    (0xaf, 0x87, 0x00),
-    (0xaf, 0x87, 0x5f),
-    (0xaf, 0x87, 0x87),
-    (0xaf, 0x87, 0xaf),
-    (0xaf, 0x87, 0xd7),
-    (0xaf, 0x87, 0xff),
-    (0xaf, 0xaf, 0x00),
-    (0xaf, 0xaf, 0x5f),
-    (0xaf, 0xaf, 0x87),
-    (0xaf, 0xaf, 0xaf),
-    (0xaf, 0xaf, 0xd7),
-    (0xaf, 0xaf, 0xff),
-    (0xaf, 0xd7, 0x00),
-    (0xaf, 0xd7, 0x5f),
-    (0xaf, 0xd7, 0x87),
-    (0xaf, 0xd7, 0xaf),
-    (0xaf, 0xd7, 0xd7),
-    (0xaf, 0xd7, 0xff),
-    (0xaf, 0xff, 0x00),
-    (0xaf, 0xff, 0x5f),
-    (0xaf, 0xff, 0x87),
-    (0xaf, 0xff, 0xaf),
-    (0xaf, 0xff, 0xd7),
-    (0xaf, 0xff, 0xff),
-    (0xd7, 0x00, 0x00),
-    (0xd7, 0x00, 0x5f),
-    (0xd7, 0x00, 0x87),
-    (0xd7, 0x00, 0xaf),
-    (0xd7, 0x00, 0xd7),
-    (0xd7, 0x00, 0xff),
-    (0xd7, 0x5f, 0x00),
-    (0xd7, 0x5f, 0x5f),
-    (0xd7, 0x5f, 0x87),
-    (0xd7, 0x5f, 0xaf),
-    (0xd7, 0x5f, 0xd7),
-    (0xd7, 0x5f, 0xff),
-    (0xd7, 0x87, 0x00),
-    (0xd7, 0x87, 0x5f),
-    (0xd7, 0x87, 0x87),
-    (0xd7, 0x87, 0xaf),
-    (0xd7, 0x87, 0xd7),
-    (0xd7, 0x87, 0xff),
-    (0xd7, 0xaf, 0x00),
-    (0xd7, 0xaf, 0x5f),
-    (0xd7, 0xaf, 0x87),
-    (0xd7, 0xaf, 0xaf),
-    (0xd7, 0xaf, 0xd7),
-    (0xd7, 0xaf, 0xff),
-    (0xd7, 0xd7, 0x00),
-    (0xd7, 0xd7, 0x5f),
-    (0xd7, 0xd7, 0x87),
-    (0xd7, 0xd7, 0xaf),
-    (0xd7, 0xd7, 0xd7),
-    (0xd7, 0xd7, 0xff),
-    (0xd7, 0xff, 0x00),
-    (0xd7, 0xff, 0x5f),
-    (0xd7, 0xff, 0x87),
-    (0xd7, 0xff, 0xaf),
-    (0xd7, 0xff, 0xd7),
-    (0xd7, 0xff, 0xff),
-    (0xff, 0x00, 0x00),
-    (0xff, 0x00, 0x5f),
-    (0xff, 0x00, 0x87),
-    (0xff, 0x00, 0xaf),
-    (0xff, 0x00, 0xd7),
-    (0xff, 0x00, 0xff),
-    (0xff, 0x5f, 0x00),
-    (0xff, 0x5f, 0x5f),
-    (0xff, 0x5f, 0x87),
-    (0xff, 0x5f, 0xaf),
-    (0xff, 0x5f, 0xd7),
-    (0xff, 0x5f, 0xff),
-    (0xff, 0x87, 0x00),
-    (0xff, 0x87, 0x5f),
-    (0xff, 0x87, 0x87),
-    (0xff, 0x87, 0xaf),
-    (0xff, 0x87, 0xd7),
-    (0xff, 0x87, 0xff),
-    (0xff, 0xaf, 0x00),
-    (0xff, 0xaf, 0x5f),
-    (0xff, 0xaf, 0x87),
-    (0xff, 0xaf, 0xaf),
-    (0xff, 0xaf, 0xd7),
-    (0xff, 0xaf, 0xff),
-    (0xff, 0xd7, 0x00),
-    (0xff, 0xd7, 0x5f),
-    (0xff, 0xd7, 0x87),
-    (0xff, 0xd7, 0xaf),
-    (0xff, 0xd7, 0xd7),
-    (0xff, 0xd7, 0xff),
-    (0xff, 0xff, 0x00),
-    (0xff, 0xff, 0x5f),
-    (0xff, 0xff, 0x87),
-    (0xff, 0xff, 0xaf),
-    (0xff, 0xff, 0xd7),
-    (0xff, 0xff, 0xff),
-    (0x08, 0x08, 0x08),
-    (0x12, 0x12, 0x12),
-    (0x1c, 0x1c, 0x1c),
-    (0x26, 0x26, 0x26),
-    (0x30, 0x30, 0x30),
-    (0x3a, 0x3a, 0x3a),
-    (0x44, 0x44, 0x44),
-    (0x4e, 0x4e, 0x4e),
-    (0x58, 0x58, 0x58),
-    (0x62, 0x62, 0x62),
-    (0x6c, 0x6c, 0x6c),
-    (0x76, 0x76, 0x76),
-    (0x80, 0x80, 0x80),
-    (0x8a, 0x8a, 0x8a),
-    (0x94, 0x94, 0x94),
-    (0x9e, 0x9e, 0x9e),
-    (0xa8, 0xa8, 0xa8),
-    (0xb2, 0xb2, 0xb2),
-    (0xbc, 0xbc, 0xbc),
-    (0xc6, 0xc6, 0xc6),
-    (0xd0, 0xd0, 0xd0),
-    (0xda, 0xda, 0xda),
-    (0xe4, 0xe4, 0xe4),
-    (0xee, 0xee, 0xee),
+/// The basic colours followed by their bright variants, which are written as `Fixed(8)` to `Fixed(15)`
+pub static ANSI_16_COLORS: [Colour; 16] = [
+    Colour::Black,
+    Colour::Red,
+    Colour::Green,
+    Colour::Yellow,
+    Colour::Blue,
+    Colour::Purple,
+    Colour::Cyan,
+    Colour::White,
+    Colour::Fixed(8),
+    Colour::Fixed(9),
+    Colour::Fixed(10),
+    Colour::Fixed(11),
+    Colour::Fixed(12),
+    Colour::Fixed(13),
+    Colour::Fixed(14),
+    Colour::Fixed(15),
 ];
 
-fn pick_closest_from<C : Sized>(color: colored::Color, choices: &[C], candidate_to_truecolor: fn(&C) -> (u8, u8, u8)) -> Option<&C> {
-    let input_as_truecolor = into_truecolor(&color);
-    choices.iter()
-        .min_by_key(|candidate| {
-            let candidate_as_truecolor = candidate_to_truecolor(*candidate);
-            euclidian_distance((input_as_truecolor.0, input_as_truecolor.1, input_as_truecolor.2), candidate_as_truecolor)
-        })
+/// The grey ramp of the 256 colour palette, between the black and the white of the colour cube
+pub static GRAYSCALE_COLORS: [Colour; 26] = {
+    let mut colours = [Colour::Fixed(16); 26];
+    let mut index = 1;
+    while index < 25 {
+        colours[index] = Colour::Fixed(231 + index as u8);
+        index += 1;
+    }
+    colours[25] = Colour::Fixed(231);
+    colours
+};
+
+/// The colours of a terminal cell: the glyph is drawn in the foreground colour on top of the
+/// background colour. Both are mapped separately, as some terminals can show fewer colours in the
+/// background.
+#[derive(Clone, Copy)]
+pub struct CellColorMapper<'a> {
+    pub foreground: &'a ColorMapper<'a>,
+    pub background: &'a ColorMapper<'a>,
+}
+
+impl<'a> CellColorMapper<'a> {
+    /// Maps foreground and background the same way
+    pub fn uniform(mapper: &'a ColorMapper<'a>) -> Self {
+        CellColorMapper { foreground: mapper, background: mapper }
+    }
+
+    pub fn foreground(&self, pixel: &Rgba<u8>) -> Colour {
+        (self.foreground)(pixel)
+    }
+
+    pub fn background(&self, pixel: &Rgba<u8>) -> Colour {
+        (self.background)(pixel)
+    }
+}
+
+/// The mappers for all palette based colour modes, with the palettes prepared for one [ColorDistance].
+pub struct ColorMappers {
+    ansi: PaletteMatcher,
+    ansi8: PaletteMatcher,
+    ansi256: PaletteMatcher,
+    grayscale: PaletteMatcher,
+    mono: PaletteMatcher,
+}
+
+impl ColorMappers {
+    /// `palette` is what the 16 colour mode is matched against, `table` the 256 colour mode
+    pub fn new(distance: ColorDistance, palette: &TerminalPalette, table: &Ansi256Palette) -> Self {
+        ColorMappers {
+            ansi: PaletteMatcher::with_rgb(&ANSI_16_COLORS, &palette.colors, distance),
+            ansi8: PaletteMatcher::with_rgb(&ANSI_8_COLORS, &palette.colors[..8], distance),
+            ansi256: PaletteMatcher::with_rgb(&ANSI_COLORS, &table.colors, distance),
+            grayscale: PaletteMatcher::with_rgb(
+                &GRAYSCALE_COLORS,
+                &GRAYSCALE_COLORS.map(|colour| match colour {
+                    Colour::Fixed(index) => table.colors[index as usize],
+                    _ => unreachable!("the grey ramp only has palette colours"),
+                }),
+                distance,
+            ),
+            mono: PaletteMatcher::with_rgb(&[Colour::Black, Colour::White], &[(0, 0, 0), (255, 255, 255)], distance),
+        }
+    }
+
+    pub fn truecolor(&self, pixel: &Rgba<u8>) -> Colour {
+        color_mapping_truecolor(pixel)
+    }
+
+    /// The closest of the 16 ANSI colours, bright ones included
+    pub fn ansi(&self, pixel: &Rgba<u8>) -> Colour {
+        self.ansi.closest(pixel)
+    }
+
+    /// The closest of the 8 basic ANSI colours, for terminals that can't show bright ones everywhere
+    pub fn ansi8(&self, pixel: &Rgba<u8>) -> Colour {
+        self.ansi8.closest(pixel)
+    }
+
+    pub fn ansi256(&self, pixel: &Rgba<u8>) -> Colour {
+        self.ansi256.closest(pixel)
+    }
+
+    /// The closest grey of the 256 colour palette to the luma of the pixel
+    pub fn grayscale(&self, pixel: &Rgba<u8>) -> Colour {
+        self.grayscale.closest(&to_grayscale(pixel))
+    }
+
+    pub fn ansi_matcher(&self) -> &PaletteMatcher {
+        &self.ansi
+    }
+
+    pub fn ansi8_matcher(&self) -> &PaletteMatcher {
+        &self.ansi8
+    }
+
+    pub fn ansi256_matcher(&self) -> &PaletteMatcher {
+        &self.ansi256
+    }
+
+    pub fn grayscale_matcher(&self) -> &PaletteMatcher {
+        &self.grayscale
+    }
+
+    /// Black and white, which decides the lit pixels of [crate::ColorMode::Mono]
+    pub fn mono_matcher(&self) -> &PaletteMatcher {
+        &self.mono
+    }
+}
+
+/// Finds the closest of a fixed set of colours, according to a [ColorDistance].
+pub struct PaletteMatcher {
+    colours: Vec<Colour>,
+    rgb: Vec<(u8, u8, u8)>,
+    coordinates: Vec<[f32; 3]>,
+    distance: ColorDistance,
+    /// Created on the first lookup, so that the matchers of colour modes a render doesn't use
+    /// cost next to nothing
+    candidates: OnceLock<CandidateTable>,
+}
+
+impl PaletteMatcher {
+    pub fn new(colours: &[Colour], distance: ColorDistance) -> Self {
+        let rgb: Vec<(u8, u8, u8)> = colours.iter().map(colour_to_truecolor).collect();
+        Self::with_rgb(colours, &rgb, distance)
+    }
+
+    /// A palette whose entries look like `rgb` on the terminal, rather than like the defaults
+    /// gaudi assumes for them
+    pub fn with_rgb(colours: &[Colour], rgb: &[(u8, u8, u8)], distance: ColorDistance) -> Self {
+        assert!(!colours.is_empty(), "a palette needs at least one colour");
+        assert_eq!(colours.len(), rgb.len(), "every colour of a palette needs an RGB value");
+        PaletteMatcher {
+            colours: colours.to_vec(),
+            rgb: rgb.to_vec(),
+            coordinates: rgb.iter().map(|c| distance.to_coordinates(*c)).collect(),
+            distance,
+            candidates: OnceLock::new(),
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.colours.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.colours.is_empty()
+    }
+
+    /// The RGB value of the entry at the given index, as it is expected to appear on the terminal
+    pub fn rgb(&self, index: usize) -> (u8, u8, u8) {
+        self.rgb[index]
+    }
+
+    /// For every entry, the distance to its nearest distinct neighbour along the channel in which
+    /// they differ most: roughly the step between neighbouring entries in that part of the
+    /// palette, whatever its shape. 0 if the palette has no other colour.
+    pub fn spacings(&self) -> Vec<f32> {
+        let channel_distance = |a: (u8, u8, u8), b: (u8, u8, u8)| {
+            a.0.abs_diff(b.0).max(a.1.abs_diff(b.1)).max(a.2.abs_diff(b.2))
+        };
+        self.rgb.iter()
+            .map(|&entry| {
+                self.rgb.iter()
+                    .map(|&neighbour| channel_distance(entry, neighbour))
+                    .filter(|distance| *distance > 0)
+                    .min()
+                    .unwrap_or(0) as f32
+            })
+            .collect()
+    }
+
+    pub fn closest(&self, pixel: &Rgba<u8>) -> Colour {
+        self.colours[self.closest_index((pixel[0], pixel[1], pixel[2]))]
+    }
+
+    pub fn closest_index(&self, rgb: (u8, u8, u8)) -> usize {
+        let candidates = self.candidates.get_or_init(CandidateTable::new);
+        match candidates.get(rgb, |cell| self.candidates_for_cell(cell)) {
+            Some([only]) => *only as usize,
+            Some(candidates) => self.closest_among(rgb, candidates.iter().map(|&index| index as usize)),
+            None => self.closest_index_exhaustive(rgb),
+        }
+    }
+
+    /// Like [PaletteMatcher::closest_index], but compares against every entry of the palette
+    /// instead of narrowing down the candidates first
+    pub fn closest_index_exhaustive(&self, rgb: (u8, u8, u8)) -> usize {
+        self.closest_among(rgb, 0..self.len())
+    }
+
+    /// The first of the closest entries, so that candidate lists pick the same entry as a full scan
+    fn closest_among(&self, rgb: (u8, u8, u8), indices: impl Iterator<Item = usize>) -> usize {
+        let target = self.distance.to_coordinates(rgb);
+        let mut best_index = 0;
+        let mut best_distance = f32::INFINITY;
+        for index in indices {
+            let distance = self.distance.distance(&target, &self.coordinates[index]);
+            if distance < best_distance {
+                best_index = index;
+                best_distance = distance;
+            }
+        }
+
+        best_index
+    }
+
+    /// The entries that can be the closest to any colour in the cell, in palette order: all of
+    /// those that may come closer than the farthest point of the entry that is nearest at worst.
+    /// None if the distance metric can't be bounded within a cell.
+    fn candidates_for_cell(&self, (low, high): ((u8, u8, u8), (u8, u8, u8))) -> Option<Box<[u16]>> {
+        let (box_low, box_high) = self.distance.coordinate_bounds(low, high);
+        let bounds: Vec<(f32, f32)> = self.coordinates.iter()
+            .map(|entry| self.distance.distance_bounds(&box_low, &box_high, entry))
+            .collect::<Option<_>>()?;
+        let nearest_at_worst = bounds.iter().map(|(_, far)| *far).fold(f32::INFINITY, f32::min);
+        // slack for the rounding of the distances, which are computed in f32
+        let threshold = nearest_at_worst * 1.001 + 1e-6;
+
+        Some(bounds.iter()
+            .enumerate()
+            .filter(|(_, (near, _))| *near <= threshold)
+            .map(|(index, _)| index as u16)
+            .collect())
+    }
 }
 
-fn euclidian_distance(ca: (u8, u8, u8), cb: (u8, u8, u8)) -> u32 {
-    use std::cmp;
-    let (r, g, b) = ca;
-    let (r1, g1, b1) = cb;
+/// A lookup table that narrows down the palette entries a colour has to be compared against. The
+/// RGB cube is split into 32x32x32 cells of 8x8x8 colours, each with the list of entries that may
+/// be the closest to one of its colours. Cells are only filled once a colour in them is looked
+/// up, so small images don't pay for the whole table.
+struct CandidateTable {
+    cells: Vec<OnceLock<Option<Box<[u16]>>>>,
+}
 
-    let rd = cmp::max(r, r1) - cmp::min(r, r1);
-    let gd = cmp::max(g, g1) - cmp::min(g, g1);
-    let bd = cmp::max(b, b1) - cmp::min(b, b1);
-    let rd: u32 = rd.into();
-    let gd: u32 = gd.into();
-    let bd: u32 = bd.into();
-    let distance = rd.pow(2) + gd.pow(2) + bd.pow(2);
+impl CandidateTable {
+    const CELL_BITS: u32 = 3;
+    const CELLS_PER_CHANNEL: usize = 256 >> Self::CELL_BITS;
+
+    fn new() -> Self {
+        CandidateTable {
+            cells: (0..Self::CELLS_PER_CHANNEL.pow(3)).map(|_| OnceLock::new()).collect(),
+        }
+    }
 
-    distance
+    /// The candidates for the cell `rgb` is in, computed from the lowest and highest colour of
+    /// the cell by `fill` if it is looked up for the first time
+    fn get(
+        &self,
+        rgb: (u8, u8, u8),
+        fill: impl FnOnce(((u8, u8, u8), (u8, u8, u8))) -> Option<Box<[u16]>>,
+    ) -> Option<&[u16]> {
+        let cell = |channel: u8| (channel >> Self::CELL_BITS) as usize;
+        let index = (cell(rgb.0) * Self::CELLS_PER_CHANNEL + cell(rgb.1)) * Self::CELLS_PER_CHANNEL + cell(rgb.2);
+        let low = |channel: u8| channel & !((1 << Self::CELL_BITS) - 1);
+        let high = |channel: u8| low(channel) | ((1 << Self::CELL_BITS) - 1);
+
+        self.cells[index]
+            .get_or_init(|| fill(((low(rgb.0), low(rgb.1), low(rgb.2)), (high(rgb.0), high(rgb.1), high(rgb.2)))))
+            .as_deref()
+    }
+}
+
+pub static ANSI_COLORS: [Colour; 256] = {
+    let mut colours = [Colour::Black; 256];
+    let mut index = 0;
+    while index < colours.len() {
+        colours[index] = Colour::Fixed(index as u8);
+        index += 1;
+    }
+    colours
+};
+
+/// The default RGB values of the 256 colour palette, generated from ansi256_table.json by build.rs
+pub static ANSI_COLOR_TO_TRUECOLOR: [(u8, u8, u8); 256] = include!(concat!(env!("OUT_DIR"), "/ansi256_table.rs"));
+
+fn colour_to_truecolor(colour: &Colour) -> (u8, u8, u8) {
+    match *colour {
+        Colour::Black => TerminalPalette::XTERM.colors[0],
+        Colour::Red => TerminalPalette::XTERM.colors[1],
+        Colour::Green => TerminalPalette::XTERM.colors[2],
+        Colour::Yellow => TerminalPalette::XTERM.colors[3],
+        Colour::Blue => TerminalPalette::XTERM.colors[4],
+        Colour::Purple => TerminalPalette::XTERM.colors[5],
+        Colour::Cyan => TerminalPalette::XTERM.colors[6],
+        Colour::White => TerminalPalette::XTERM.colors[7],
+        Colour::Fixed(index) => ANSI_COLOR_TO_TRUECOLOR[index as usize],
+        Colour::RGB(r, g, b) => (r, g, b),
+    }
 }
 
-fn into_truecolor(color: &colored::Color) -> (u8, u8, u8) {
-    use colored::Color::*;
-    match color {
-        Black => (0, 0, 0),
-        Red => (205, 0, 0),
-        Green => (0, 205, 0),
-        Yellow => (205, 205, 0),
-        Blue => (0, 0, 238),
-        Magenta => (205, 0, 205),
-        Cyan => (0, 205, 205),
-        White => (229, 229, 229),
-        BrightBlack => (127, 127, 127),
-        BrightRed => (255, 0, 0),
-        BrightGreen => (0, 255, 0),
-        BrightYellow => (255, 255, 0),
-        BrightBlue => (92, 92, 255),
-        BrightMagenta => (255, 0, 255),
-        BrightCyan => (0, 255, 255),
-        BrightWhite => (255, 255, 255),
-        TrueColor { r, g, b } => (r.clone(), g.clone(), b.clone()),
-    }
-}
\ No newline at end of file
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A theme of the kind --palette loads, with colours out of order and a duplicate, unlike
+    /// the regular builtin palettes
+    const THEME: TerminalPalette = TerminalPalette {
+        colors: [
+            (0x1d, 0x1f, 0x21), (0xcc, 0x66, 0x66), (0xb5, 0xbd, 0x68), (0xf0, 0xc6, 0x74),
+            (0x81, 0xa2, 0xbe), (0xb2, 0x94, 0xbb), (0x8a, 0xbe, 0xb7), (0xc5, 0xc8, 0xc6),
+            (0x96, 0x98, 0x96), (0xcc, 0x66, 0x66), (0xb5, 0xbd, 0x68), (0xf0, 0xc6, 0x74),
+            (0x81, 0xa2, 0xbe), (0xb2, 0x94, 0xbb), (0x8a, 0xbe, 0xb7), (0xff, 0xff, 0xff),
+        ],
+    };
+
+    /// Every `step`th value per channel, from 0
+    fn sampled_cube(step: usize) -> impl Iterator<Item = (u8, u8, u8)> {
+        let values = move || (0..=255).step_by(step);
+        values().flat_map(move |r| values().flat_map(move |g| values().map(move |b| (r, g, b))))
+    }
+
+    fn assert_tables_match_the_full_scan(distance: ColorDistance, step: usize) {
+        let ansi256 = ColorMappers::new(distance, &TerminalPalette::default(), &Ansi256Palette::default());
+        let xterm = ColorMappers::new(distance, &TerminalPalette::XTERM, &Ansi256Palette::default());
+        let theme = ColorMappers::new(distance, &THEME, &Ansi256Palette::default());
+        let matchers = [("256", ansi256.ansi256_matcher()), ("xterm", xterm.ansi_matcher()), ("theme", theme.ansi_matcher())];
+        for (name, matcher) in matchers {
+            for rgb in sampled_cube(step) {
+                assert_eq!(
+                    matcher.closest_index(rgb),
+                    matcher.closest_index_exhaustive(rgb),
+                    "{} with {:?}: {:?}", name, distance, rgb,
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn rgb_tables_pick_what_a_full_scan_picks() {
+        assert_tables_match_the_full_scan(ColorDistance::Rgb, 3);
+    }
+
+    #[test]
+    fn redmean_tables_pick_what_a_full_scan_picks() {
+        assert_tables_match_the_full_scan(ColorDistance::Redmean, 3);
+    }
+
+    #[test]
+    fn cielab76_tables_pick_what_a_full_scan_picks() {
+        assert_tables_match_the_full_scan(ColorDistance::Cielab76, 3);
+    }
+
+    #[test]
+    fn oklab_tables_pick_what_a_full_scan_picks() {
+        assert_tables_match_the_full_scan(ColorDistance::Oklab, 3);
+    }
+
+    /// ΔE*00 can't be bounded within a cell, so every lookup is a full scan, and a coarser sample
+    /// keeps the test quick
+    #[test]
+    fn ciede2000_tables_pick_what_a_full_scan_picks() {
+        let matcher = PaletteMatcher::new(&ANSI_COLORS, ColorDistance::Ciede2000);
+        assert!(matcher.candidates_for_cell(((0, 0, 0), (7, 7, 7))).is_none());
+        assert_tables_match_the_full_scan(ColorDistance::Ciede2000, 15);
+    }
+}
@@ -0,0 +1,87 @@
+use std::fmt;
+use ansi_term::ANSIGenericString;
+use crate::bash_syntax::{write_with_minimal_control_sequences, ColorEncoding};
+
+/// A delta is only used while it takes at most this share of the bytes of a full redraw. Beyond
+/// that it saves little, and a full redraw also repairs whatever else ended up on the image.
+pub const FULL_REDRAW_THRESHOLD: f32 = 0.75;
+
+/// Unchanged cells between changed ones are drawn again rather than skipped when there are at most
+/// this many: moving the cursor and setting up the colours again costs about as much
+const MAX_REDRAWN_GAP: usize = 3;
+
+/// The spans of a rendered image, split into rows of one span per cell
+pub fn cells(spans: &[ANSIGenericString<'static, str>]) -> Vec<Vec<ANSIGenericString<'static, str>>> {
+    let mut rows = Vec::new();
+    let mut row = Vec::new();
+    for span in spans {
+        if &**span == "\n" {
+            rows.push(std::mem::take(&mut row));
+            continue;
+        }
+        // padding comes as a single span of blanks
+        for char in span.chars() {
+            row.push(span.style_ref().paint(char.to_string()));
+        }
+    }
+    if !row.is_empty() {
+        rows.push(row);
+    }
+    rows
+}
+
+/// Writes what turns `before` on the screen into `after`, both [cells] of the same image size: the
+/// changed runs of cells only, with the cursor moved from one to the next. Rows are moved relative
+/// to each other, as where the image is on the screen isn't known. The cursor starts and ends in
+/// the first column below the image, where drawing the full image leaves it.
+pub fn write_delta(
+    before: &[Vec<ANSIGenericString<'static, str>>],
+    after: &[Vec<ANSIGenericString<'static, str>>],
+    encoding: ColorEncoding,
+    f: &mut fmt::Formatter,
+) -> fmt::Result {
+    let mut cursor = (after.len(), 0);
+    for (row, (old, new)) in before.iter().zip(after).enumerate() {
+        for (start, end) in changed_runs(old, new) {
+            write_cursor_movement(cursor, (row, start), f)?;
+            write_with_minimal_control_sequences(&new[start..end], encoding, f)?;
+            cursor = (row, end);
+        }
+    }
+    write_cursor_movement(cursor, (after.len(), 0), f)
+}
+
+/// The ranges of columns that differ between the rows
+fn changed_runs(old: &[ANSIGenericString<'static, str>], new: &[ANSIGenericString<'static, str>]) -> Vec<(usize, usize)> {
+    let mut runs: Vec<(usize, usize)> = Vec::new();
+    for (column, cell) in new.iter().enumerate() {
+        if old.get(column) == Some(cell) {
+            continue;
+        }
+        match runs.last_mut() {
+            Some((_, end)) if column - *end <= MAX_REDRAWN_GAP => *end = column + 1,
+            _ => runs.push((column, column + 1)),
+        }
+    }
+    runs
+}
+
+/// Writes CUU or CUD for the rows and CUF for the columns. Going left takes a carriage return
+/// first, which also gets the cursor out of the last column of the terminal, where it stays after
+/// drawing into it.
+fn write_cursor_movement(from: (usize, usize), to: (usize, usize), f: &mut fmt::Formatter) -> fmt::Result {
+    let ((from_row, mut from_column), (to_row, to_column)) = (from, to);
+    if to_row < from_row {
+        write!(f, "\x1b[{}A", from_row - to_row)?;
+    } else if to_row > from_row {
+        write!(f, "\x1b[{}B", to_row - from_row)?;
+    }
+    if to_column < from_column {
+        f.write_str("\r")?;
+        from_column = 0;
+    }
+    if to_column > from_column {
+        write!(f, "\x1b[{}C", to_column - from_column)?;
+    }
+    Ok(())
+}
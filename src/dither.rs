@@ -0,0 +1,410 @@
+use std::borrow::Cow;
+use std::sync::LazyLock;
+use image::{DynamicImage, Rgba, RgbaImage};
+use crate::colormath::PaletteMatcher;
+use crate::render::is_transparent;
+
+/// How the quantisation error of the palette based colour modes is distributed.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Default)]
+pub enum Dither {
+    /// Every pixel is mapped to its closest palette entry on its own
+    #[default]
+    None,
+    FloydSteinberg,
+    Atkinson,
+    Jarvis,
+    Sierra,
+    /// The following are ordered dithers: the offset applied to a pixel depends on nothing but its
+    /// position, so identical regions of an image always come out identical.
+    Bayer2,
+    Bayer4,
+    Bayer8,
+    BlueNoise,
+}
+
+/// (dx, dy, weight) triples; the weights are relative to a divisor that comes with the kernel
+type ErrorDiffusionKernel = [(i32, i32, f32)];
+
+impl Dither {
+    /// Snaps every opaque pixel of the image to an entry of the palette, so that the colour mappers
+    /// will pick exactly that entry later on. Transparent pixels are left untouched and neither
+    /// receive nor spread any error, as they are not drawn.
+    ///
+    /// The error is diffused across the pixel grid, not across terminal cells: the lower pixel of a
+    /// half-block cell is simply the next image row, so it picks up the error of the upper one.
+    pub fn apply<'a>(self, image: &'a DynamicImage, matcher: &PaletteMatcher) -> Cow<'a, DynamicImage> {
+        if let Some((kernel, divisor)) = self.error_diffusion_kernel() {
+            return Cow::Owned(DynamicImage::ImageRgba8(diffuse_error(image, matcher, kernel, divisor)));
+        }
+        if let Some(threshold_map) = self.threshold_map() {
+            return Cow::Owned(DynamicImage::ImageRgba8(apply_threshold_map(image, matcher, threshold_map)));
+        }
+
+        Cow::Borrowed(image)
+    }
+
+    fn error_diffusion_kernel(self) -> Option<(&'static ErrorDiffusionKernel, f32)> {
+        match self {
+            Dither::FloydSteinberg => Some((&[
+                (1, 0, 7.0),
+                (-1, 1, 3.0), (0, 1, 5.0), (1, 1, 1.0),
+            ], 16.0)),
+            // only diffuses 3/4 of the error, which keeps contrast high
+            Dither::Atkinson => Some((&[
+                (1, 0, 1.0), (2, 0, 1.0),
+                (-1, 1, 1.0), (0, 1, 1.0), (1, 1, 1.0),
+                (0, 2, 1.0),
+            ], 8.0)),
+            Dither::Jarvis => Some((&[
+                (1, 0, 7.0), (2, 0, 5.0),
+                (-2, 1, 3.0), (-1, 1, 5.0), (0, 1, 7.0), (1, 1, 5.0), (2, 1, 3.0),
+                (-2, 2, 1.0), (-1, 2, 3.0), (0, 2, 5.0), (1, 2, 3.0), (2, 2, 1.0),
+            ], 48.0)),
+            Dither::Sierra => Some((&[
+                (1, 0, 5.0), (2, 0, 3.0),
+                (-2, 1, 2.0), (-1, 1, 4.0), (0, 1, 5.0), (1, 1, 4.0), (2, 1, 2.0),
+                (-1, 2, 2.0), (0, 2, 3.0), (1, 2, 2.0),
+            ], 32.0)),
+            _ => None,
+        }
+    }
+
+    fn threshold_map(self) -> Option<&'static ThresholdMap> {
+        match self {
+            Dither::Bayer2 => Some(&BAYER_2),
+            Dither::Bayer4 => Some(&BAYER_4),
+            Dither::Bayer8 => Some(&BAYER_8),
+            Dither::BlueNoise => Some(&BLUE_NOISE),
+            _ => None,
+        }
+    }
+}
+
+fn diffuse_error(image: &DynamicImage, matcher: &PaletteMatcher, kernel: &ErrorDiffusionKernel, divisor: f32) -> RgbaImage {
+    let source = image.to_rgba8();
+    let width = source.width() as i32;
+    let height = source.height() as i32;
+    let mut working: Vec<[f32; 3]> = source.pixels()
+        .map(|p| [p[0] as f32, p[1] as f32, p[2] as f32])
+        .collect();
+    let mut output = RgbaImage::new(source.width(), source.height());
+
+    for y in 0..height {
+        for x in 0..width {
+            let pixel = source.get_pixel(x as u32, y as u32);
+            if is_transparent(pixel) {
+                output.put_pixel(x as u32, y as u32, *pixel);
+                continue;
+            }
+
+            let old = working[(y * width + x) as usize];
+            let old_rgb = (clamp_channel(old[0]), clamp_channel(old[1]), clamp_channel(old[2]));
+            let new_rgb = matcher.rgb(matcher.closest_index(old_rgb));
+            output.put_pixel(x as u32, y as u32, Rgba([new_rgb.0, new_rgb.1, new_rgb.2, pixel[3]]));
+
+            let error = [
+                old[0] - new_rgb.0 as f32,
+                old[1] - new_rgb.1 as f32,
+                old[2] - new_rgb.2 as f32,
+            ];
+            for &(dx, dy, weight) in kernel {
+                let (nx, ny) = (x + dx, y + dy);
+                if nx < 0 || nx >= width || ny >= height || is_transparent(source.get_pixel(nx as u32, ny as u32)) {
+                    continue;
+                }
+                let target = &mut working[(ny * width + nx) as usize];
+                for channel in 0..3 {
+                    target[channel] += error[channel] * weight / divisor;
+                }
+            }
+        }
+    }
+
+    output
+}
+
+fn clamp_channel(value: f32) -> u8 {
+    value.round().clamp(0.0, 255.0) as u8
+}
+
+/// A square matrix of thresholds in the range (0, 1), tiled across the image
+struct ThresholdMap {
+    size: usize,
+    thresholds: Vec<f32>,
+}
+
+impl ThresholdMap {
+    fn from_ranks(size: usize, ranks: &[usize]) -> Self {
+        let cells = (size * size) as f32;
+        ThresholdMap {
+            size,
+            thresholds: ranks.iter().map(|&rank| (rank as f32 + 0.5) / cells).collect(),
+        }
+    }
+
+    fn threshold_at(&self, x: u32, y: u32) -> f32 {
+        self.thresholds[(y as usize % self.size) * self.size + (x as usize % self.size)]
+    }
+}
+
+/// The blue noise pattern gaudi uses, in the range (0, 1), tiled across the plane
+pub fn blue_noise_threshold(x: u32, y: u32) -> f32 {
+    BLUE_NOISE.threshold_at(x, y)
+}
+
+static BAYER_2: LazyLock<ThresholdMap> = LazyLock::new(|| bayer_matrix(1));
+static BAYER_4: LazyLock<ThresholdMap> = LazyLock::new(|| bayer_matrix(2));
+static BAYER_8: LazyLock<ThresholdMap> = LazyLock::new(|| bayer_matrix(3));
+static BLUE_NOISE: LazyLock<ThresholdMap> = LazyLock::new(|| void_and_cluster(32));
+
+fn bayer_matrix(order: u32) -> ThresholdMap {
+    let size = 1usize << order;
+    let ranks: Vec<usize> = (0..size * size)
+        .map(|i| {
+            let (x, y) = (i % size, i / size);
+            // interleave the bits of x ^ y and y, most significant pair last
+            let mut rank = 0;
+            for bit in 0..order {
+                let xy_bit = ((x ^ y) >> bit) & 1;
+                let y_bit = (y >> bit) & 1;
+                rank |= ((xy_bit << 1) | y_bit) << (2 * (order - 1 - bit));
+            }
+            rank
+        })
+        .collect();
+
+    ThresholdMap::from_ranks(size, &ranks)
+}
+
+/// Generates a blue noise threshold map with Ulichney's void-and-cluster method. Deterministic,
+/// so the output of gaudi does not change from one run to the next.
+fn void_and_cluster(size: usize) -> ThresholdMap {
+    const SIGMA: f32 = 1.5;
+    let cells = size * size;
+
+    // the gaussian energy one set pixel contributes to every other pixel, with toroidal wrap-around
+    let kernel: Vec<f32> = (0..cells)
+        .map(|i| {
+            let (dx, dy) = (i % size, i / size);
+            let dx = dx.min(size - dx) as f32;
+            let dy = dy.min(size - dy) as f32;
+            (-(dx * dx + dy * dy) / (2.0 * SIGMA * SIGMA)).exp()
+        })
+        .collect();
+
+    struct Pattern<'k> {
+        size: usize,
+        kernel: &'k [f32],
+        bits: Vec<bool>,
+        energy: Vec<f32>,
+    }
+    impl Pattern<'_> {
+        fn toggle(&mut self, index: usize) {
+            self.bits[index] = !self.bits[index];
+            let sign = if self.bits[index] { 1.0 } else { -1.0 };
+            let (x, y) = (index % self.size, index / self.size);
+            for (target, energy) in self.energy.iter_mut().enumerate() {
+                let dx = (target % self.size + self.size - x) % self.size;
+                let dy = (target / self.size + self.size - y) % self.size;
+                *energy += sign * self.kernel[dy * self.size + dx];
+            }
+        }
+        fn tightest_cluster(&self) -> usize {
+            self.extreme(true, |candidate, best| candidate > best)
+        }
+        fn largest_void(&self) -> usize {
+            self.extreme(false, |candidate, best| candidate < best)
+        }
+        fn extreme(&self, bit: bool, is_better: impl Fn(f32, f32) -> bool) -> usize {
+            let mut best: Option<usize> = None;
+            for index in 0..self.bits.len() {
+                if self.bits[index] == bit && best.is_none_or(|b| is_better(self.energy[index], self.energy[b])) {
+                    best = Some(index);
+                }
+            }
+            best.unwrap()
+        }
+    }
+
+    // initial pattern: about a tenth of the pixels set, picked by a fixed-seed LCG
+    let mut pattern = Pattern { size, kernel: &kernel, bits: vec![false; cells], energy: vec![0.0; cells] };
+    let mut seed: u32 = 0x9E37_79B9;
+    let initial_ones = cells / 10;
+    while pattern.bits.iter().filter(|b| **b).count() < initial_ones {
+        seed = seed.wrapping_mul(1_664_525).wrapping_add(1_013_904_223);
+        let index = (seed >> 8) as usize % cells;
+        if !pattern.bits[index] {
+            pattern.toggle(index);
+        }
+    }
+
+    // spread the initial points evenly
+    loop {
+        let cluster = pattern.tightest_cluster();
+        pattern.toggle(cluster);
+        let void = pattern.largest_void();
+        pattern.toggle(void);
+        if void == cluster {
+            break;
+        }
+    }
+
+    let mut ranks = vec![0usize; cells];
+
+    // ranks below the initial pattern: remove points from the tightest clusters first
+    let mut shrinking = Pattern { size, kernel: &kernel, bits: pattern.bits.clone(), energy: pattern.energy.clone() };
+    for rank in (0..initial_ones).rev() {
+        let cluster = shrinking.tightest_cluster();
+        shrinking.toggle(cluster);
+        ranks[cluster] = rank;
+    }
+
+    // ranks above: fill the largest voids first
+    for rank in initial_ones..cells {
+        let void = pattern.largest_void();
+        pattern.toggle(void);
+        ranks[void] = rank;
+    }
+
+    ThresholdMap::from_ranks(size, &ranks)
+}
+
+fn apply_threshold_map(image: &DynamicImage, matcher: &PaletteMatcher, threshold_map: &ThresholdMap) -> RgbaImage {
+    let spacings = matcher.spacings();
+
+    let mut output = image.to_rgba8();
+    for (x, y, pixel) in output.enumerate_pixels_mut() {
+        if is_transparent(pixel) {
+            continue;
+        }
+
+        // the offset stays below half the step to the next entry around the pixel's own colour,
+        // truncated so that rounding can't reach the midpoint: colours of the palette come out
+        // unchanged, and dense parts of a palette don't drown in noise meant for sparse ones
+        let spread = spacings[matcher.closest_index((pixel[0], pixel[1], pixel[2]))];
+        let offset = ((threshold_map.threshold_at(x, y) - 0.5) * spread).trunc();
+        let rgb = (
+            clamp_channel(pixel[0] as f32 + offset),
+            clamp_channel(pixel[1] as f32 + offset),
+            clamp_channel(pixel[2] as f32 + offset),
+        );
+        let new_rgb = matcher.rgb(matcher.closest_index(rgb));
+        *pixel = Rgba([new_rgb.0, new_rgb.1, new_rgb.2, pixel[3]]);
+    }
+
+    output
+}
+
+#[cfg(test)]
+mod tests {
+    use image::{DynamicImage, Rgba, RgbaImage};
+    use crate::color_distance::ColorDistance;
+    use crate::colormath::{ColorMappers, PaletteMatcher};
+    use super::*;
+
+    const ORDERED: [Dither; 4] = [Dither::Bayer2, Dither::Bayer4, Dither::Bayer8, Dither::BlueNoise];
+
+    fn mappers() -> ColorMappers {
+        ColorMappers::new(ColorDistance::default(), &Default::default(), &Default::default())
+    }
+
+    fn flat(colour: (u8, u8, u8)) -> DynamicImage {
+        DynamicImage::ImageRgba8(RgbaImage::from_pixel(64, 64, Rgba([colour.0, colour.1, colour.2, 255])))
+    }
+
+    fn dithered(dither: Dither, image: &DynamicImage, matcher: &PaletteMatcher) -> RgbaImage {
+        dither.apply(image, matcher).to_rgba8()
+    }
+
+    fn assert_is_permutation(map: &ThresholdMap) {
+        let cells = map.size * map.size;
+        let mut ranks: Vec<usize> = map.thresholds.iter()
+            .map(|threshold| (threshold * cells as f32 - 0.5).round() as usize)
+            .collect();
+        ranks.sort_unstable();
+        assert_eq!(ranks, (0..cells).collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn mid_gray_lights_about_half_of_the_pixels_in_mono() {
+        let mappers = mappers();
+        let image = flat((128, 128, 128));
+        for dither in [Dither::FloydSteinberg, Dither::Atkinson, Dither::Jarvis, Dither::Sierra].into_iter().chain(ORDERED) {
+            let output = dithered(dither, &image, mappers.mono_matcher());
+            let lit = output.pixels().filter(|p| p[0] == 255).count() as f32 / output.pixels().len() as f32;
+            assert!((0.4..=0.6).contains(&lit), "{dither:?} lit {lit} of the pixels");
+        }
+    }
+
+    #[test]
+    fn palette_colours_pass_through_unchanged() {
+        let mappers = mappers();
+        let matcher = mappers.ansi256_matcher();
+        let all = [Dither::None, Dither::FloydSteinberg, Dither::Atkinson, Dither::Jarvis, Dither::Sierra].into_iter().chain(ORDERED);
+        for dither in all {
+            for index in [0, 9, 46, 130, 196, 231, 244] {
+                let colour = matcher.rgb(index);
+                let output = dithered(dither, &flat(colour), matcher);
+                assert!(
+                    output.pixels().all(|p| (p[0], p[1], p[2]) == colour),
+                    "{dither:?} changed palette colour {index} {colour:?}"
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn transparent_pixels_are_left_alone() {
+        let mappers = mappers();
+        let image = DynamicImage::ImageRgba8(RgbaImage::from_pixel(8, 8, Rgba([128, 128, 128, 0])));
+        for dither in [Dither::FloydSteinberg, Dither::Bayer4] {
+            assert_eq!(dithered(dither, &image, mappers.mono_matcher()), image.to_rgba8());
+        }
+    }
+
+    #[test]
+    fn bayer_maps_are_permutations() {
+        for (map, size) in [(&*BAYER_2, 2), (&*BAYER_4, 4), (&*BAYER_8, 8)] {
+            assert_eq!(map.size, size);
+            assert_is_permutation(map);
+        }
+    }
+
+    #[test]
+    fn bayer_2_is_the_classic_matrix() {
+        let ranks: Vec<f32> = BAYER_2.thresholds.iter().map(|t| t * 4.0 - 0.5).collect();
+        assert_eq!(ranks, [0.0, 2.0, 3.0, 1.0]);
+    }
+
+    #[test]
+    fn blue_noise_map_is_a_permutation() {
+        assert_eq!(BLUE_NOISE.size, 32);
+        assert_is_permutation(&BLUE_NOISE);
+    }
+
+    #[test]
+    fn ordered_dither_spread_follows_the_palette() {
+        let mappers = mappers();
+        assert_eq!(mappers.mono_matcher().spacings(), [255.0, 255.0]);
+        for spacing in mappers.grayscale_matcher().spacings() {
+            assert!((6.0..=17.0).contains(&spacing), "grey ramp spacing {spacing}");
+        }
+        let cube_spacing = mappers.ansi256_matcher().spacings()[21];
+        assert_eq!(cube_spacing, 40.0);
+    }
+
+    #[test]
+    fn ordered_dither_in_grayscale_only_reaches_the_neighbouring_greys() {
+        let mappers = mappers();
+        let matcher = mappers.grayscale_matcher();
+        let image = flat((100, 100, 100));
+        let closest = matcher.closest_index((100, 100, 100));
+        for dither in ORDERED {
+            let output = dithered(dither, &image, matcher);
+            for pixel in output.pixels() {
+                let index = matcher.closest_index((pixel[0], pixel[1], pixel[2]));
+                assert!(index.abs_diff(closest) <= 1, "{dither:?} reached grey {index}, {closest} is closest");
+            }
+        }
+    }
+}
@@ -0,0 +1,71 @@
+use std::fmt::{Display, Formatter};
+use std::io;
+use std::path::PathBuf;
+use image::ImageError;
+
+/// Everything that can go wrong between reading the input and writing the snippet.
+///
+/// Each variant maps to its own process exit code, see [GaudiError::exit_code].
+#[derive(Debug)]
+pub enum GaudiError {
+    /// Reading the input or writing the output failed. Exit code 3.
+    Io {
+        context: String,
+        source: io::Error,
+    },
+    /// The image format could not be detected or is not supported. Exit code 4.
+    UnsupportedFormat {
+        path: PathBuf,
+    },
+    /// The image format was recognized, but the data is corrupt. Exit code 5.
+    Decode {
+        path: PathBuf,
+        source: ImageError,
+    },
+    /// A combination of options that cannot be rendered. Exit code 2, same as for usage errors.
+    InvalidOption {
+        option: &'static str,
+        message: String,
+    },
+}
+
+impl GaudiError {
+    pub const EXIT_CODE_INVALID_OPTION: i32 = 2;
+    pub const EXIT_CODE_IO: i32 = 3;
+    pub const EXIT_CODE_UNSUPPORTED_FORMAT: i32 = 4;
+    pub const EXIT_CODE_DECODE: i32 = 5;
+
+    pub fn exit_code(&self) -> i32 {
+        match self {
+            GaudiError::Io { .. } => Self::EXIT_CODE_IO,
+            GaudiError::UnsupportedFormat { .. } => Self::EXIT_CODE_UNSUPPORTED_FORMAT,
+            GaudiError::Decode { .. } => Self::EXIT_CODE_DECODE,
+            GaudiError::InvalidOption { .. } => Self::EXIT_CODE_INVALID_OPTION,
+        }
+    }
+
+    pub fn invalid_option(option: &'static str, message: impl Into<String>) -> Self {
+        GaudiError::InvalidOption { option, message: message.into() }
+    }
+}
+
+impl Display for GaudiError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            GaudiError::Io { context, source } => write!(f, "{}: {}", context, source),
+            GaudiError::UnsupportedFormat { path } => write!(f, "{}: unsupported image format", path.display()),
+            GaudiError::Decode { path, source } => write!(f, "{}: failed to decode image data: {}", path.display(), source),
+            GaudiError::InvalidOption { option, message } => write!(f, "invalid value for {}: {}", option, message),
+        }
+    }
+}
+
+impl std::error::Error for GaudiError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            GaudiError::Io { source, .. } => Some(source),
+            GaudiError::Decode { source, .. } => Some(source),
+            GaudiError::UnsupportedFormat { .. } | GaudiError::InvalidOption { .. } => None,
+        }
+    }
+}
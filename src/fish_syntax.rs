@@ -0,0 +1,167 @@
+use std::fmt::Formatter;
+use std::time::Duration;
+use crate::animation::{seconds, Repeat};
+use crate::shell_syntax::{mode_chain, write_chain, ShellSyntax, TerminalCheck};
+
+/// The fish equivalent of [crate::bash_syntax::AUTO_DETECTION_PREAMBLE]. The variables are local
+/// to the snippet, so that nothing is left behind when it is sourced.
+pub const AUTO_DETECTION_PREAMBLE: &str = r#"set -l gaudi_mode ansi
+set -l gaudi_colors
+if test -n "$NO_COLOR"; or test "$TERM" = dumb; or not test -t 1
+    set gaudi_mode plain
+else if contains -- "$COLORTERM" truecolor 24bit; or string match -q -- '*-direct' "$TERM"
+    set gaudi_mode truecolor
+else if test "$TERM" = linux
+    set gaudi_mode 8
+else
+    if command -q tput
+        set gaudi_colors (tput colors 2>/dev/null)
+    else if string match -q -- '*-256color' "$TERM"
+        set gaudi_colors 256
+    else
+        set gaudi_colors 8
+    end
+    if string match -q -r -- '^[0-9]+$' "$gaudi_colors"
+        if test "$gaudi_colors" -ge 16777216
+            set gaudi_mode truecolor
+        else if test "$gaudi_colors" -ge 256
+            set gaudi_mode 256
+        else if test "$gaudi_colors" -lt 8
+            set gaudi_mode mono
+        end
+    else
+        set gaudi_mode mono
+    end
+end
+"#;
+
+/// The fish equivalent of [crate::bash_syntax::WIDTH_DETECTION_PREAMBLE], falling back to the
+/// `$COLUMNS` fish keeps up to date.
+pub const WIDTH_DETECTION_PREAMBLE: &str = r#"set -l gaudi_columns (tput cols 2>/dev/null)
+if not string match -q -r -- '^[0-9]+$' "$gaudi_columns"
+    set gaudi_columns $COLUMNS
+end
+"#;
+
+/// The fish equivalent of [crate::posix_syntax::DEVICE_ATTRIBUTES_QUERY].
+pub const DEVICE_ATTRIBUTES_QUERY: &str = r#"set -l gaudi_da1
+set -l gaudi_stty (stty -g </dev/tty 2>/dev/null)
+if test -t 1; and test -n "$gaudi_stty"
+    stty -echo -icanon min 0 time 10 </dev/tty
+    printf '\e[c' >/dev/tty
+    set gaudi_da1 (dd bs=64 count=1 </dev/tty 2>/dev/null)
+    stty $gaudi_stty </dev/tty
+end
+"#;
+
+/// Escapes `payload` for the format string of fish's `printf`, in single quotes, where only `\'`
+/// and `\\` are escapes. Anything outside of ASCII is left as it is.
+pub fn escape_for_printf_format(payload: &str) -> String {
+    let mut out = String::with_capacity(payload.len());
+
+    for char in payload.chars() {
+        match char {
+            '%' => out.push_str("%%"),
+            // one level for the quotes, one for printf
+            '\\' => out.push_str("\\\\\\\\"),
+            '\'' => out.push_str("\\'"),
+            '\u{1b}' => out.push_str("\\e"),
+            '\n' => out.push_str("\\n"),
+            _ if char.is_ascii_control() => out.push_str(&format!("\\{:03o}", char as u32)),
+            _ => out.push(char),
+        }
+    }
+
+    out
+}
+
+pub struct FishSyntax;
+impl ShellSyntax for FishSyntax {
+    fn write_print(&self, escapes: &str, f: &mut Formatter) -> std::fmt::Result {
+        write!(f, "printf '{}'", escape_for_printf_format(escapes))
+    }
+
+    fn auto_detection_preamble(&self) -> &'static str {
+        AUTO_DETECTION_PREAMBLE
+    }
+
+    fn write_dispatch(&self, variants: &[(&str, String)], fallback: &str, f: &mut Formatter) -> std::fmt::Result {
+        f.write_str("switch $gaudi_mode\n")?;
+        for (name, escapes) in variants {
+            write!(f, "    case {}\n        ", name)?;
+            self.write_print(escapes, f)?;
+            f.write_str("\n")?;
+        }
+        f.write_str("    case '*'\n        ")?;
+        self.write_print(fallback, f)?;
+        f.write_str("\nend\n")
+    }
+
+    fn write_snippet_dispatch(&self, variants: &[(&str, String)], fallback: &str, f: &mut Formatter) -> std::fmt::Result {
+        write_chain(
+            &mode_chain(variants, fallback),
+            ["if {}", "else if {}", "else", "end"],
+            &|mode| format!("test \"$gaudi_mode\" = \"{}\"", mode),
+            f,
+        )
+    }
+
+    fn write_width_dispatch(&self, variants: &[(u32, String)], f: &mut Formatter) -> std::fmt::Result {
+        f.write_str(WIDTH_DETECTION_PREAMBLE)?;
+        write_chain(
+            variants,
+            ["if {}", "else if {}", "else", "end"],
+            &|columns| format!("test \"$gaudi_columns\" -ge {}", columns),
+            f,
+        )
+    }
+
+    fn write_terminal_dispatch(&self, check: TerminalCheck, escapes: &str, fallback: &str, f: &mut Formatter) -> std::fmt::Result {
+        match check {
+            TerminalCheck::DeviceAttribute(attribute) => {
+                f.write_str(DEVICE_ATTRIBUTES_QUERY)?;
+                // the attributes follow `?` and are separated by `;`, the answer ends with `c`
+                write!(f, "if string match -q -r -- '[?;]{}[;c]' \"$gaudi_da1\"\n    ", attribute)?;
+            }
+            TerminalCheck::Environment(variables) => {
+                let conditions: Vec<String> = variables.iter()
+                    .map(|(name, value)| match value {
+                        Some(value) => format!("test \"${}\" = \"{}\"", name, value),
+                        None => format!("test -n \"${}\"", name),
+                    })
+                    .collect();
+                write!(f, "if test -t 1; and begin; {}; end\n    ", conditions.join("; or "))?;
+            }
+        }
+        self.write_print(escapes, f)?;
+        f.write_str("\nelse\n")?;
+        f.write_str(fallback)?;
+        if !fallback.ends_with('\n') {
+            f.write_str("\n")?;
+        }
+        f.write_str("end\n")
+    }
+
+    fn write_animation(&self, start: &str, frames: &[(String, Duration)], end: &str, repeat: Repeat, f: &mut Formatter) -> std::fmt::Result {
+        self.write_print(start, f)?;
+        // Ctrl-C ends sleep, and the handler only marks the interruption, so that `end` is printed
+        // once below; the handler and its flag are global, so they are erased again
+        f.write_str("\nset -g gaudi_interrupted\nfunction gaudi_on_interrupt --on-signal SIGINT\n    set -g gaudi_interrupted 1\nend\n")?;
+        match repeat {
+            Repeat::Times(times) => write!(
+                f,
+                "set -l gaudi_loop 0\nwhile test -z \"$gaudi_interrupted\"; and test $gaudi_loop -lt {}\n    set gaudi_loop (math $gaudi_loop + 1)\n",
+                times,
+            )?,
+            Repeat::Forever => f.write_str("while test -z \"$gaudi_interrupted\"\n")?,
+        }
+        for (escapes, delay) in frames {
+            f.write_str("    ")?;
+            self.write_print(escapes, f)?;
+            write!(f, "\n    sleep {}; and test -z \"$gaudi_interrupted\"; or break\n", seconds(*delay))?;
+        }
+        f.write_str("end\nfunctions -e gaudi_on_interrupt\nset -e -g gaudi_interrupted\n")?;
+        self.write_print(end, f)?;
+        f.write_str("\n")
+    }
+}
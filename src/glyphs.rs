@@ -0,0 +1,541 @@
+use ansi_term::{ANSIGenericString, Style};
+use image::{DynamicImage, GenericImageView, Rgba};
+use crate::colormath::{luminance, CellColorMapper};
+use crate::dither::blue_noise_threshold;
+use crate::render::{image_to_ascii, is_transparent, pad_to_width, Alignment};
+
+/// How pixels are mapped onto characters of a terminal cell.
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord)]
+pub enum GlyphMode {
+    /// ▀ and ▄, 1x2 pixels per cell
+    HalfBlock,
+    /// ▘▝▖▗▚▞▙▛▜▟ and friends, 2x2 pixels per cell
+    Quadrant,
+    /// Sextants from Symbols for Legacy Computing (U+1FB00), 2x3 pixels per cell
+    Sextant,
+    /// Octants from Unicode 16 (U+1CD00), 2x4 pixels per cell
+    Octant,
+    /// Braille patterns (U+2800), 2x4 dots per cell in a single colour on the terminal background
+    Braille(DotThreshold),
+    /// Plain characters picked by luminance, for terminals without unicode fonts. 1x2 pixels per
+    /// cell, which makes up for cells being about twice as high as they are wide.
+    Ascii(AsciiRamp),
+}
+
+impl GlyphMode {
+    /// How many pixels of the image make up one terminal cell, across and down
+    pub fn pixels_per_cell(&self) -> (u32, u32) {
+        match self {
+            GlyphMode::HalfBlock | GlyphMode::Ascii(_) => (1, 2),
+            GlyphMode::Quadrant => (QUADRANT.cell_width, QUADRANT.cell_height),
+            GlyphMode::Sextant => (SEXTANT.cell_width, SEXTANT.cell_height),
+            GlyphMode::Octant => (OCTANT.cell_width, OCTANT.cell_height),
+            GlyphMode::Braille(_) => (2, 4),
+        }
+    }
+
+    pub fn render(
+        &self,
+        image: &DynamicImage,
+        alignment: &Alignment,
+        color_mapper: &CellColorMapper,
+    ) -> Vec<ANSIGenericString<'static, str>> {
+        let spans = match self {
+            GlyphMode::HalfBlock => image_to_ascii(image, alignment.vertical, color_mapper),
+            GlyphMode::Quadrant => image_to_block_glyphs(image, &QUADRANT, alignment, color_mapper),
+            GlyphMode::Sextant => image_to_block_glyphs(image, &SEXTANT, alignment, color_mapper),
+            GlyphMode::Octant => image_to_block_glyphs(image, &OCTANT, alignment, color_mapper),
+            GlyphMode::Braille(dot_threshold) => image_to_braille(image, *dot_threshold, alignment, color_mapper),
+            GlyphMode::Ascii(ramp) => image_to_ascii_ramp(image, ramp, alignment, color_mapper),
+        };
+
+        pad_to_width(spans, alignment)
+    }
+}
+
+/// A family of block characters that can show any foreground/background pattern on a grid of
+/// `cell_width` x `cell_height` pixels.
+pub struct BlockGlyphs {
+    pub cell_width: u32,
+    pub cell_height: u32,
+    /// The character for a pattern. Bit `y * cell_width + x` of the mask is set if pixel (x, y)
+    /// of the cell is drawn in the foreground colour.
+    pub glyph_for_mask: fn(u32) -> char,
+}
+
+pub static QUADRANT: BlockGlyphs = BlockGlyphs {
+    cell_width: 2,
+    cell_height: 2,
+    glyph_for_mask: |mask| QUADRANT_GLYPHS[mask as usize],
+};
+
+static QUADRANT_GLYPHS: [char; 16] = [
+    ' ', '▘', '▝', '▀', '▖', '▌', '▞', '▛', '▗', '▚', '▐', '▜', '▄', '▙', '▟', '█',
+];
+
+/// U+1FB00 onwards has a sextant for every pattern, in mask order, except the four patterns that
+/// already existed: empty, left half, right half and full.
+pub static SEXTANT: BlockGlyphs = BlockGlyphs {
+    cell_width: 2,
+    cell_height: 3,
+    glyph_for_mask: |mask| match mask {
+        0 => ' ',
+        0b010101 => '▌',
+        0b101010 => '▐',
+        0b111111 => '█',
+        _ => {
+            let skipped = 1 + (mask > 0b010101) as u32 + (mask > 0b101010) as u32;
+            char::from_u32(0x1FB00 + mask - skipped).unwrap()
+        }
+    },
+};
+
+/// U+1CD00 onwards has an octant for every pattern, in mask order, except the ones that already
+/// had a character elsewhere.
+pub static OCTANT: BlockGlyphs = BlockGlyphs {
+    cell_width: 2,
+    cell_height: 4,
+    glyph_for_mask: |mask| {
+        if let Some(glyph) = preexisting_octant_glyph(mask) {
+            return glyph;
+        }
+        let skipped = (0..mask).filter(|m| preexisting_octant_glyph(*m).is_some()).count() as u32;
+        char::from_u32(0x1CD00 + mask - skipped).unwrap()
+    },
+};
+
+fn preexisting_octant_glyph(mask: u32) -> Option<char> {
+    // every quadrant covers two octants on top of each other
+    let as_quadrants = [(0b0000_0101, 1), (0b0000_1010, 2), (0b0101_0000, 4), (0b1010_0000, 8)];
+    let mut quadrant_mask = 0;
+    let mut covered = 0;
+    for (octants, quadrant) in as_quadrants {
+        if mask & octants == octants {
+            quadrant_mask |= quadrant;
+            covered |= octants;
+        }
+    }
+    if covered == mask {
+        return Some(QUADRANT_GLYPHS[quadrant_mask]);
+    }
+
+    match mask {
+        0b0000_0001 => Some('\u{1CEA8}'),
+        0b0000_0010 => Some('\u{1CEAB}'),
+        0b0000_0011 => Some('\u{1FB82}'),
+        0b0001_0100 => Some('\u{1FBE6}'),
+        0b0010_1000 => Some('\u{1FBE7}'),
+        0b0011_1111 => Some('\u{1FB85}'),
+        0b0100_0000 => Some('\u{1CEA3}'),
+        0b1000_0000 => Some('\u{1CEA0}'),
+        0b1100_0000 => Some('▂'),
+        0b1111_1100 => Some('▆'),
+        _ => None,
+    }
+}
+
+pub fn image_to_block_glyphs(
+    image: &DynamicImage,
+    glyphs: &BlockGlyphs,
+    alignment: &Alignment,
+    color_mapper: &CellColorMapper,
+) -> Vec<ANSIGenericString<'static, str>> {
+    image_to_cells(image, glyphs.cell_width, glyphs.cell_height, alignment, &mut |cell, _, _| {
+        cell_to_block_glyph(cell, glyphs, color_mapper)
+    })
+}
+
+pub fn image_to_braille(
+    image: &DynamicImage,
+    dot_threshold: DotThreshold,
+    alignment: &Alignment,
+    color_mapper: &CellColorMapper,
+) -> Vec<ANSIGenericString<'static, str>> {
+    image_to_cells(image, 2, 4, alignment, &mut |cell, x, y| {
+        cell_to_braille(cell, x, y, dot_threshold, color_mapper)
+    })
+}
+
+type CellToGlyph<'a> = dyn FnMut(&[Rgba<u8>], u32, u32) -> ANSIGenericString<'static, str> + 'a;
+
+pub fn image_to_ascii_ramp(
+    image: &DynamicImage,
+    ramp: &AsciiRamp,
+    alignment: &Alignment,
+    color_mapper: &CellColorMapper,
+) -> Vec<ANSIGenericString<'static, str>> {
+    image_to_cells(image, 1, 2, alignment, &mut |cell, _, _| {
+        cell_to_ascii(cell, ramp, color_mapper)
+    })
+}
+
+/// Cuts the image into cells of `cell_width` x `cell_height` pixels, row by row, and has
+/// `cell_to_glyph` draw each one. `cell_to_glyph` gets the pixels of the cell in row-major order
+/// and the position of its top left pixel on the padded grid.
+fn image_to_cells(
+    image: &DynamicImage,
+    cell_width: u32,
+    cell_height: u32,
+    alignment: &Alignment,
+    cell_to_glyph: &mut CellToGlyph,
+) -> Vec<ANSIGenericString<'static, str>> {
+    let columns = image.width().div_ceil(cell_width);
+    let rows = image.height().div_ceil(cell_height);
+    // the pixels missing for whole cells are made up with transparent ones, according to the alignment
+    let top_padding = alignment.leading_vertical_padding(rows * cell_height - image.height());
+    let left_padding = alignment.leading_horizontal_padding(columns * cell_width - image.width());
+
+    let mut as_string = Vec::with_capacity((columns as usize + 1) * rows as usize);
+    let mut cell = Vec::with_capacity((cell_width * cell_height) as usize);
+    for row in 0..rows {
+        for col in 0..columns {
+            cell.clear();
+            for dy in 0..cell_height {
+                for dx in 0..cell_width {
+                    let x = (col * cell_width + dx).checked_sub(left_padding);
+                    let y = (row * cell_height + dy).checked_sub(top_padding);
+                    cell.push(match (x, y) {
+                        (Some(x), Some(y)) if x < image.width() && y < image.height() => image.get_pixel(x, y),
+                        _ => Rgba::from([0, 0, 0, 0]),
+                    });
+                }
+            }
+            as_string.push(cell_to_glyph(&cell, col * cell_width, row * cell_height));
+        }
+        as_string.push(Style::default().paint("\n"));
+    }
+
+    as_string
+}
+
+/// Decides which braille dots are raised.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum DotThreshold {
+    /// Dots with at least this luminance are raised
+    Fixed(u8),
+    /// Compares the luminance against a blue noise pattern, which keeps gradients visible
+    Dithered,
+}
+
+impl Default for DotThreshold {
+    fn default() -> Self {
+        DotThreshold::Fixed(128)
+    }
+}
+
+/// The characters of the ASCII mode, from darkest to brightest.
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct AsciiRamp {
+    pub characters: Vec<char>,
+    /// Whether to colour the characters, too
+    pub colored: bool,
+}
+
+impl AsciiRamp {
+    pub const DEFAULT_CHARACTERS: &'static str = " .:-=+*#%@";
+
+    pub fn new(characters: &str, colored: bool) -> Self {
+        AsciiRamp { characters: characters.chars().collect(), colored }
+    }
+}
+
+impl Default for AsciiRamp {
+    fn default() -> Self {
+        AsciiRamp::new(AsciiRamp::DEFAULT_CHARACTERS, false)
+    }
+}
+
+fn cell_to_ascii(
+    cell: &[Rgba<u8>],
+    ramp: &AsciiRamp,
+    color_mapper: &CellColorMapper,
+) -> ANSIGenericString<'static, str> {
+    let opaque_mask = cell.iter().enumerate()
+        .filter(|(_, pixel)| !is_transparent(pixel))
+        .fold(0, |mask, (index, _)| mask | (1 << index));
+    if opaque_mask == 0 {
+        return Style::default().paint(" ");
+    }
+
+    // transparent pixels are not drawn, so they must not darken the cell
+    let opaque_pixels = cell.iter().filter(|pixel| !is_transparent(pixel));
+    let mean_luminance = opaque_pixels.clone().map(luminance).sum::<f32>() / opaque_pixels.count() as f32;
+    let index = (mean_luminance / 255.0 * (ramp.characters.len() - 1) as f32).round() as usize;
+    let glyph = ramp.characters[index.min(ramp.characters.len() - 1)].to_string();
+    if ramp.colored {
+        color_mapper.foreground(&mean_color(cell, opaque_mask)).paint(glyph)
+    } else {
+        Style::default().paint(glyph)
+    }
+}
+
+/// Bit of the U+2800 code point offset for the dot at (x, y) of a braille cell
+static BRAILLE_DOT_BITS: [u32; 8] = [
+    0x01, 0x08,
+    0x02, 0x10,
+    0x04, 0x20,
+    0x40, 0x80,
+];
+
+fn cell_to_braille(
+    cell: &[Rgba<u8>],
+    x: u32,
+    y: u32,
+    dot_threshold: DotThreshold,
+    color_mapper: &CellColorMapper,
+) -> ANSIGenericString<'static, str> {
+    let mut raised_mask = 0;
+    for (index, pixel) in cell.iter().enumerate() {
+        let threshold = match dot_threshold {
+            DotThreshold::Fixed(threshold) => threshold as f32,
+            DotThreshold::Dithered => 255.0 * blue_noise_threshold(x + index as u32 % 2, y + index as u32 / 2),
+        };
+        if !is_transparent(pixel) && luminance(pixel) >= threshold {
+            raised_mask |= 1 << index;
+        }
+    }
+
+    if raised_mask == 0 {
+        // like in the other glyph modes, cells without any opaque pixel are left empty; opaque
+        // ones are the blank pattern, so that the art is braille throughout
+        let blank = if cell.iter().all(is_transparent) { " " } else { "\u{2800}" };
+        return Style::default().paint(blank);
+    }
+
+    let dots = BRAILLE_DOT_BITS.iter().enumerate()
+        .filter(|(index, _)| raised_mask & (1 << index) != 0)
+        .fold(0, |dots, (_, bit)| dots | bit);
+    let glyph = char::from_u32(0x2800 + dots).unwrap();
+    color_mapper.foreground(&mean_color(cell, raised_mask)).paint(glyph.to_string())
+}
+
+fn cell_to_block_glyph(
+    cell: &[Rgba<u8>],
+    glyphs: &BlockGlyphs,
+    color_mapper: &CellColorMapper,
+) -> ANSIGenericString<'static, str> {
+    let full_mask = (1u32 << cell.len()) - 1;
+    let opaque_mask = cell.iter().enumerate()
+        .filter(|(_, pixel)| !is_transparent(pixel))
+        .fold(0, |mask, (index, _)| mask | (1 << index));
+
+    if opaque_mask == 0 {
+        return Style::default().paint(" ");
+    }
+
+    if opaque_mask != full_mask {
+        // the background has to stay transparent, so all opaque pixels share the foreground colour
+        let foreground = mean_color(cell, opaque_mask);
+        return color_mapper.foreground(&foreground).paint((glyphs.glyph_for_mask)(opaque_mask).to_string());
+    }
+
+    let (mask, foreground, background) = best_two_color_split(cell);
+    match background {
+        None => color_mapper.foreground(&foreground).paint((glyphs.glyph_for_mask)(mask).to_string()),
+        Some(background) => color_mapper.foreground(&foreground)
+            .on(color_mapper.background(&background))
+            .paint((glyphs.glyph_for_mask)(mask).to_string()),
+    }
+}
+
+/// Finds the partition of the pixels into a foreground and a background set that, when each set
+/// is drawn in its mean colour, has the least squared error. Returns the foreground mask and the
+/// two colours; no background colour if the cell is best drawn in a single colour.
+pub fn best_two_color_split(cell: &[Rgba<u8>]) -> (u32, Rgba<u8>, Option<Rgba<u8>>) {
+    let full_mask = (1u32 << cell.len()) - 1;
+    let squared_sum: f32 = cell.iter()
+        .map(|p| (0..3).map(|c| (p[c] as f32).powi(2)).sum::<f32>())
+        .sum();
+    let error_of = |mask: u32| {
+        let (sum, count) = channel_sums(cell, mask);
+        if count == 0 {
+            return 0.0;
+        }
+        sum.iter().map(|s| s * s).sum::<f32>() / count as f32
+    };
+
+    // a solid block wins all ties, which keeps uniform areas free of needless background codes
+    let mut best_mask = full_mask;
+    let mut best_error = squared_sum - error_of(full_mask);
+    for mask in 1..full_mask {
+        let error = squared_sum - error_of(mask) - error_of(full_mask & !mask);
+        if error < best_error {
+            best_mask = mask;
+            best_error = error;
+        }
+    }
+
+    if best_mask == full_mask {
+        (full_mask, mean_color(cell, full_mask), None)
+    } else {
+        (best_mask, mean_color(cell, best_mask), Some(mean_color(cell, full_mask & !best_mask)))
+    }
+}
+
+fn channel_sums(cell: &[Rgba<u8>], mask: u32) -> ([f32; 3], u32) {
+    let mut sum = [0.0f32; 3];
+    let mut count = 0;
+    for (index, pixel) in cell.iter().enumerate() {
+        if mask & (1 << index) != 0 {
+            for channel in 0..3 {
+                sum[channel] += pixel[channel] as f32;
+            }
+            count += 1;
+        }
+    }
+
+    (sum, count)
+}
+
+fn mean_color(cell: &[Rgba<u8>], mask: u32) -> Rgba<u8> {
+    let (sum, count) = channel_sums(cell, mask);
+    let count = count.max(1) as f32;
+    Rgba([
+        (sum[0] / count).round() as u8,
+        (sum[1] / count).round() as u8,
+        (sum[2] / count).round() as u8,
+        255,
+    ])
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashSet;
+    use image::Rgba;
+    use crate::colormath::{color_mapping_truecolor, CellColorMapper};
+    use super::*;
+
+    const OPAQUE_BLACK: Rgba<u8> = Rgba([0, 0, 0, 255]);
+    const OPAQUE_WHITE: Rgba<u8> = Rgba([255, 255, 255, 255]);
+    const TRANSPARENT: Rgba<u8> = Rgba([0, 0, 0, 0]);
+
+    fn ascii(cell: &[Rgba<u8>]) -> String {
+        let mapper = CellColorMapper::uniform(&color_mapping_truecolor);
+        cell_to_ascii(cell, &AsciiRamp::default(), &mapper).to_string()
+    }
+
+    fn all_glyphs(glyphs: &BlockGlyphs) -> Vec<char> {
+        (0..1 << (glyphs.cell_width * glyphs.cell_height)).map(glyphs.glyph_for_mask).collect()
+    }
+
+    fn assert_distinct(glyphs: &[char]) {
+        assert_eq!(glyphs.iter().collect::<HashSet<_>>().len(), glyphs.len());
+    }
+
+    #[test]
+    fn quadrant_masks_have_distinct_glyphs() {
+        let glyphs = all_glyphs(&QUADRANT);
+        assert_eq!(glyphs.len(), 16);
+        assert_distinct(&glyphs);
+        assert_eq!(glyphs[0b0011], '▀');
+        assert_eq!(glyphs[0b0101], '▌');
+        assert_eq!(glyphs[0b1001], '▚');
+        assert_eq!(glyphs[0b1111], '█');
+    }
+
+    #[test]
+    fn sextant_masks_have_distinct_glyphs() {
+        let glyphs = all_glyphs(&SEXTANT);
+        assert_eq!(glyphs.len(), 64);
+        assert_distinct(&glyphs);
+        assert_eq!(glyphs[0b000001], '\u{1FB00}');
+        assert_eq!(glyphs[0b111110], '\u{1FB3B}');
+        let new_glyphs = glyphs.iter().filter(|glyph| ('\u{1FB00}'..='\u{1FB3B}').contains(*glyph)).count();
+        assert_eq!(new_glyphs, 60);
+    }
+
+    #[test]
+    fn octant_masks_have_distinct_glyphs() {
+        let glyphs = all_glyphs(&OCTANT);
+        assert_eq!(glyphs.len(), 256);
+        assert_distinct(&glyphs);
+        let new_glyphs: Vec<char> = glyphs.iter().copied().filter(|glyph| ('\u{1CD00}'..='\u{1CDFF}').contains(glyph)).collect();
+        assert_eq!(new_glyphs.first(), Some(&'\u{1CD00}'));
+        assert_eq!(new_glyphs.last(), Some(&'\u{1CDE5}'));
+        assert_eq!(new_glyphs.len(), 0x1CDE5 - 0x1CD00 + 1);
+        assert_eq!(glyphs[0b0000_1111], '▀');
+        assert_eq!(glyphs[0b1111_0000], '▄');
+    }
+
+    #[test]
+    fn best_split_separates_two_colours() {
+        let red = Rgba([200, 0, 0, 255]);
+        let blue = Rgba([0, 0, 200, 255]);
+        assert_eq!(best_two_color_split(&[red, red, blue, blue]), (0b0011, red, Some(blue)));
+        assert_eq!(best_two_color_split(&[red, blue, blue, red]), (0b0110, blue, Some(red)));
+    }
+
+    #[test]
+    fn best_split_keeps_uniform_cells_solid() {
+        let grey = Rgba([90, 90, 90, 255]);
+        assert_eq!(best_two_color_split(&[grey; 4]), (0b1111, grey, None));
+    }
+
+    #[test]
+    fn ascii_ramp_follows_luminance() {
+        assert_eq!(ascii(&[OPAQUE_BLACK, OPAQUE_BLACK]), " ");
+        assert_eq!(ascii(&[OPAQUE_WHITE, OPAQUE_WHITE]), "@");
+        assert_eq!(ascii(&[OPAQUE_BLACK, OPAQUE_WHITE]), "+");
+    }
+
+    #[test]
+    fn ascii_ignores_transparent_pixels() {
+        assert_eq!(ascii(&[TRANSPARENT, TRANSPARENT]), " ");
+        assert_eq!(ascii(&[OPAQUE_WHITE, TRANSPARENT]), "@");
+    }
+
+    fn braille(cell: &[Rgba<u8>], dot_threshold: DotThreshold) -> String {
+        let mapper = CellColorMapper::uniform(&color_mapping_truecolor);
+        cell_to_braille(cell, 0, 0, dot_threshold, &mapper).to_string()
+    }
+
+    /// The glyph without the escapes around it
+    fn braille_glyph(cell: &[Rgba<u8>], dot_threshold: DotThreshold) -> char {
+        let glyphs: Vec<char> = braille(cell, dot_threshold).chars().filter(|c| ('\u{2800}'..='\u{28ff}').contains(c)).collect();
+        assert_eq!(glyphs.len(), 1, "{:?}", braille(cell, dot_threshold));
+        glyphs[0]
+    }
+
+    #[test]
+    fn braille_dots_follow_the_unicode_numbering() {
+        // dots 1-3 and 4-6 go down the left and right column, 7 and 8 are the bottom row
+        let expected = ['⠁', '⠈', '⠂', '⠐', '⠄', '⠠', '⡀', '⢀'];
+        for (index, expected) in expected.into_iter().enumerate() {
+            let mut cell = [OPAQUE_BLACK; 8];
+            cell[index] = OPAQUE_WHITE;
+            assert_eq!(braille_glyph(&cell, DotThreshold::default()), expected, "pixel ({}, {})", index % 2, index / 2);
+        }
+        assert_eq!(braille_glyph(&[OPAQUE_WHITE; 8], DotThreshold::default()), '⣿');
+    }
+
+    #[test]
+    fn braille_cells_without_raised_dots_are_blank() {
+        assert_eq!(braille(&[OPAQUE_BLACK; 8], DotThreshold::default()), "\u{2800}");
+        assert_eq!(braille(&[TRANSPARENT; 8], DotThreshold::default()), " ");
+    }
+
+    #[test]
+    fn braille_dots_are_raised_from_the_fixed_threshold() {
+        let grey = |level: u8| [Rgba([level, level, level, 255]); 8];
+        assert_eq!(braille_glyph(&grey(99), DotThreshold::Fixed(100)), '\u{2800}');
+        assert_eq!(braille_glyph(&grey(100), DotThreshold::Fixed(100)), '⣿');
+        assert_eq!(braille_glyph(&grey(101), DotThreshold::Fixed(100)), '⣿');
+    }
+
+    #[test]
+    fn dithered_braille_raises_dots_in_proportion_to_the_luminance() {
+        let raised_dots = |level: u8| {
+            let cell = [Rgba([level, level, level, 255]); 8];
+            let mapper = CellColorMapper::uniform(&color_mapping_truecolor);
+            (0..16).flat_map(|y| (0..16).map(move |x| (x * 2, y * 4)))
+                .map(|(x, y)| cell_to_braille(&cell, x, y, DotThreshold::Dithered, &mapper).to_string())
+                .flat_map(|glyph| glyph.chars().filter(|c| ('\u{2800}'..='\u{28ff}').contains(c)).collect::<Vec<_>>())
+                .map(|glyph| (glyph as u32 - 0x2800).count_ones())
+                .sum::<u32>()
+        };
+        assert_eq!(raised_dots(0), 0);
+        assert_eq!(raised_dots(255), 16 * 16 * 8);
+        let half = raised_dots(128);
+        assert!((16 * 16 * 8 * 2 / 5..=16 * 16 * 8 * 3 / 5).contains(&half), "{}", half);
+    }
+}
@@ -0,0 +1,87 @@
+use std::fs::File;
+use std::io::{BufReader, ErrorKind};
+use std::path::Path;
+use std::time::Duration;
+use image::{AnimationDecoder, DynamicImage, ImageError, ImageFormat, ImageReader};
+use image::codecs::gif::GifDecoder;
+use image::codecs::png::PngDecoder;
+use image::codecs::webp::WebPDecoder;
+use crate::animation::Frame;
+use crate::error::GaudiError;
+
+pub fn load_image(path: &Path) -> Result<DynamicImage, GaudiError> {
+    let reader = open_image(path)?;
+    let image = reader.decode().map_err(|e| image_error_to_gaudi_error(path, e))?;
+    Ok(DynamicImage::ImageRgba8(image.into_rgba8()))
+}
+
+/// Every frame of an animated GIF, PNG (APNG) or WebP with its delay, or the image as a single
+/// frame for the other formats and images that aren't animated
+pub fn load_frames(path: &Path) -> Result<Vec<Frame>, GaudiError> {
+    let reader = open_image(path)?;
+    let to_gaudi_error = |e| image_error_to_gaudi_error(path, e);
+    let frames = match reader.format() {
+        Some(ImageFormat::Gif) => GifDecoder::new(reader.into_inner()).map_err(to_gaudi_error)?.into_frames(),
+        Some(ImageFormat::Png) => {
+            let decoder = PngDecoder::new(reader.into_inner()).map_err(to_gaudi_error)?;
+            if !decoder.is_apng().map_err(to_gaudi_error)? {
+                return Ok(vec![still_frame(load_image(path)?)]);
+            }
+            decoder.apng().map_err(to_gaudi_error)?.into_frames()
+        }
+        Some(ImageFormat::WebP) => {
+            let decoder = WebPDecoder::new(reader.into_inner()).map_err(to_gaudi_error)?;
+            if !decoder.has_animation() {
+                return Ok(vec![still_frame(load_image(path)?)]);
+            }
+            decoder.into_frames()
+        }
+        _ => return Ok(vec![still_frame(reader.decode().map_err(to_gaudi_error)?)]),
+    };
+
+    frames
+        .map(|frame| {
+            let frame = frame.map_err(to_gaudi_error)?;
+            let (numerator, denominator) = frame.delay().numer_denom_ms();
+            Ok(Frame {
+                delay: Duration::from_micros(numerator as u64 * 1000 / denominator.max(1) as u64),
+                image: DynamicImage::ImageRgba8(frame.into_buffer()),
+            })
+        })
+        .collect()
+}
+
+fn still_frame(image: DynamicImage) -> Frame {
+    Frame { image: DynamicImage::ImageRgba8(image.into_rgba8()), delay: Duration::ZERO }
+}
+
+/// The reader for the image, with its format guessed from the content
+fn open_image(path: &Path) -> Result<ImageReader<BufReader<File>>, GaudiError> {
+    let input_file = File::open(path).map_err(|source| GaudiError::Io {
+        context: format!("could not open {}", path.display()),
+        source,
+    })?;
+
+    let reader = ImageReader::new(BufReader::new(input_file))
+        .with_guessed_format()
+        .map_err(|source| GaudiError::Io {
+            context: format!("could not read {}", path.display()),
+            source,
+        })?;
+
+    if reader.format().is_none() {
+        return Err(GaudiError::UnsupportedFormat { path: path.to_path_buf() });
+    }
+    Ok(reader)
+}
+
+fn image_error_to_gaudi_error(path: &Path, error: ImageError) -> GaudiError {
+    match error {
+        ImageError::IoError(source) if source.kind() != ErrorKind::UnexpectedEof => GaudiError::Io {
+            context: format!("could not read {}", path.display()),
+            source,
+        },
+        ImageError::Unsupported(_) => GaudiError::UnsupportedFormat { path: path.to_path_buf() },
+        source => GaudiError::Decode { path: path.to_path_buf(), source },
+    }
+}
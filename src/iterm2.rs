@@ -0,0 +1,25 @@
+use image::DynamicImage;
+use crate::base64;
+use crate::render::encode_png;
+use crate::shell_syntax::TerminalCheck;
+
+/// How snippets recognise iTerm2, and WezTerm, which understands its inline images as well.
+/// tmux overwrites `$TERM_PROGRAM`, but leaves `$LC_TERMINAL` as iTerm2 sets it.
+pub const TERMINAL_CHECK: TerminalCheck = TerminalCheck::Environment(&[
+    ("TERM_PROGRAM", Some("iTerm.app")),
+    ("TERM_PROGRAM", Some("WezTerm")),
+    ("LC_TERMINAL", Some("iTerm2")),
+]);
+
+/// Encodes the image as PNG for an iTerm2 inline image (OSC 1337), to be shown scaled to `cells`,
+/// its columns and rows. Ends with a line break, like the other output.
+pub fn encode(image: &DynamicImage, cells: (u32, u32)) -> String {
+    let png = encode_png(image);
+    format!(
+        "\x1b]1337;File=inline=1;size={};width={};height={};preserveAspectRatio=1:{}\x07\n",
+        png.len(),
+        cells.0,
+        cells.1,
+        base64::encode(&png),
+    )
+}
@@ -0,0 +1,40 @@
+use std::fmt::Write;
+use image::DynamicImage;
+use crate::base64;
+use crate::render::encode_png;
+use crate::shell_syntax::TerminalCheck;
+
+/// The largest payload one escape sequence may carry, in bytes of base64
+pub const CHUNK_SIZE: usize = 4096;
+
+/// How snippets recognise kitty, and the terminals that set up the same environment for it
+pub const TERMINAL_CHECK: TerminalCheck = TerminalCheck::Environment(&[
+    ("TERM", Some("xterm-kitty")),
+    ("KITTY_WINDOW_ID", None),
+]);
+
+/// Encodes the image as PNG for the kitty graphics protocol, to be shown scaled to `cells`, its
+/// columns and rows. The payload is split into escape sequences of at most [CHUNK_SIZE] bytes.
+/// Responses from the terminal are switched off, as nothing would read them. Ends with a line
+/// break, like the other output.
+pub fn encode(image: &DynamicImage, cells: (u32, u32)) -> String {
+    let payload = base64::encode(&encode_png(image));
+
+    let chunks: Vec<&[u8]> = payload.as_bytes().chunks(CHUNK_SIZE).collect();
+    let mut out = String::with_capacity(payload.len() + chunks.len() * 16);
+    for (index, chunk) in chunks.iter().enumerate() {
+        // m=1 announces another chunk
+        let more = u8::from(index + 1 < chunks.len());
+        if index == 0 {
+            write!(out, "\x1b_Ga=T,f=100,q=2,c={},r={},m={};", cells.0, cells.1, more).unwrap();
+        } else {
+            write!(out, "\x1b_Gm={};", more).unwrap();
+        }
+        // base64 is ASCII
+        out.push_str(std::str::from_utf8(chunk).unwrap());
+        out.push_str("\x1b\\");
+    }
+
+    out.push('\n');
+    out
+}
@@ -0,0 +1,34 @@
+pub mod colormath;
+pub mod color_distance;
+pub mod dither;
+pub mod bash_syntax;
+pub mod posix_syntax;
+pub mod zsh_syntax;
+pub mod fish_syntax;
+pub mod pwsh_syntax;
+pub mod shell_syntax;
+pub mod render;
+pub mod glyphs;
+pub mod snippet;
+pub mod renderer;
+pub mod error;
+pub mod input;
+pub mod palette;
+pub mod terminal;
+pub mod sixel;
+pub mod kitty;
+pub mod iterm2;
+pub mod passthrough;
+pub mod animation;
+pub mod delta;
+pub mod base64;
+
+pub use renderer::{BrightStrategy, ColorMode, Emitter, OutputFormat, Passthrough, Protocol, Renderer};
+pub use color_distance::ColorDistance;
+pub use dither::Dither;
+pub use error::GaudiError;
+pub use glyphs::GlyphMode;
+pub use render::{Alignment, HorizontalAlignment, VerticalAlignment};
+pub use input::{load_frames, load_image};
+pub use animation::{Frame, Repeat};
+pub use palette::{load_ansi256_palette, load_palette, Ansi256Palette, TerminalPalette};
@@ -1,46 +1,285 @@
-pub mod colormath;
-pub mod bash_syntax;
-
-use std::fmt::{Debug, Display, Formatter};
-use std::io::BufReader;
+use std::fmt::Debug;
+use std::io::Write;
 use clap::{Parser, ValueEnum};
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
+use std::process::ExitCode;
 use std::str::FromStr;
-use image::{DynamicImage, GenericImageView, ImageReader, Rgba,};
-use ansi_term::{ANSIGenericString, Colour, Style};
 use image::imageops::FilterType;
-use crate::bash_syntax::escape_for_string_content;
+use gaudi::{Alignment, Ansi256Palette, BrightStrategy, ColorDistance, ColorMode, Dither, Emitter, GaudiError, OutputFormat, Passthrough, Protocol, GlyphMode, HorizontalAlignment, Renderer, Repeat, TerminalPalette, VerticalAlignment};
+use gaudi::glyphs::{AsciiRamp, DotThreshold};
+
+const EXIT_CODES_HELP: &str = "\
+Exit codes:
+  0  success
+  2  invalid command line option
+  3  I/O error while reading the image or writing the snippet
+  4  unsupported image format
+  5  corrupt image data";
 
 #[derive(Parser, Debug)]
-#[command(version, about, long_about = None)]
+#[command(version, about, long_about = None, after_help = EXIT_CODES_HELP)]
 struct Args {
     input_file: PathBuf,
 
-    #[arg(long, value_enum, default_value = "up")]
-    vertical_gravity: VerticalDirection,
+    /// Deprecated, use --align top or --align bottom
+    #[arg(long, value_enum, conflicts_with = "align")]
+    vertical_gravity: Option<VerticalDirection>,
+
+    /// <VERTICAL>[,<HORIZONTAL>] with top, center or bottom and left, center or right. center
+    /// puts an odd spare pixel row below the image, like top
+    #[arg(long, default_value = "top,left")]
+    align: RequestedAlignment,
+
+    /// The width in columns to align the image within
+    #[arg(long)]
+    align_width: Option<u32>,
 
     #[arg(long)]
     resize_to_width: Option<u32>,
 
+    /// terminal or <COLUMNS>x<ROWS>: scales the image to the largest size that fits, terminal asks
+    /// $COLUMNS and $LINES or the terminal itself
+    #[arg(long, conflicts_with = "resize_to_width")]
+    fit: Option<RequestedFit>,
+
+    /// <COLUMNS>,<COLUMNS>,...: embeds the image once per width into the snippet, which prints the
+    /// widest that fits into the terminal
+    #[arg(long, value_delimiter = ',', conflicts_with_all = ["fit", "resize_to_width"])]
+    fit_widths: Vec<u32>,
+
     #[arg(long, value_enum, default_value = "catmull-rom")]
     resize_filter: RequestedFilterType,
 
+    /// truecolor, ansi, 256, grayscale, mono, 8 or auto
     #[arg(long, default_value = "auto")]
     color_mode: RequestedColorMode,
+
+    /// For --color-mode ansi: how bright colours are requested, bold is for terminals that only
+    /// show them for bold text
+    #[arg(long, value_enum, default_value = "aixterm")]
+    bright_strategy: RequestedBrightStrategy,
+
+    /// For --color-mode ansi: the colours your terminal shows for the 16 ANSI colours. One of
+    /// xterm, vga, tango, solarized, gruvbox-dark, gruvbox-light or dracula, or an iTerm2
+    /// .itermcolors, Xresources, Alacritty or Kitty configuration file
+    #[arg(long, default_value = "xterm")]
+    palette: String,
+
+    /// For --color-mode 256: the colours your terminal shows for the 256 colour palette, from a
+    /// JSON, GIMP .gpl or .hex file. Defaults to the xterm colours
+    #[arg(long)]
+    palette_256: Option<PathBuf>,
+
+    /// How the closest palette colour is measured. ciede2000 can't narrow down the palette
+    /// entries up front and compares every pixel against the whole palette, which is slower
+    #[arg(long, value_enum, default_value = "rgb")]
+    color_distance: RequestedColorDistance,
+
+    #[arg(long, value_enum, default_value = "none")]
+    dither: RequestedDither,
+
+    #[arg(long, value_enum, default_value = "half-block")]
+    glyphs: RequestedGlyphMode,
+
+    /// For --glyphs braille: the luminance from which a dot is raised, 0-255, or "dither"
+    #[arg(long, default_value = "128")]
+    braille_threshold: RequestedDotThreshold,
+
+    /// For --glyphs ascii: the characters to use, from darkest to brightest
+    #[arg(long, default_value = AsciiRamp::DEFAULT_CHARACTERS)]
+    ascii_ramp: String,
+
+    /// For --glyphs ascii: colour the characters, too
+    #[arg(long)]
+    ascii_color: bool,
+
+    /// The shell the snippet is written for, posix runs under dash and busybox sh, pwsh is
+    /// PowerShell
+    #[arg(long, value_enum, default_value = "bash")]
+    shell: RequestedShell,
+
+    /// cells for coloured characters, sixel for DEC sixel graphics, kitty for the kitty graphics
+    /// protocol, iterm2 for iTerm2 inline images. Snippets fall back to cells where the terminal
+    /// doesn't support the protocol
+    #[arg(long, value_enum, default_value = "cells")]
+    protocol: RequestedProtocol,
+
+    /// Wraps the graphics protocols for the terminal multiplexer they are shown in, auto for the
+    /// one gaudi runs in. tmux needs `set -g allow-passthrough on`
+    #[arg(long, value_enum, default_value = "auto")]
+    passthrough: RequestedPassthrough,
+
+    /// How often an animated GIF, PNG or WebP is played: a number of times or forever
+    #[arg(long = "loop", default_value = "1")]
+    repeat: RequestedRepeat,
+
+    /// snippet for a shell script that prints the image, raw for the escape sequences themselves
+    #[arg(long, value_enum, default_value = "snippet")]
+    output_format: RequestedOutputFormat,
+
+    /// For --output-format raw: end with a colour reset and a clear to the end of the line
+    #[arg(long)]
+    reset: bool,
 }
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, ValueEnum)]
 enum VerticalDirection {
-    UP,
-    DOWN,
+    Up,
+    Down,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+struct RequestedAlignment {
+    vertical: VerticalAlignment,
+    horizontal: HorizontalAlignment,
+}
+impl FromStr for RequestedAlignment {
+    type Err = &'static str;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        const ERROR: &str = "Invalid alignment, use <VERTICAL>[,<HORIZONTAL>] with top, center or bottom and left, center or right";
+        let input_lowercase = s.to_lowercase();
+        let mut alignment = RequestedAlignment { vertical: VerticalAlignment::Top, horizontal: HorizontalAlignment::Left };
+        match input_lowercase.split_once(',') {
+            Some((vertical, horizontal)) => {
+                alignment.vertical = parse_vertical_alignment(vertical.trim()).ok_or(ERROR)?;
+                alignment.horizontal = parse_horizontal_alignment(horizontal.trim()).ok_or(ERROR)?;
+            },
+            None => {
+                if let Some(vertical) = parse_vertical_alignment(&input_lowercase) {
+                    alignment.vertical = vertical;
+                } else {
+                    alignment.horizontal = parse_horizontal_alignment(&input_lowercase).ok_or(ERROR)?;
+                }
+            }
+        }
+
+        Ok(alignment)
+    }
+}
+
+fn parse_vertical_alignment(s: &str) -> Option<VerticalAlignment> {
+    match s {
+        "top" => Some(VerticalAlignment::Top),
+        "center" => Some(VerticalAlignment::Center),
+        "bottom" => Some(VerticalAlignment::Bottom),
+        _ => None,
+    }
+}
+
+fn parse_horizontal_alignment(s: &str) -> Option<HorizontalAlignment> {
+    match s {
+        "left" => Some(HorizontalAlignment::Left),
+        "center" => Some(HorizontalAlignment::Center),
+        "right" => Some(HorizontalAlignment::Right),
+        _ => None,
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+enum RequestedFit {
+    Terminal,
+    Cells(u32, u32),
+}
+impl FromStr for RequestedFit {
+    type Err = &'static str;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        const ERROR: &str = "Invalid size, use terminal or <COLUMNS>x<ROWS>";
+        if s.eq_ignore_ascii_case("terminal") {
+            return Ok(RequestedFit::Terminal);
+        }
+        let (columns, rows) = s.split_once(['x', 'X']).ok_or(ERROR)?;
+        Ok(RequestedFit::Cells(columns.trim().parse().map_err(|_| ERROR)?, rows.trim().parse().map_err(|_| ERROR)?))
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+enum RequestedRepeat {
+    Times(u32),
+    Forever,
+}
+impl FromStr for RequestedRepeat {
+    type Err = &'static str;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        if s.eq_ignore_ascii_case("forever") {
+            return Ok(RequestedRepeat::Forever);
+        }
+        match s.trim().parse() {
+            Ok(times) if times > 0 => Ok(RequestedRepeat::Times(times)),
+            _ => Err("Invalid loop count, use a number greater than 0 or forever"),
+        }
+    }
+}
+impl From<RequestedRepeat> for Repeat {
+    fn from(value: RequestedRepeat) -> Self {
+        match value {
+            RequestedRepeat::Times(times) => Repeat::Times(times),
+            RequestedRepeat::Forever => Repeat::Forever,
+        }
+    }
+}
+
+fn fit_from_args(args: &Args) -> Result<Option<(u32, u32)>, GaudiError> {
+    match args.fit {
+        Some(RequestedFit::Terminal) => match gaudi::terminal::window_size() {
+            (None, None) => Err(GaudiError::invalid_option("fit", "could not find out the size of the terminal, use <COLUMNS>x<ROWS>")),
+            // a side whose size is unknown doesn't limit the image
+            (columns, rows) => Ok(Some((
+                columns.unwrap_or(u32::MAX),
+                // one row less, so that the prompt below the image doesn't push its top out of view
+                rows.map_or(u32::MAX, |rows| rows.saturating_sub(1).max(1)),
+            ))),
+        },
+        Some(RequestedFit::Cells(columns, rows)) => Ok(Some((columns, rows))),
+        None => Ok(None),
+    }
+}
+
+fn passthrough_from_args(args: &Args) -> Passthrough {
+    match args.passthrough {
+        RequestedPassthrough::Auto => match gaudi::terminal::passthrough_from_environment() {
+            // the other protocols don't make it through screen anyway
+            Passthrough::Screen if args.protocol != RequestedProtocol::ITerm2 => Passthrough::None,
+            passthrough => passthrough,
+        },
+        RequestedPassthrough::None => Passthrough::None,
+        RequestedPassthrough::Tmux => Passthrough::Tmux,
+        RequestedPassthrough::Screen => Passthrough::Screen,
+    }
+}
+
+fn palette_from_args(args: &Args) -> Result<TerminalPalette, GaudiError> {
+    match TerminalPalette::builtin(&args.palette) {
+        Some(palette) => Ok(palette),
+        None => gaudi::load_palette(Path::new(&args.palette)),
+    }
+}
+
+fn alignment_from_args(args: &Args) -> Alignment {
+    let vertical = match args.vertical_gravity {
+        Some(VerticalDirection::Up) => VerticalAlignment::Top,
+        Some(VerticalDirection::Down) => VerticalAlignment::Bottom,
+        None => args.align.vertical,
+    };
+
+    Alignment {
+        vertical,
+        horizontal: args.align.horizontal,
+        width: args.align_width,
+    }
 }
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
 enum RequestedColorMode {
     TrueColor,
-    ANSI,
+    Ansi,
     M256Color,
-    AUTO,
+    Grayscale,
+    Mono,
+    M8Color,
+    Auto,
 }
 impl FromStr for RequestedColorMode {
     type Err = &'static str;
@@ -49,175 +288,271 @@ impl FromStr for RequestedColorMode {
         let input_lowercase = s.to_lowercase();
         match input_lowercase.as_str() {
             "truecolor" => Ok(RequestedColorMode::TrueColor),
-            "ansi" => Ok(RequestedColorMode::ANSI),
+            "ansi" => Ok(RequestedColorMode::Ansi),
             "256" => Ok(RequestedColorMode::M256Color),
-            "auto" => Ok(RequestedColorMode::AUTO),
-            _ => Err("Invalid color mode, use truecolor, ansi, 256 or auto"),
+            "grayscale" | "greyscale" => Ok(RequestedColorMode::Grayscale),
+            "mono" => Ok(RequestedColorMode::Mono),
+            "8" => Ok(RequestedColorMode::M8Color),
+            "auto" => Ok(RequestedColorMode::Auto),
+            _ => Err("Invalid color mode, use truecolor, ansi, 256, grayscale, mono, 8 or auto"),
+        }
+    }
+}
+impl From<RequestedColorMode> for ColorMode {
+    fn from(value: RequestedColorMode) -> Self {
+        match value {
+            RequestedColorMode::TrueColor => ColorMode::TrueColor,
+            RequestedColorMode::Ansi => ColorMode::Ansi,
+            RequestedColorMode::M256Color => ColorMode::Ansi256,
+            RequestedColorMode::Grayscale => ColorMode::Grayscale,
+            RequestedColorMode::Mono => ColorMode::Mono,
+            RequestedColorMode::M8Color => ColorMode::Ansi8,
+            RequestedColorMode::Auto => ColorMode::Auto,
         }
     }
 }
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, ValueEnum)]
-enum RequestedFilterType {
-    Lanczos3,
-    Nearest,
-    Triangle,
-    CatmullRom,
-    Gaussian
+enum RequestedBrightStrategy {
+    Aixterm,
+    Bold,
 }
-impl Into<FilterType> for RequestedFilterType {
-    fn into(self) -> FilterType {
-        match self {
-            RequestedFilterType::Lanczos3 => FilterType::Lanczos3,
-            RequestedFilterType::Nearest => FilterType::Nearest,
-            RequestedFilterType::Triangle => FilterType::Triangle,
-            RequestedFilterType::CatmullRom => FilterType::CatmullRom,
-            RequestedFilterType::Gaussian => FilterType::Gaussian,
+impl From<RequestedBrightStrategy> for BrightStrategy {
+    fn from(value: RequestedBrightStrategy) -> Self {
+        match value {
+            RequestedBrightStrategy::Aixterm => BrightStrategy::Aixterm,
+            RequestedBrightStrategy::Bold => BrightStrategy::Bold,
         }
     }
 }
 
-fn main() {
-    let args = Args::parse();
-
-    let input_file = std::fs::File::open(&args.input_file).unwrap_or_else(|e| panic!("Could not open file {}: {}", args.input_file.display(), e));
-    let mut image = DynamicImage::ImageRgba8(ImageReader::new(BufReader::new(input_file))
-        .with_guessed_format().unwrap_or_else(|e| panic!("Failed to read image data from {}: {}", args.input_file.display(), e))
-        .decode().unwrap_or_else(|e| panic!("Failed to decode image data from {}: {}", args.input_file.display(), e))
-        .into_rgba8()
-    );
-
-    if let Some(resize_to_width) = args.resize_to_width {
-        let factor = resize_to_width as f32 / image.width() as f32;
-        let new_height = (image.height() as f32 * factor) as u32;
-        image = image.resize(resize_to_width, new_height, args.resize_filter.into());
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, ValueEnum)]
+enum RequestedShell {
+    Bash,
+    Posix,
+    Zsh,
+    Fish,
+    Pwsh,
+}
+impl From<RequestedShell> for Emitter {
+    fn from(value: RequestedShell) -> Self {
+        match value {
+            RequestedShell::Bash => Emitter::Bash,
+            RequestedShell::Posix => Emitter::Posix,
+            RequestedShell::Zsh => Emitter::Zsh,
+            RequestedShell::Fish => Emitter::Fish,
+            RequestedShell::Pwsh => Emitter::Pwsh,
+        }
     }
+}
 
-    let explicit_mapper: Option<&dyn Fn(&Rgba<u8>) -> Colour> = match args.color_mode {
-        RequestedColorMode::TrueColor => Some(&colormath::color_mapping_truecolor),
-        RequestedColorMode::ANSI => Some(&colormath::color_mapping_ansi),
-        RequestedColorMode::M256Color => Some(&colormath::color_mapping_256),
-        RequestedColorMode::AUTO => None
-    };
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, ValueEnum)]
+enum RequestedProtocol {
+    Cells,
+    Sixel,
+    Kitty,
+    #[value(name = "iterm2")]
+    ITerm2,
+}
+impl From<RequestedProtocol> for Protocol {
+    fn from(value: RequestedProtocol) -> Self {
+        match value {
+            RequestedProtocol::Cells => Protocol::Cells,
+            RequestedProtocol::Sixel => Protocol::Sixel,
+            RequestedProtocol::Kitty => Protocol::Kitty,
+            RequestedProtocol::ITerm2 => Protocol::ITerm2,
+        }
+    }
+}
 
-    let snippet = ImageEmittingBashSnippet { image, explicit_mapper };
-    println!("{}", snippet);
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, ValueEnum)]
+enum RequestedPassthrough {
+    Auto,
+    None,
+    Tmux,
+    Screen,
 }
 
-fn image_to_ascii(
-    image: &DynamicImage,
-    vertical_gravity: VerticalDirection,
-    color_mapper: &dyn Fn(&Rgba<u8>) -> Colour,
-) -> Vec<ANSIGenericString<'static, str>> {
-    let mut as_string: Vec<ANSIGenericString<'static, str>> = Vec::with_capacity((image.width() as usize + 1) * (image.height() as usize / 2 + 1));
-    let mut row: u32 = 0;
-    if image.height() % 2 != 0 && vertical_gravity == VerticalDirection::DOWN {
-        for col in 0..image.width() {
-            let upper_pixel = Rgba::from([0, 0, 0, 0]);
-            let lower_pixel = image.get_pixel(col, 0);
-            as_string.push(two_pixels_to_ascii_char(&upper_pixel, &lower_pixel, color_mapper));
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, ValueEnum)]
+enum RequestedOutputFormat {
+    Snippet,
+    Raw,
+}
+impl From<RequestedOutputFormat> for OutputFormat {
+    fn from(value: RequestedOutputFormat) -> Self {
+        match value {
+            RequestedOutputFormat::Snippet => OutputFormat::Snippet,
+            RequestedOutputFormat::Raw => OutputFormat::Raw,
         }
-        as_string.push(Style::default().paint("\n"));
-        row = 1;
     }
+}
 
-    loop {
-        if row + 1 >= image.height() {
-            break;
-        }
-        for col in 0..image.width() {
-            let upper_pixel = image.get_pixel(col, row);
-            let lower_pixel = image.get_pixel(col, row + 1);
-            as_string.push(two_pixels_to_ascii_char(&upper_pixel, &lower_pixel, color_mapper));
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, ValueEnum)]
+enum RequestedColorDistance {
+    Rgb,
+    Redmean,
+    Cielab76,
+    Ciede2000,
+    Oklab,
+}
+impl From<RequestedColorDistance> for ColorDistance {
+    fn from(value: RequestedColorDistance) -> Self {
+        match value {
+            RequestedColorDistance::Rgb => ColorDistance::Rgb,
+            RequestedColorDistance::Redmean => ColorDistance::Redmean,
+            RequestedColorDistance::Cielab76 => ColorDistance::Cielab76,
+            RequestedColorDistance::Ciede2000 => ColorDistance::Ciede2000,
+            RequestedColorDistance::Oklab => ColorDistance::Oklab,
         }
-        as_string.push(Style::default().paint("\n"));
-        row += 2;
     }
+}
 
-    if image.height() % 2 != 0 && vertical_gravity == VerticalDirection::UP {
-        for col in 0..image.width() {
-            let upper_pixel = image.get_pixel(col, image.height() - 1);
-            let lower_pixel = Rgba::from([0, 0, 0, 0]);
-            as_string.push(two_pixels_to_ascii_char(&upper_pixel, &lower_pixel, color_mapper));
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, ValueEnum)]
+enum RequestedDither {
+    None,
+    FloydSteinberg,
+    Atkinson,
+    Jarvis,
+    Sierra,
+    Bayer2,
+    Bayer4,
+    Bayer8,
+    BlueNoise,
+}
+impl From<RequestedDither> for Dither {
+    fn from(value: RequestedDither) -> Self {
+        match value {
+            RequestedDither::None => Dither::None,
+            RequestedDither::FloydSteinberg => Dither::FloydSteinberg,
+            RequestedDither::Atkinson => Dither::Atkinson,
+            RequestedDither::Jarvis => Dither::Jarvis,
+            RequestedDither::Sierra => Dither::Sierra,
+            RequestedDither::Bayer2 => Dither::Bayer2,
+            RequestedDither::Bayer4 => Dither::Bayer4,
+            RequestedDither::Bayer8 => Dither::Bayer8,
+            RequestedDither::BlueNoise => Dither::BlueNoise,
         }
-        as_string.push(Style::default().paint("\n"));
     }
+}
 
-    as_string
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, ValueEnum)]
+enum RequestedGlyphMode {
+    HalfBlock,
+    Quadrant,
+    Sextant,
+    Octant,
+    Braille,
+    Ascii,
 }
 
-fn is_transparent(pixel: &Rgba<u8>) -> bool {
-    pixel[3] == 0
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+enum RequestedDotThreshold {
+    Fixed(u8),
+    Dither,
 }
+impl FromStr for RequestedDotThreshold {
+    type Err = &'static str;
 
-fn two_pixels_to_ascii_char(
-    upper_pixel: &Rgba<u8>,
-    lower_pixel: &Rgba<u8>,
-    color_mapper: &dyn Fn (&Rgba<u8>) -> Colour,
-) -> ANSIGenericString<'static, str> {
-    if is_transparent(upper_pixel) && is_transparent(lower_pixel) {
-        return Style::default().paint(" ");
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        if s.eq_ignore_ascii_case("dither") {
+            return Ok(RequestedDotThreshold::Dither);
+        }
+        s.parse::<u8>()
+            .map(RequestedDotThreshold::Fixed)
+            .map_err(|_| "Invalid braille threshold, use a number from 0 to 255 or dither")
     }
-
-    if is_transparent(upper_pixel) {
-        assert!(!is_transparent(lower_pixel));
-        return color_mapper(lower_pixel).paint("▄");
+}
+impl From<RequestedDotThreshold> for DotThreshold {
+    fn from(value: RequestedDotThreshold) -> Self {
+        match value {
+            RequestedDotThreshold::Fixed(threshold) => DotThreshold::Fixed(threshold),
+            RequestedDotThreshold::Dither => DotThreshold::Dithered,
+        }
     }
+}
 
-    if is_transparent(lower_pixel) {
-        assert!(!is_transparent(upper_pixel));
-        return color_mapper(upper_pixel).paint("▀");
+fn glyph_mode_from_args(args: &Args) -> GlyphMode {
+    match args.glyphs {
+        RequestedGlyphMode::HalfBlock => GlyphMode::HalfBlock,
+        RequestedGlyphMode::Quadrant => GlyphMode::Quadrant,
+        RequestedGlyphMode::Sextant => GlyphMode::Sextant,
+        RequestedGlyphMode::Octant => GlyphMode::Octant,
+        RequestedGlyphMode::Braille => GlyphMode::Braille(args.braille_threshold.into()),
+        RequestedGlyphMode::Ascii => GlyphMode::Ascii(AsciiRamp::new(&args.ascii_ramp, args.ascii_color)),
     }
-
-    color_mapper(lower_pixel).on(color_mapper(upper_pixel)).paint("▄")
 }
 
-struct ImageEmittingBashSnippet {
-    image: DynamicImage,
-    explicit_mapper: Option<&'static dyn Fn(&Rgba<u8>) -> Colour>,
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, ValueEnum)]
+enum RequestedFilterType {
+    Lanczos3,
+    Nearest,
+    Triangle,
+    CatmullRom,
+    Gaussian
 }
-impl Display for ImageEmittingBashSnippet {
-    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
-        if let Some(mapper) = self.explicit_mapper {
-            self.emit_bash_with_color_mapper(mapper, f)
-        } else {
-            f.write_str("if [[ \"$COLORTERM\" == \"truecolor\" || \"$COLORTERM\" == \"24bit\" ]]; then\n    ")?;
-            self.emit_bash_with_color_mapper(&colormath::color_mapping_truecolor, f)?;
-            f.write_str("\nelif [[ \"$(tput colors)\" == \"256\" ]]; then \n    ")?;
-            self.emit_bash_with_color_mapper(&colormath::color_mapping_256, f)?;
-            f.write_str("\nelse\n    ")?;
-            self.emit_bash_with_color_mapper(&colormath::color_mapping_ansi, f)?;
-            f.write_str("\nfi\n")
+impl From<RequestedFilterType> for FilterType {
+    fn from(value: RequestedFilterType) -> Self {
+        match value {
+            RequestedFilterType::Lanczos3 => FilterType::Lanczos3,
+            RequestedFilterType::Nearest => FilterType::Nearest,
+            RequestedFilterType::Triangle => FilterType::Triangle,
+            RequestedFilterType::CatmullRom => FilterType::CatmullRom,
+            RequestedFilterType::Gaussian => FilterType::Gaussian,
         }
     }
 }
-impl ImageEmittingBashSnippet {
-    fn emit_bash_with_color_mapper(&self, mapper: &dyn Fn(&Rgba<u8>) -> Colour, f: &mut Formatter) -> std::fmt::Result {
-        f.write_str("echo -e -n \"")?;
-        let string_content = capture_to_string(&|f| {
-            bash_syntax::write_with_minimal_control_sequences(
-                image_to_ascii(&self.image, VerticalDirection::UP, mapper),
-                f,
-            )
-        });
-        f.write_str(escape_for_string_content(&string_content).as_str())?;
-        f.write_str("\"")
+
+fn main() -> ExitCode {
+    let args = Args::parse();
+
+    match run(args) {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(e) => {
+            eprintln!("gaudi: {}", e);
+            ExitCode::from(e.exit_code() as u8)
+        }
     }
 }
 
-fn capture_to_string(formats: &dyn Fn(&mut Formatter) -> std::fmt::Result) -> String {
-    let displayable = Displayable {
-        formats,
+fn run(args: Args) -> Result<(), GaudiError> {
+    let mut frames = gaudi::load_frames(&args.input_file)?;
+    let renderer = match frames.len() {
+        1 => Renderer::new(frames.remove(0).image),
+        _ => Renderer::animated(frames),
     };
 
-    format!("{}", displayable)
-}
+    let snippet = renderer
+        .color_mode(args.color_mode.into())
+        .color_distance(args.color_distance.into())
+        .bright_strategy(args.bright_strategy.into())
+        .palette(palette_from_args(&args)?)
+        .ansi256_palette(match &args.palette_256 {
+            Some(path) => gaudi::load_ansi256_palette(path)?,
+            None => Ansi256Palette::default(),
+        })
+        .dither(args.dither.into())
+        .glyph_mode(glyph_mode_from_args(&args))
+        .alignment(alignment_from_args(&args))
+        .resize_to_width(args.resize_to_width)
+        .fit(fit_from_args(&args)?)
+        .cell_size(gaudi::terminal::cell_size())
+        .protocol(args.protocol.into())
+        .passthrough(passthrough_from_args(&args))
+        .snippet_widths(args.fit_widths.clone())
+        .resize_filter(args.resize_filter.into())
+        .emitter(args.shell.into())
+        .output_format(args.output_format.into())
+        .reset_at_end(args.reset)
+        .repeat(args.repeat.into())
+        .render()?;
 
-struct Displayable<'a> {
-    formats: &'a dyn Fn(&mut Formatter) -> std::fmt::Result,
+    let written = match args.output_format {
+        // the escapes end with a line break of their own
+        RequestedOutputFormat::Raw => write!(std::io::stdout(), "{}", snippet),
+        RequestedOutputFormat::Snippet => writeln!(std::io::stdout(), "{}", snippet),
+    };
+    written.map_err(|source| GaudiError::Io {
+        context: "could not write to stdout".to_string(),
+        source,
+    })
 }
-impl Display for Displayable<'_> {
-    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
-        (self.formats)(f)
-    }
-}
\ No newline at end of file
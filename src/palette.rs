@@ -0,0 +1,551 @@
+use std::io::ErrorKind;
+use std::path::Path;
+use crate::colormath::ANSI_COLOR_TO_TRUECOLOR;
+use crate::error::GaudiError;
+
+/// The RGB values a terminal shows for the 16 ANSI colours, in the order black, red, green,
+/// yellow, blue, magenta, cyan, white, followed by the bright variants in the same order.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct TerminalPalette {
+    pub colors: [(u8, u8, u8); 16],
+}
+
+impl Default for TerminalPalette {
+    fn default() -> Self {
+        TerminalPalette::XTERM
+    }
+}
+
+impl TerminalPalette {
+    pub const XTERM: TerminalPalette = TerminalPalette {
+        colors: [
+            (0, 0, 0), (205, 0, 0), (0, 205, 0), (205, 205, 0),
+            (0, 0, 238), (205, 0, 205), (0, 205, 205), (229, 229, 229),
+            (127, 127, 127), (255, 0, 0), (0, 255, 0), (255, 255, 0),
+            (92, 92, 255), (255, 0, 255), (0, 255, 255), (255, 255, 255),
+        ],
+    };
+
+    /// The names accepted by [TerminalPalette::builtin]
+    pub const BUILTIN_NAMES: [&'static str; 7] = ["xterm", "vga", "tango", "solarized", "gruvbox-dark", "gruvbox-light", "dracula"];
+
+    pub fn builtin(name: &str) -> Option<TerminalPalette> {
+        let hex = match name {
+            "xterm" => return Some(TerminalPalette::XTERM),
+            "vga" => [
+                0x000000, 0xaa0000, 0x00aa00, 0xaa5500, 0x0000aa, 0xaa00aa, 0x00aaaa, 0xaaaaaa,
+                0x555555, 0xff5555, 0x55ff55, 0xffff55, 0x5555ff, 0xff55ff, 0x55ffff, 0xffffff,
+            ],
+            "tango" => [
+                0x000000, 0xcc0000, 0x4e9a06, 0xc4a000, 0x3465a4, 0x75507b, 0x06989a, 0xd3d7cf,
+                0x555753, 0xef2929, 0x8ae234, 0xfce94f, 0x729fcf, 0xad7fa8, 0x34e2e2, 0xeeeeec,
+            ],
+            // the same for the dark and the light variant, only the default colours differ
+            "solarized" => [
+                0x073642, 0xdc322f, 0x859900, 0xb58900, 0x268bd2, 0xd33682, 0x2aa198, 0xeee8d5,
+                0x002b36, 0xcb4b16, 0x586e75, 0x657b83, 0x839496, 0x6c71c4, 0x93a1a1, 0xfdf6e3,
+            ],
+            "gruvbox-dark" => [
+                0x282828, 0xcc241d, 0x98971a, 0xd79921, 0x458588, 0xb16286, 0x689d6a, 0xa89984,
+                0x928374, 0xfb4934, 0xb8bb26, 0xfabd2f, 0x83a598, 0xd3869b, 0x8ec07c, 0xebdbb2,
+            ],
+            "gruvbox-light" => [
+                0xfbf1c7, 0xcc241d, 0x98971a, 0xd79921, 0x458588, 0xb16286, 0x689d6a, 0x7c6f64,
+                0x928374, 0x9d0006, 0x79740e, 0xb57614, 0x076678, 0x8f3f71, 0x427b58, 0x3c3836,
+            ],
+            "dracula" => [
+                0x21222c, 0xff5555, 0x50fa7b, 0xf1fa8c, 0xbd93f9, 0xff79c6, 0x8be9fd, 0xf8f8f2,
+                0x6272a4, 0xff6e6e, 0x69ff94, 0xffffa5, 0xd6acff, 0xff92df, 0xa4ffff, 0xffffff,
+            ],
+            _ => return None,
+        };
+
+        Some(TerminalPalette {
+            colors: hex.map(|rgb: u32| ((rgb >> 16) as u8, (rgb >> 8) as u8, rgb as u8)),
+        })
+    }
+}
+
+/// The RGB values a terminal shows for the colours of the 256 colour mode, for terminals that
+/// redefine them through OSC 4.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Ansi256Palette {
+    pub colors: [(u8, u8, u8); 256],
+}
+
+impl Default for Ansi256Palette {
+    fn default() -> Self {
+        Ansi256Palette { colors: ANSI_COLOR_TO_TRUECOLOR }
+    }
+}
+
+/// (index, rgb) pairs, in the order a file defines them
+type PaletteEntries = Vec<(usize, (u8, u8, u8))>;
+
+/// The configuration formats [load_palette] understands
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum PaletteFormat {
+    /// iTerm2 colour presets, an XML property list
+    ITerm2,
+    /// `*.color0: #rrggbb` lines, with `#define` substitution
+    Xresources,
+    /// The `[colors.normal]` and `[colors.bright]` tables of alacritty.toml, or the equivalent
+    /// sections of the older alacritty.yml
+    Alacritty,
+    /// `color0 #rrggbb` lines of kitty.conf
+    Kitty,
+}
+
+/// Reads the 16 colours from a terminal configuration file. Colours the file does not define
+/// keep their xterm values.
+pub fn load_palette(path: &Path) -> Result<TerminalPalette, GaudiError> {
+    let content = std::fs::read_to_string(path).map_err(|source| {
+        if source.kind() == ErrorKind::NotFound {
+            GaudiError::invalid_option("palette", format!(
+                "{} is neither a file nor one of {}",
+                path.display(),
+                TerminalPalette::BUILTIN_NAMES.join(", "),
+            ))
+        } else {
+            GaudiError::Io { context: format!("could not read {}", path.display()), source }
+        }
+    })?;
+
+    let entries = match detect_format(path, &content) {
+        PaletteFormat::ITerm2 => parse_itermcolors(&content),
+        PaletteFormat::Xresources => parse_xresources(&content),
+        PaletteFormat::Alacritty => parse_alacritty(&content),
+        PaletteFormat::Kitty => parse_kitty(&content),
+    };
+
+    let mut palette = TerminalPalette::XTERM;
+    let mut found_any = false;
+    for (index, rgb) in entries {
+        if index < 16 {
+            palette.colors[index] = rgb;
+            found_any = true;
+        }
+    }
+    if !found_any {
+        return Err(GaudiError::invalid_option("palette", format!("{}: no ANSI colours found", path.display())));
+    }
+
+    Ok(palette)
+}
+
+/// Reads a 256 colour palette from a JSON object of `"index": "#rrggbb"` pairs or array of
+/// `"#rrggbb"` strings, a GIMP `.gpl` palette or a `.hex` list with one colour per line, with or
+/// without a `#` or `0x` in front. Lists may
+/// be shorter than 256 entries; the colours they do not define keep their default values.
+pub fn load_ansi256_palette(path: &Path) -> Result<Ansi256Palette, GaudiError> {
+    let content = std::fs::read_to_string(path).map_err(|source| GaudiError::Io {
+        context: format!("could not read {}", path.display()),
+        source,
+    })?;
+    let invalid = |message: String| GaudiError::invalid_option("palette-256", format!("{}: {}", path.display(), message));
+
+    let extension = path.extension().and_then(|e| e.to_str()).map(|e| e.to_lowercase());
+    let trimmed = content.trim_start();
+    let entries = match extension.as_deref() {
+        Some("json") => parse_json_table(&content),
+        Some("gpl") => parse_gpl(&content),
+        Some("hex") => parse_hex_list(&content),
+        _ if trimmed.starts_with('{') || trimmed.starts_with('[') => parse_json_table(&content),
+        _ if trimmed.starts_with("GIMP Palette") => parse_gpl(&content),
+        _ => parse_hex_list(&content),
+    }.map_err(invalid)?;
+
+    if entries.is_empty() {
+        return Err(invalid("no colours found".to_string()));
+    }
+    let mut palette = Ansi256Palette::default();
+    for (index, rgb) in entries {
+        *palette.colors.get_mut(index).ok_or_else(|| invalid(format!("index {} is out of range", index)))? = rgb;
+    }
+
+    Ok(palette)
+}
+
+/// Understands just the subset of JSON that palettes are written in: string keys and values
+/// without escapes
+fn parse_json_table(content: &str) -> Result<PaletteEntries, String> {
+    let content = content.trim();
+    let is_object = content.starts_with('{') && content.ends_with('}');
+    let is_array = content.starts_with('[') && content.ends_with(']');
+    if !is_object && !is_array {
+        return Err("expected a JSON object or array".to_string());
+    }
+
+    let strings: Vec<&str> = content.split('"').skip(1).step_by(2).collect();
+    let parse_value = |value: &str| parse_color(value).ok_or_else(|| format!("invalid colour {:?}", value));
+    if is_array {
+        return strings.iter().enumerate().map(|(index, value)| Ok((index, parse_value(value)?))).collect();
+    }
+
+    strings.chunks(2)
+        .map(|pair| {
+            let [key, value] = pair else {
+                return Err(format!("{:?} has no value", pair[0]));
+            };
+            let index = key.parse().map_err(|_| format!("invalid index {:?}", key))?;
+            Ok((index, parse_value(value)?))
+        })
+        .collect()
+}
+
+fn parse_gpl(content: &str) -> Result<PaletteEntries, String> {
+    let mut entries = Vec::new();
+    for (number, line) in content.lines().enumerate() {
+        let line = line.trim();
+        let is_header = number == 0 || line.starts_with("Name:") || line.starts_with("Columns:");
+        if is_header || line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+
+        let mut channels = line.split_whitespace().take(3).map(|channel| channel.parse::<u8>().ok());
+        match (channels.next().flatten(), channels.next().flatten(), channels.next().flatten()) {
+            (Some(r), Some(g), Some(b)) => entries.push((entries.len(), (r, g, b))),
+            _ => return Err(format!("line {}: expected red, green and blue values from 0 to 255", number + 1)),
+        }
+    }
+
+    Ok(entries)
+}
+
+fn parse_hex_list(content: &str) -> Result<PaletteEntries, String> {
+    content.lines()
+        .map(str::trim)
+        .enumerate()
+        .filter(|(_, line)| !line.is_empty())
+        .enumerate()
+        .map(|(index, (number, line))| {
+            let digits = line.strip_prefix("0x").or_else(|| line.strip_prefix('#')).unwrap_or(line);
+            let rgb = parse_color(&format!("#{}", digits))
+                .ok_or_else(|| format!("line {}: invalid colour {:?}", number + 1, line))?;
+            Ok((index, rgb))
+        })
+        .collect()
+}
+
+fn detect_format(path: &Path, content: &str) -> PaletteFormat {
+    let extension = path.extension().and_then(|e| e.to_str()).map(|e| e.to_lowercase());
+    match extension.as_deref() {
+        Some("itermcolors") => return PaletteFormat::ITerm2,
+        Some("toml" | "yml" | "yaml") => return PaletteFormat::Alacritty,
+        Some("conf") => return PaletteFormat::Kitty,
+        _ => {}
+    }
+
+    if content.contains("<plist") {
+        PaletteFormat::ITerm2
+    } else if content.contains("[colors.") || content.lines().any(|l| matches!(l.trim(), "normal:" | "bright:")) {
+        PaletteFormat::Alacritty
+    } else if content.lines().any(|l| xresources_color_index(l.split_once(':').map_or("", |(key, _)| key)).is_some()) {
+        PaletteFormat::Xresources
+    } else {
+        PaletteFormat::Kitty
+    }
+}
+
+/// Accepts `#rgb`, `#rrggbb`, `0xrrggbb` and X11's `rgb:r/g/b` with 1 to 4 digits per channel,
+/// optionally in quotes
+fn parse_color(value: &str) -> Option<(u8, u8, u8)> {
+    let value = value.trim().trim_matches(|c| c == '"' || c == '\'');
+    let hex = value.strip_prefix('#').or_else(|| value.strip_prefix("0x"));
+    if let Some(hex) = hex {
+        let digits = |range: std::ops::Range<usize>| u8::from_str_radix(hex.get(range)?, 16).ok();
+        return match hex.len() {
+            3 => Some((digits(0..1)? * 17, digits(1..2)? * 17, digits(2..3)? * 17)),
+            6 => Some((digits(0..2)?, digits(2..4)?, digits(4..6)?)),
+            _ => None,
+        };
+    }
+
+    let mut channels = value.strip_prefix("rgb:")?.split('/').map(|channel| {
+        if channel.is_empty() || channel.len() > 4 {
+            return None;
+        }
+        let max = (1u32 << (4 * channel.len())) - 1;
+        let level = u32::from_str_radix(channel, 16).ok()?;
+        Some(((level * 255 + max / 2) / max) as u8)
+    });
+    let rgb = (channels.next()??, channels.next()??, channels.next()??);
+    channels.next().is_none().then_some(rgb)
+}
+
+fn parse_itermcolors(content: &str) -> PaletteEntries {
+    let mut entries = Vec::new();
+    let mut rest = content;
+    while let Some(start) = rest.find("<key>Ansi ") {
+        rest = &rest[start + "<key>Ansi ".len()..];
+        let Some((index, after_key)) = rest.split_once(" Color</key>") else {
+            continue;
+        };
+        let Some(dict) = after_key.split_once("</dict>").map(|(dict, _)| dict) else {
+            break;
+        };
+        let component = |name: &str| -> Option<u8> {
+            let after = dict.split_once(&format!("<key>{} Component</key>", name))?.1;
+            let value = after.split_once("<real>")?.1.split_once("</real>")?.0;
+            let value: f32 = value.trim().parse().ok()?;
+            Some((value.clamp(0.0, 1.0) * 255.0).round() as u8)
+        };
+        if let (Ok(index), Some(r), Some(g), Some(b)) = (index.trim().parse(), component("Red"), component("Green"), component("Blue")) {
+            entries.push((index, (r, g, b)));
+        }
+    }
+
+    entries
+}
+
+/// The index in `URxvt.color12`, `*color12` or `*.color12`
+fn xresources_color_index(key: &str) -> Option<usize> {
+    let name = key.trim().rsplit(['*', '.']).next()?;
+    name.strip_prefix("color")?.parse().ok()
+}
+
+fn parse_xresources(content: &str) -> PaletteEntries {
+    let mut defines: Vec<(&str, &str)> = Vec::new();
+    let mut entries = Vec::new();
+    for line in content.lines().map(str::trim) {
+        if line.starts_with('!') {
+            continue;
+        }
+        if let Some(define) = line.strip_prefix("#define") {
+            if let Some((name, value)) = define.trim().split_once(char::is_whitespace) {
+                defines.push((name, value.trim()));
+            }
+            continue;
+        }
+        let Some((key, value)) = line.split_once(':') else {
+            continue;
+        };
+        let Some(index) = xresources_color_index(key) else {
+            continue;
+        };
+        let value = value.trim();
+        let value = defines.iter().rev().find(|(name, _)| *name == value).map_or(value, |(_, v)| v);
+        if let Some(rgb) = parse_color(value) {
+            entries.push((index, rgb));
+        }
+    }
+
+    entries
+}
+
+fn parse_alacritty(content: &str) -> PaletteEntries {
+    const NAMES: [&str; 8] = ["black", "red", "green", "yellow", "blue", "magenta", "cyan", "white"];
+
+    // the offset of the section the current line is in, 0 for normal and 8 for bright colours
+    let mut section: Option<usize> = None;
+    let mut entries = Vec::new();
+    for line in content.lines() {
+        let line = line.split(" #").next().unwrap_or_default().trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+
+        // TOML table headers, or YAML keys that open a mapping
+        if line.starts_with('[') || line.ends_with(':') {
+            section = match line.trim_matches(['[', ']', ':']).trim() {
+                "colors.normal" | "normal" => Some(0),
+                "colors.bright" | "bright" => Some(8),
+                _ => None,
+            };
+            continue;
+        }
+
+        let Some(offset) = section else {
+            continue;
+        };
+        let Some((key, value)) = line.split_once(['=', ':']) else {
+            continue;
+        };
+        if let (Some(index), Some(rgb)) = (NAMES.iter().position(|name| *name == key.trim()), parse_color(value)) {
+            entries.push((offset + index, rgb));
+        }
+    }
+
+    entries
+}
+
+fn parse_kitty(content: &str) -> PaletteEntries {
+    content.lines()
+        .map(str::trim)
+        .filter(|line| !line.starts_with('#'))
+        .filter_map(|line| {
+            let (key, value) = line.split_once(char::is_whitespace)?;
+            let index = key.strip_prefix("color")?.parse().ok()?;
+            Some((index, parse_color(value)?))
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Has `load` read `content` from a file called `name`, which is removed again afterwards
+    fn loaded<T>(name: &str, content: &str, load: fn(&Path) -> Result<T, GaudiError>) -> Result<T, GaudiError> {
+        let path = std::env::temp_dir().join(format!("gaudi-palette-{}-{}", std::process::id(), name));
+        std::fs::write(&path, content).unwrap();
+        let result = load(&path);
+        std::fs::remove_file(&path).unwrap();
+        result
+    }
+
+    fn assert_invalid_option(result: Result<impl std::fmt::Debug, GaudiError>) {
+        let error = result.expect_err("malformed input should be rejected");
+        assert_eq!(error.exit_code(), GaudiError::EXIT_CODE_INVALID_OPTION, "{}", error);
+    }
+
+    #[test]
+    fn builtin_themes_are_all_known() {
+        for name in TerminalPalette::BUILTIN_NAMES {
+            assert!(TerminalPalette::builtin(name).is_some(), "{}", name);
+        }
+        assert_eq!(TerminalPalette::builtin("xterm"), Some(TerminalPalette::XTERM));
+        assert_eq!(TerminalPalette::builtin("nonexistent"), None);
+    }
+
+    #[test]
+    fn builtin_themes_decode_the_hex_values() {
+        let vga = TerminalPalette::builtin("vga").unwrap();
+        assert_eq!(vga.colors[0], (0, 0, 0));
+        assert_eq!(vga.colors[3], (0xaa, 0x55, 0x00));
+        assert_eq!(vga.colors[15], (0xff, 0xff, 0xff));
+        let dracula = TerminalPalette::builtin("dracula").unwrap();
+        assert_eq!(dracula.colors[0], (0x21, 0x22, 0x2c));
+    }
+
+    #[test]
+    fn parses_colour_notations() {
+        assert_eq!(parse_color("#abc"), Some((0xaa, 0xbb, 0xcc)));
+        assert_eq!(parse_color("'#0a0b0c'"), Some((0x0a, 0x0b, 0x0c)));
+        assert_eq!(parse_color("\"0x102030\""), Some((0x10, 0x20, 0x30)));
+        assert_eq!(parse_color("rgb:ff/80/0"), Some((255, 128, 0)));
+        assert_eq!(parse_color("rgb:ffff/0000/8080"), Some((255, 0, 128)));
+        for malformed in ["", "#12345", "#ggg", "rgb:1/2", "rgb:1/2/3/4", "rgb:12345/0/0", "red"] {
+            assert_eq!(parse_color(malformed), None, "{:?}", malformed);
+        }
+    }
+
+    #[test]
+    fn loads_itermcolors() {
+        let palette = loaded("theme.itermcolors", r#"<?xml version="1.0" encoding="UTF-8"?>
+<plist version="1.0">
+<dict>
+    <key>Ansi 1 Color</key>
+    <dict>
+        <key>Blue Component</key>
+        <real>0.0</real>
+        <key>Green Component</key>
+        <real>0.5</real>
+        <key>Red Component</key>
+        <real>1</real>
+    </dict>
+    <key>Ansi 12 Color</key>
+    <dict>
+        <key>Red Component</key>
+        <real>0</real>
+        <key>Green Component</key>
+        <real>0</real>
+        <key>Blue Component</key>
+        <real>1</real>
+    </dict>
+</dict>
+</plist>
+"#, load_palette).unwrap();
+        assert_eq!(palette.colors[1], (255, 128, 0));
+        assert_eq!(palette.colors[12], (0, 0, 255));
+        assert_eq!(palette.colors[0], TerminalPalette::XTERM.colors[0]);
+    }
+
+    #[test]
+    fn loads_xresources() {
+        let palette = loaded("Xresources", "! comment\n#define red #ff0000\n*.color1: red\nURxvt.color9: rgb:80/80/80\n*color15:#fff\n", load_palette).unwrap();
+        assert_eq!(palette.colors[1], (255, 0, 0));
+        assert_eq!(palette.colors[9], (128, 128, 128));
+        assert_eq!(palette.colors[15], (255, 255, 255));
+    }
+
+    #[test]
+    fn loads_alacritty_toml_and_yaml() {
+        let palette = loaded("alacritty.toml", "[colors.primary]\nforeground = '#ffffff'\n\n[colors.normal]\nred = '#cc0000' # comment\n\n[colors.bright]\nblue = \"0x0000ff\"\n", load_palette).unwrap();
+        assert_eq!(palette.colors[1], (0xcc, 0, 0));
+        assert_eq!(palette.colors[12], (0, 0, 0xff));
+        assert_eq!(palette.colors[7], TerminalPalette::XTERM.colors[7]);
+
+        let palette = loaded("alacritty.yml", "colors:\n  normal:\n    green: '#00cc00'\n  bright:\n    white: '#eeeeee'\n", load_palette).unwrap();
+        assert_eq!(palette.colors[2], (0, 0xcc, 0));
+        assert_eq!(palette.colors[15], (0xee, 0xee, 0xee));
+    }
+
+    #[test]
+    fn loads_kitty_conf() {
+        let palette = loaded("kitty.conf", "# theme\nforeground #ffffff\ncolor3 #aabb00\ncolor11   #ffff00\ncolor200 #123456\n", load_palette).unwrap();
+        assert_eq!(palette.colors[3], (0xaa, 0xbb, 0));
+        assert_eq!(palette.colors[11], (0xff, 0xff, 0));
+    }
+
+    #[test]
+    fn rejects_configurations_without_colours() {
+        for (name, content) in [
+            ("empty.conf", ""),
+            ("garbage.itermcolors", "<plist><key>Ansi 1 Color</key><dict><key>Red Component</key><real>x</real>"),
+            ("broken.toml", "[colors.normal]\nred = 'not a colour'\n"),
+            ("broken.Xresources", "*.color1: #12"),
+            ("binary.conf", "\u{0}\u{1}color\u{7f}"),
+        ] {
+            assert_invalid_option(loaded(name, content, load_palette));
+        }
+    }
+
+    #[test]
+    fn rejects_missing_palette_files_as_invalid_option() {
+        assert_invalid_option(load_palette(Path::new("/nonexistent/gaudi/palette")));
+    }
+
+    #[test]
+    fn loads_json_objects_and_arrays() {
+        let palette = loaded("table.json", r##"{ "1": "#ff0000", "255": "0x010203" }"##, load_ansi256_palette).unwrap();
+        assert_eq!(palette.colors[1], (255, 0, 0));
+        assert_eq!(palette.colors[255], (1, 2, 3));
+        assert_eq!(palette.colors[2], Ansi256Palette::default().colors[2]);
+
+        let palette = loaded("list", r##"["#000001", "#000002"]"##, load_ansi256_palette).unwrap();
+        assert_eq!(&palette.colors[..2], [(0, 0, 1), (0, 0, 2)]);
+    }
+
+    #[test]
+    fn loads_gpl() {
+        let content = "GIMP Palette\nName: test\nColumns: 16\n# comment\n255   0   0\tred\n  0 128 255\n";
+        let palette = loaded("test.gpl", content, load_ansi256_palette).unwrap();
+        assert_eq!(&palette.colors[..2], [(255, 0, 0), (0, 128, 255)]);
+    }
+
+    #[test]
+    fn loads_hex_lists_with_any_prefix() {
+        let palette = loaded("test.hex", "ff0000\n\n#00ff00\n0x0000ff\nabc\n", load_ansi256_palette).unwrap();
+        assert_eq!(&palette.colors[..4], [(255, 0, 0), (0, 255, 0), (0, 0, 255), (0xaa, 0xbb, 0xcc)]);
+    }
+
+    #[test]
+    fn rejects_malformed_256_colour_palettes() {
+        for (name, content) in [
+            ("empty.hex", ""),
+            ("broken.hex", "ff0000\nnot a colour\n"),
+            ("prefixes.hex", "0x#ff0000\n"),
+            ("broken.gpl", "GIMP Palette\n255 0\n"),
+            ("overflow.gpl", "GIMP Palette\n256 0 0\n"),
+            ("unclosed.json", "{ \"1\": \"#ff0000\""),
+            ("no-value.json", "{ \"1\" }"),
+            ("bad-index.json", "{ \"one\": \"#ff0000\" }"),
+            ("out-of-range.json", "{ \"256\": \"#ff0000\" }"),
+            ("bad-colour.json", "[\"#ff00\"]"),
+            ("too-long.hex", &"000000\n".repeat(257)),
+        ] {
+            assert_invalid_option(loaded(name, content, load_ansi256_palette));
+        }
+    }
+}
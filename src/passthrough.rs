@@ -0,0 +1,27 @@
+/// The longest string screen passes on in one DCS
+pub const SCREEN_CHUNK_SIZE: usize = 768;
+
+/// Wraps the escape sequences into a DCS that tmux passes on to the terminal it runs in, which
+/// takes `set -g allow-passthrough on` from tmux 3.3 on. The ESCs within are doubled.
+pub fn wrap_for_tmux(escapes: &str) -> String {
+    format!("\x1bPtmux;{}\x1b\\", escapes.replace('\x1b', "\x1b\x1b"))
+}
+
+/// Wraps the escape sequences into DCSs that screen passes on to the terminal it runs in, split
+/// into pieces of at most [SCREEN_CHUNK_SIZE] bytes. The escapes must not contain a string
+/// terminator (ST), as it would end the DCS early.
+pub fn wrap_for_screen(escapes: &str) -> String {
+    let mut out = String::with_capacity(escapes.len() + escapes.len() / SCREEN_CHUNK_SIZE * 4 + 4);
+    let mut rest = escapes;
+    while !rest.is_empty() {
+        let mut end = rest.len().min(SCREEN_CHUNK_SIZE);
+        while !rest.is_char_boundary(end) {
+            end -= 1;
+        }
+        out.push_str("\x1bP");
+        out.push_str(&rest[..end]);
+        out.push_str("\x1b\\");
+        rest = &rest[end..];
+    }
+    out
+}
@@ -0,0 +1,125 @@
+use std::fmt::Formatter;
+use std::time::Duration;
+use crate::animation::Repeat;
+use crate::shell_syntax::{mode_chain, write_case_dispatch, write_chain, write_sh_animation, write_sh_terminal_dispatch, ShellSyntax, TerminalCheck};
+
+/// The POSIX sh equivalent of [crate::bash_syntax::AUTO_DETECTION_PREAMBLE], with `test` and `case`
+/// instead of `[[ ]]`, so that it runs under dash and busybox sh as well.
+pub const AUTO_DETECTION_PREAMBLE: &str = r#"gaudi_mode=ansi
+if [ -n "${NO_COLOR-}" ] || [ "${TERM-}" = "dumb" ] || [ ! -t 1 ]; then
+    gaudi_mode=plain
+elif [ "${COLORTERM-}" = "truecolor" ] || [ "${COLORTERM-}" = "24bit" ]; then
+    gaudi_mode=truecolor
+else
+    case "${TERM-}" in
+        *-direct) gaudi_mode=truecolor ;;
+        linux) gaudi_mode=8 ;;
+        *)
+            if command -v tput >/dev/null 2>&1; then
+                gaudi_colors="$(tput colors 2>/dev/null)"
+            else
+                case "${TERM-}" in
+                    *-256color) gaudi_colors=256 ;;
+                    *) gaudi_colors=8 ;;
+                esac
+            fi
+            case "$gaudi_colors" in
+                ''|*[!0-9]*) gaudi_mode=mono ;;
+                *)
+                    if [ "$gaudi_colors" -ge 16777216 ]; then
+                        gaudi_mode=truecolor
+                    elif [ "$gaudi_colors" -ge 256 ]; then
+                        gaudi_mode=256
+                    elif [ "$gaudi_colors" -lt 8 ]; then
+                        gaudi_mode=mono
+                    fi
+                    ;;
+            esac
+            ;;
+    esac
+fi
+"#;
+
+/// The POSIX sh equivalent of [crate::bash_syntax::WIDTH_DETECTION_PREAMBLE].
+pub const WIDTH_DETECTION_PREAMBLE: &str = r#"gaudi_columns="$(tput cols 2>/dev/null)"
+case "$gaudi_columns" in
+    ''|*[!0-9]*) gaudi_columns="${COLUMNS:-80}" ;;
+esac
+"#;
+
+/// Sets `$gaudi_da1` to the primary device attributes the terminal answers with, or leaves it
+/// empty if stdout is no terminal or the terminal doesn't answer within a second. Only needs
+/// `stty` and `dd`, so it works in bash and zsh as well.
+pub const DEVICE_ATTRIBUTES_QUERY: &str = r#"gaudi_da1=
+if [ -t 1 ] && gaudi_stty="$(stty -g </dev/tty 2>/dev/null)"; then
+    stty -echo -icanon min 0 time 10 </dev/tty
+    printf '\033[c' >/dev/tty
+    gaudi_da1="$(dd bs=64 count=1 </dev/tty 2>/dev/null)"
+    stty "$gaudi_stty" </dev/tty
+fi
+"#;
+
+/// Escapes `payload` for the format string of `printf`, in single quotes. Everything outside of
+/// printable ASCII is written as octal escapes of its UTF-8 bytes, as POSIX printf knows neither
+/// `\e` nor `\u`.
+pub fn escape_for_printf_format(payload: &str) -> String {
+    let mut out = String::with_capacity(payload.len());
+
+    for byte in payload.bytes() {
+        match byte {
+            b'%' => out.push_str("%%"),
+            b'\\' => out.push_str("\\\\"),
+            b'\n' => out.push_str("\\n"),
+            // can't be escaped inside single quotes
+            b'\'' => out.push_str("\\047"),
+            b' '..=b'~' => out.push(byte as char),
+            _ => out.push_str(&format!("\\{:03o}", byte)),
+        }
+    }
+
+    out
+}
+
+pub struct PosixSyntax;
+impl ShellSyntax for PosixSyntax {
+    fn write_print(&self, escapes: &str, f: &mut Formatter) -> std::fmt::Result {
+        write!(f, "printf '{}'", escape_for_printf_format(escapes))
+    }
+
+    fn auto_detection_preamble(&self) -> &'static str {
+        AUTO_DETECTION_PREAMBLE
+    }
+
+    fn write_dispatch(&self, variants: &[(&str, String)], fallback: &str, f: &mut Formatter) -> std::fmt::Result {
+        write_case_dispatch(self, variants, fallback, f)
+    }
+
+    fn write_snippet_dispatch(&self, variants: &[(&str, String)], fallback: &str, f: &mut Formatter) -> std::fmt::Result {
+        write_chain(
+            &mode_chain(variants, fallback),
+            ["if {}; then", "elif {}; then", "else", "fi"],
+            &|mode| format!("[ \"$gaudi_mode\" = \"{}\" ]", mode),
+            f,
+        )?;
+        f.write_str("unset gaudi_mode gaudi_colors\n")
+    }
+
+    fn write_width_dispatch(&self, variants: &[(u32, String)], f: &mut Formatter) -> std::fmt::Result {
+        f.write_str(WIDTH_DETECTION_PREAMBLE)?;
+        write_chain(
+            variants,
+            ["if {}; then", "elif {}; then", "else", "fi"],
+            &|columns| format!("[ \"$gaudi_columns\" -ge {} ]", columns),
+            f,
+        )?;
+        f.write_str("unset gaudi_columns\n")
+    }
+
+    fn write_terminal_dispatch(&self, check: TerminalCheck, escapes: &str, fallback: &str, f: &mut Formatter) -> std::fmt::Result {
+        write_sh_terminal_dispatch(self, check, escapes, fallback, f)
+    }
+
+    fn write_animation(&self, start: &str, frames: &[(String, Duration)], end: &str, repeat: Repeat, f: &mut Formatter) -> std::fmt::Result {
+        write_sh_animation(self, "trap", start, frames, end, repeat, f)
+    }
+}
@@ -0,0 +1,175 @@
+use std::fmt::Formatter;
+use std::time::Duration;
+use crate::animation::Repeat;
+use crate::shell_syntax::{mode_chain, write_chain, ShellSyntax, TerminalCheck};
+
+/// The PowerShell equivalent of [crate::bash_syntax::AUTO_DETECTION_PREAMBLE]. On top of the
+/// checks of the other shells, Windows consoles without virtual terminal sequences get `plain`,
+/// and Windows Terminal and other Windows consoles get truecolor.
+pub const AUTO_DETECTION_PREAMBLE: &str = r#"$gaudi_mode = 'ansi'
+if ($env:NO_COLOR -or ($env:TERM -eq 'dumb') -or [Console]::IsOutputRedirected -or ($IsWindows -and -not $Host.UI.SupportsVirtualTerminal)) {
+    $gaudi_mode = 'plain'
+} elseif (($env:COLORTERM -in @('truecolor', '24bit')) -or ($env:TERM -like '*-direct') -or $env:WT_SESSION) {
+    $gaudi_mode = 'truecolor'
+} elseif ($env:TERM -eq 'linux') {
+    $gaudi_mode = '8'
+} elseif ($IsWindows -and -not $env:TERM) {
+    $gaudi_mode = 'truecolor'
+} else {
+    if (Get-Command tput -CommandType Application -ErrorAction Ignore) {
+        $gaudi_colors = tput colors 2>$null
+    } elseif ($env:TERM -like '*-256color') {
+        $gaudi_colors = '256'
+    } else {
+        $gaudi_colors = '8'
+    }
+    if ("$gaudi_colors" -match '^[0-9]+$') {
+        if ([long]"$gaudi_colors" -ge 16777216) {
+            $gaudi_mode = 'truecolor'
+        } elseif ([long]"$gaudi_colors" -ge 256) {
+            $gaudi_mode = '256'
+        } elseif ([long]"$gaudi_colors" -lt 8) {
+            $gaudi_mode = 'mono'
+        }
+    } else {
+        $gaudi_mode = 'mono'
+    }
+}
+"#;
+
+/// The PowerShell equivalent of [crate::bash_syntax::WIDTH_DETECTION_PREAMBLE], which asks the
+/// console rather than tput.
+pub const WIDTH_DETECTION_PREAMBLE: &str = r#"$gaudi_columns = try { [Console]::WindowWidth } catch { 0 }
+if (-not $gaudi_columns) {
+    $gaudi_columns = 80
+}
+"#;
+
+/// The PowerShell equivalent of [crate::posix_syntax::DEVICE_ATTRIBUTES_QUERY], reading the answer
+/// key by key from the console.
+pub const DEVICE_ATTRIBUTES_QUERY: &str = r#"$gaudi_da1 = ''
+if (-not [Console]::IsOutputRedirected -and -not [Console]::IsInputRedirected) {
+    [Console]::Write("`e[c")
+    $gaudi_deadline = [DateTime]::Now.AddSeconds(1)
+    while (-not $gaudi_da1.EndsWith('c') -and [DateTime]::Now -lt $gaudi_deadline) {
+        if ([Console]::KeyAvailable) {
+            $gaudi_da1 += [Console]::ReadKey($true).KeyChar
+        } else {
+            Start-Sleep -Milliseconds 10
+        }
+    }
+}
+"#;
+
+/// Escapes `payload` for double quotes. Anything outside of printable ASCII is written as a
+/// `` `u{...} `` escape, so that neither the encoding of the script nor PowerShell's typographic
+/// quotes get in the way.
+pub fn escape_for_double_quotes(payload: &str) -> String {
+    let mut out = String::with_capacity(payload.len());
+
+    for char in payload.chars() {
+        match char {
+            '`' | '"' | '$' => {
+                out.push('`');
+                out.push(char);
+            }
+            '\u{1b}' => out.push_str("`e"),
+            '\n' => out.push_str("`n"),
+            ' '..='~' => out.push(char),
+            _ => out.push_str(&format!("`u{{{:x}}}", char as u32)),
+        }
+    }
+
+    out
+}
+
+pub struct PwshSyntax;
+impl ShellSyntax for PwshSyntax {
+    fn write_print(&self, escapes: &str, f: &mut Formatter) -> std::fmt::Result {
+        write!(f, "[Console]::Write(\"{}\")", escape_for_double_quotes(escapes))
+    }
+
+    fn auto_detection_preamble(&self) -> &'static str {
+        AUTO_DETECTION_PREAMBLE
+    }
+
+    fn write_dispatch(&self, variants: &[(&str, String)], fallback: &str, f: &mut Formatter) -> std::fmt::Result {
+        f.write_str("switch ($gaudi_mode) {\n")?;
+        for (name, escapes) in variants {
+            write!(f, "    '{}' {{\n        ", name)?;
+            self.write_print(escapes, f)?;
+            f.write_str("\n    }\n")?;
+        }
+        f.write_str("    default {\n        ")?;
+        self.write_print(fallback, f)?;
+        f.write_str("\n    }\n}\nRemove-Variable gaudi_mode, gaudi_colors -ErrorAction Ignore\n")
+    }
+
+    fn write_snippet_dispatch(&self, variants: &[(&str, String)], fallback: &str, f: &mut Formatter) -> std::fmt::Result {
+        write_chain(
+            &mode_chain(variants, fallback),
+            ["if ({}) {", "} elseif ({}) {", "} else {", "}"],
+            &|mode| format!("$gaudi_mode -eq '{}'", mode),
+            f,
+        )?;
+        f.write_str("Remove-Variable gaudi_mode, gaudi_colors -ErrorAction Ignore\n")
+    }
+
+    fn write_width_dispatch(&self, variants: &[(u32, String)], f: &mut Formatter) -> std::fmt::Result {
+        f.write_str(WIDTH_DETECTION_PREAMBLE)?;
+        write_chain(
+            variants,
+            ["if ({}) {", "} elseif ({}) {", "} else {", "}"],
+            &|columns| format!("$gaudi_columns -ge {}", columns),
+            f,
+        )?;
+        f.write_str("Remove-Variable gaudi_columns -ErrorAction Ignore\n")
+    }
+
+    fn write_terminal_dispatch(&self, check: TerminalCheck, escapes: &str, fallback: &str, f: &mut Formatter) -> std::fmt::Result {
+        let cleanup = match check {
+            TerminalCheck::DeviceAttribute(attribute) => {
+                f.write_str(DEVICE_ATTRIBUTES_QUERY)?;
+                // the attributes follow `?` and are separated by `;`, the answer ends with `c`
+                write!(f, "if ($gaudi_da1 -match '[?;]{}[;c]') {{\n    ", attribute)?;
+                "Remove-Variable gaudi_da1, gaudi_deadline -ErrorAction Ignore\n"
+            }
+            TerminalCheck::Environment(variables) => {
+                let conditions: Vec<String> = variables.iter()
+                    .map(|(name, value)| match value {
+                        Some(value) => format!("$env:{} -eq '{}'", name, value),
+                        None => format!("$env:{}", name),
+                    })
+                    .collect();
+                write!(f, "if (-not [Console]::IsOutputRedirected -and ({})) {{\n    ", conditions.join(" -or "))?;
+                ""
+            }
+        };
+        self.write_print(escapes, f)?;
+        f.write_str("\n} else {\n")?;
+        f.write_str(fallback)?;
+        if !fallback.ends_with('\n') {
+            f.write_str("\n")?;
+        }
+        f.write_str("}\n")?;
+        f.write_str(cleanup)
+    }
+
+    fn write_animation(&self, start: &str, frames: &[(String, Duration)], end: &str, repeat: Repeat, f: &mut Formatter) -> std::fmt::Result {
+        self.write_print(start, f)?;
+        // Ctrl-C stops the script, but runs the finally block
+        f.write_str("\ntry {\n")?;
+        match repeat {
+            Repeat::Times(times) => writeln!(f, "    for ($gaudi_loop = 0; $gaudi_loop -lt {}; $gaudi_loop++) {{", times)?,
+            Repeat::Forever => f.write_str("    while ($true) {\n")?,
+        }
+        for (escapes, delay) in frames {
+            f.write_str("        ")?;
+            self.write_print(escapes, f)?;
+            write!(f, "\n        Start-Sleep -Milliseconds {}\n", delay.as_millis())?;
+        }
+        f.write_str("    }\n} finally {\n    ")?;
+        self.write_print(end, f)?;
+        f.write_str("\n    Remove-Variable gaudi_loop -ErrorAction Ignore\n}\n")
+    }
+}
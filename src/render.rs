@@ -0,0 +1,164 @@
+use ansi_term::{ANSIGenericString, Style};
+use image::{DynamicImage, GenericImageView, Rgba};
+use crate::colormath::CellColorMapper;
+
+/// Where the image goes when it doesn't fill its last row of cells.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Default)]
+pub enum VerticalAlignment {
+    #[default]
+    Top,
+    /// Splits the padding evenly, any odd pixel row going below the image. Half-block images with
+    /// an odd number of rows come out just like with [VerticalAlignment::Top].
+    Center,
+    Bottom,
+}
+
+/// Where the image goes when it doesn't fill its last column of cells, or is narrower than
+/// [Alignment::width].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Default)]
+pub enum HorizontalAlignment {
+    #[default]
+    Left,
+    Center,
+    Right,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Default)]
+pub struct Alignment {
+    pub vertical: VerticalAlignment,
+    pub horizontal: HorizontalAlignment,
+    /// The width in cells to pad every line to, according to [Alignment::horizontal]
+    pub width: Option<u32>,
+}
+
+impl Alignment {
+    /// How many of `padding` pixels or cells go before the image, vertically
+    pub fn leading_vertical_padding(&self, padding: u32) -> u32 {
+        match self.vertical {
+            VerticalAlignment::Top => 0,
+            VerticalAlignment::Center => padding / 2,
+            VerticalAlignment::Bottom => padding,
+        }
+    }
+
+    /// How many of `padding` pixels or cells go before the image, horizontally
+    pub fn leading_horizontal_padding(&self, padding: u32) -> u32 {
+        match self.horizontal {
+            HorizontalAlignment::Left => 0,
+            HorizontalAlignment::Center => padding / 2,
+            HorizontalAlignment::Right => padding,
+        }
+    }
+}
+
+/// Indents every line of the rendered image so that it is aligned within [Alignment::width].
+/// Lines are only ever padded at the front; trailing blanks would have no visible effect.
+pub fn pad_to_width(spans: Vec<ANSIGenericString<'static, str>>, alignment: &Alignment) -> Vec<ANSIGenericString<'static, str>> {
+    let Some(width) = alignment.width else {
+        return spans;
+    };
+    let columns = spans.iter().take_while(|span| &***span != "\n").count() as u32;
+    let indent = alignment.leading_horizontal_padding(width.saturating_sub(columns));
+    if indent == 0 {
+        return spans;
+    }
+
+    let indent = " ".repeat(indent as usize);
+    let mut padded = Vec::with_capacity(spans.len() + spans.len() / (columns as usize + 1) + 1);
+    let mut at_line_start = true;
+    for span in spans {
+        if at_line_start {
+            padded.push(Style::default().paint(indent.clone()));
+        }
+        at_line_start = &*span == "\n";
+        padded.push(span);
+    }
+
+    padded
+}
+
+/// Drops all colours from the rendered image, for images whose opaque pixels all have the same
+/// colour: cells with a background colour are lit everywhere, so they become full blocks.
+pub fn to_monochrome(spans: Vec<ANSIGenericString<'static, str>>) -> Vec<ANSIGenericString<'static, str>> {
+    spans.into_iter()
+        .map(|span| match span.style_ref().background {
+            Some(_) => Style::default().paint("█"),
+            None => Style::default().paint((*span).to_string()),
+        })
+        .collect()
+}
+
+pub fn image_to_ascii(
+    image: &DynamicImage,
+    vertical_alignment: VerticalAlignment,
+    color_mapper: &CellColorMapper,
+) -> Vec<ANSIGenericString<'static, str>> {
+    let mut as_string: Vec<ANSIGenericString<'static, str>> = Vec::with_capacity((image.width() as usize + 1) * (image.height() as usize / 2 + 1));
+    let mut row: u32 = 0;
+    if !image.height().is_multiple_of(2) && vertical_alignment == VerticalAlignment::Bottom {
+        for col in 0..image.width() {
+            let upper_pixel = Rgba::from([0, 0, 0, 0]);
+            let lower_pixel = image.get_pixel(col, 0);
+            as_string.push(two_pixels_to_ascii_char(&upper_pixel, &lower_pixel, color_mapper));
+        }
+        as_string.push(Style::default().paint("\n"));
+        row = 1;
+    }
+
+    loop {
+        if row + 1 >= image.height() {
+            break;
+        }
+        for col in 0..image.width() {
+            let upper_pixel = image.get_pixel(col, row);
+            let lower_pixel = image.get_pixel(col, row + 1);
+            as_string.push(two_pixels_to_ascii_char(&upper_pixel, &lower_pixel, color_mapper));
+        }
+        as_string.push(Style::default().paint("\n"));
+        row += 2;
+    }
+
+    if !image.height().is_multiple_of(2) && vertical_alignment != VerticalAlignment::Bottom {
+        for col in 0..image.width() {
+            let upper_pixel = image.get_pixel(col, image.height() - 1);
+            let lower_pixel = Rgba::from([0, 0, 0, 0]);
+            as_string.push(two_pixels_to_ascii_char(&upper_pixel, &lower_pixel, color_mapper));
+        }
+        as_string.push(Style::default().paint("\n"));
+    }
+
+    as_string
+}
+
+/// The image encoded as PNG, for the graphics protocols that take image files
+pub fn encode_png(image: &DynamicImage) -> Vec<u8> {
+    let mut png = Vec::new();
+    image.write_to(&mut std::io::Cursor::new(&mut png), image::ImageFormat::Png).expect("PNG encoding into memory can't fail");
+    png
+}
+
+pub fn is_transparent(pixel: &Rgba<u8>) -> bool {
+    pixel[3] == 0
+}
+
+pub fn two_pixels_to_ascii_char(
+    upper_pixel: &Rgba<u8>,
+    lower_pixel: &Rgba<u8>,
+    color_mapper: &CellColorMapper,
+) -> ANSIGenericString<'static, str> {
+    if is_transparent(upper_pixel) && is_transparent(lower_pixel) {
+        return Style::default().paint(" ");
+    }
+
+    if is_transparent(upper_pixel) {
+        assert!(!is_transparent(lower_pixel));
+        return color_mapper.foreground(lower_pixel).paint("▄");
+    }
+
+    if is_transparent(lower_pixel) {
+        assert!(!is_transparent(upper_pixel));
+        return color_mapper.foreground(upper_pixel).paint("▀");
+    }
+
+    color_mapper.foreground(lower_pixel).on(color_mapper.background(upper_pixel)).paint("▄")
+}
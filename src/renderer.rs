@@ -0,0 +1,516 @@
+use std::borrow::Cow;
+use std::time::Duration;
+use ansi_term::ANSIGenericString;
+use image::{DynamicImage, GenericImageView};
+use image::imageops::FilterType;
+use crate::animation::{effective_delay, Frame, Repeat};
+use crate::bash_syntax::{write_with_minimal_control_sequences, ColorEncoding};
+use crate::color_distance::ColorDistance;
+use crate::colormath::ColorMappers;
+use crate::dither::Dither;
+use crate::error::GaudiError;
+use crate::glyphs::GlyphMode;
+use crate::palette::{Ansi256Palette, TerminalPalette};
+use crate::render::Alignment;
+use crate::{delta, iterm2, kitty, passthrough, sixel};
+use crate::shell_syntax::TerminalCheck;
+use crate::snippet::{capture_to_string, ImageEmittingSnippet, AUTO_VARIANTS};
+
+/// Which colours the emitted escape sequences may use.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum ColorMode {
+    TrueColor,
+    Ansi,
+    Ansi256,
+    /// The 24 greys of the 256 colour palette plus black and white. Never picked by [ColorMode::Auto],
+    /// as terminals have no way to advertise a grey-only palette.
+    Grayscale,
+    /// No colours at all: the lit pixels are drawn in the terminal's foreground colour
+    Mono,
+    /// The 8 basic colours, for the Linux console
+    Ansi8,
+    /// Emits one variant per colour mode, chosen by the snippet at runtime. For
+    /// [OutputFormat::Raw], the mode is chosen from the environment instead.
+    Auto,
+}
+
+/// How the bright half of the 16 colours of [ColorMode::Ansi] is requested from the terminal.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Default)]
+pub enum BrightStrategy {
+    /// The aixterm codes 90–97 and 100–107, understood by nearly every terminal emulator
+    #[default]
+    Aixterm,
+    /// Bold plus the basic colour code, for terminals that only show bright colours for bold text.
+    /// Bright backgrounds can't be requested this way, so backgrounds are limited to 8 colours.
+    Bold,
+}
+
+/// The shell syntax the rendered image is wrapped in.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Emitter {
+    Bash,
+    /// POSIX sh, for dash, busybox and /etc/profile.d
+    Posix,
+    Zsh,
+    Fish,
+    /// PowerShell 6 and later
+    Pwsh,
+}
+
+/// How the image is drawn on the terminal.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Protocol {
+    /// Coloured characters, see [GlyphMode]
+    Cells,
+    /// DEC sixel graphics, for xterm -ti vt340, foot, WezTerm, mlterm and others. Snippets fall
+    /// back to cells on terminals that don't list sixels in their device attributes.
+    Sixel,
+    /// The kitty graphics protocol. Snippets fall back to cells outside of kitty.
+    Kitty,
+    /// iTerm2 inline images (OSC 1337), which WezTerm shows as well. Snippets fall back to cells
+    /// in other terminals.
+    ITerm2,
+}
+
+/// The terminal multiplexer the graphics protocols are passed through, so that they reach the
+/// terminal it runs in.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Passthrough {
+    None,
+    Tmux,
+    /// GNU screen, which only passes short strings without a string terminator on, so it only
+    /// suits [Protocol::ITerm2]
+    Screen,
+}
+
+/// What [Renderer::render] produces.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum OutputFormat {
+    /// A shell snippet that prints the image, in the syntax of the [Emitter]
+    Snippet,
+    /// The escape sequences themselves, to be written to the terminal or into a file to `cat`
+    Raw,
+}
+
+/// What terminal cells are assumed to measure in pixels where the terminal doesn't tell
+const DEFAULT_CELL_SIZE: (u32, u32) = (10, 20);
+
+/// Hides the cursor while an animation plays, so that it doesn't flicker over the frames
+const ANIMATION_START: &str = "\x1b[?25l";
+/// Shows the cursor again after an animation, with the colours reset in case it was interrupted
+const ANIMATION_END: &str = "\x1b[0m\x1b[?25h";
+
+/// How [Renderer::animation] gets the cells of a frame, and how their colours are written
+type FrameSpans<'a> = dyn Fn(&ImageEmittingSnippet) -> (Vec<ANSIGenericString<'static, str>>, ColorEncoding) + 'a;
+
+/// Appended to raw output on request, so that whatever follows starts from a clean state
+const RESET_AT_END: &str = "\x1b[0m\x1b[K";
+
+/// Renders a [DynamicImage] into a shell snippet that prints the image to the terminal.
+///
+/// ```no_run
+/// # let image = image::DynamicImage::new_rgba8(1, 1);
+/// let snippet = gaudi::Renderer::new(image)
+///     .color_mode(gaudi::ColorMode::Ansi256)
+///     .resize_to_width(Some(40))
+///     .render()?;
+/// # Ok::<(), gaudi::GaudiError>(())
+/// ```
+#[derive(Debug, Clone)]
+pub struct Renderer {
+    image: DynamicImage,
+    frames: Vec<Frame>,
+    repeat: Repeat,
+    color_mode: ColorMode,
+    color_distance: ColorDistance,
+    bright_strategy: BrightStrategy,
+    palette: TerminalPalette,
+    ansi256_palette: Ansi256Palette,
+    dither: Dither,
+    glyph_mode: GlyphMode,
+    alignment: Alignment,
+    emitter: Emitter,
+    output_format: OutputFormat,
+    reset_at_end: bool,
+    resize_to_width: Option<u32>,
+    fit: Option<(u32, u32)>,
+    cell_size: (u32, u32),
+    protocol: Protocol,
+    passthrough: Passthrough,
+    snippet_widths: Vec<u32>,
+    resize_filter: FilterType,
+}
+
+impl Renderer {
+    pub fn new(image: DynamicImage) -> Self {
+        Renderer {
+            image: DynamicImage::ImageRgba8(image.into_rgba8()),
+            frames: Vec::new(),
+            repeat: Repeat::default(),
+            color_mode: ColorMode::Auto,
+            color_distance: ColorDistance::default(),
+            bright_strategy: BrightStrategy::default(),
+            palette: TerminalPalette::default(),
+            ansi256_palette: Ansi256Palette::default(),
+            dither: Dither::default(),
+            glyph_mode: GlyphMode::HalfBlock,
+            alignment: Alignment::default(),
+            emitter: Emitter::Bash,
+            output_format: OutputFormat::Snippet,
+            reset_at_end: false,
+            resize_to_width: None,
+            fit: None,
+            cell_size: DEFAULT_CELL_SIZE,
+            protocol: Protocol::Cells,
+            passthrough: Passthrough::None,
+            snippet_widths: Vec::new(),
+            resize_filter: FilterType::CatmullRom,
+        }
+    }
+
+    /// Renders a snippet that plays the frames, see [crate::load_frames]. Each frame is drawn over
+    /// the one before it, so they all have to have the same size. For [ColorMode::Auto] the
+    /// snippet holds the animation in every colour mode and picks one when it runs, as it does
+    /// for still images.
+    pub fn animated(frames: Vec<Frame>) -> Self {
+        let first = frames.first().map_or_else(|| DynamicImage::new_rgba8(0, 0), |frame| frame.image.clone());
+        Renderer { frames, ..Renderer::new(first) }
+    }
+
+    pub fn color_mode(mut self, color_mode: ColorMode) -> Self {
+        self.color_mode = color_mode;
+        self
+    }
+
+    /// The metric used to find the closest palette entry in the [ColorMode::Ansi] and [ColorMode::Ansi256] modes
+    pub fn color_distance(mut self, color_distance: ColorDistance) -> Self {
+        self.color_distance = color_distance;
+        self
+    }
+
+    /// Only affects [ColorMode::Ansi]
+    pub fn bright_strategy(mut self, bright_strategy: BrightStrategy) -> Self {
+        self.bright_strategy = bright_strategy;
+        self
+    }
+
+    /// The colours the terminal shows for the 16 ANSI colours, which [ColorMode::Ansi] is matched against
+    pub fn palette(mut self, palette: TerminalPalette) -> Self {
+        self.palette = palette;
+        self
+    }
+
+    /// The colours the terminal shows for the 256 colour palette, which [ColorMode::Ansi256] is matched against
+    pub fn ansi256_palette(mut self, palette: Ansi256Palette) -> Self {
+        self.ansi256_palette = palette;
+        self
+    }
+
+    /// Only affects the palette based colour modes
+    pub fn dither(mut self, dither: Dither) -> Self {
+        self.dither = dither;
+        self
+    }
+
+    pub fn glyph_mode(mut self, glyph_mode: GlyphMode) -> Self {
+        self.glyph_mode = glyph_mode;
+        self
+    }
+
+    pub fn alignment(mut self, alignment: Alignment) -> Self {
+        self.alignment = alignment;
+        self
+    }
+
+    pub fn emitter(mut self, emitter: Emitter) -> Self {
+        self.emitter = emitter;
+        self
+    }
+
+    pub fn output_format(mut self, output_format: OutputFormat) -> Self {
+        self.output_format = output_format;
+        self
+    }
+
+    /// Only affects [OutputFormat::Raw]: ends the output with a colour reset and a clear to the end
+    /// of the line, so that whatever follows it starts from a clean state
+    pub fn reset_at_end(mut self, reset_at_end: bool) -> Self {
+        self.reset_at_end = reset_at_end;
+        self
+    }
+
+    /// Scales the image to the given width in pixels, keeping the aspect ratio
+    pub fn resize_to_width(mut self, width: Option<u32>) -> Self {
+        self.resize_to_width = width;
+        self
+    }
+
+    /// Scales the image to the largest size that fits into the given columns and rows, keeping the
+    /// aspect ratio. How many pixels that is depends on the glyph mode.
+    pub fn fit(mut self, cells: Option<(u32, u32)>) -> Self {
+        self.fit = cells;
+        self
+    }
+
+    /// The size of a terminal cell in pixels, which sizes the graphics protocols for
+    /// [Renderer::fit] and [Renderer::snippet_widths]. None for a guess of 10x20.
+    pub fn cell_size(mut self, pixels: Option<(u32, u32)>) -> Self {
+        self.cell_size = pixels.unwrap_or(DEFAULT_CELL_SIZE);
+        self
+    }
+
+    pub fn protocol(mut self, protocol: Protocol) -> Self {
+        self.protocol = protocol;
+        self
+    }
+
+    /// Only affects the graphics protocols
+    pub fn passthrough(mut self, passthrough: Passthrough) -> Self {
+        self.passthrough = passthrough;
+        self
+    }
+
+    /// Only affects [OutputFormat::Snippet]: embeds the image once per width in columns, and has
+    /// the snippet print the widest that fits into the terminal, or the narrowest if none does
+    pub fn snippet_widths(mut self, widths: Vec<u32>) -> Self {
+        self.snippet_widths = widths;
+        self
+    }
+
+    /// Only affects [Renderer::animated]
+    pub fn repeat(mut self, repeat: Repeat) -> Self {
+        self.repeat = repeat;
+        self
+    }
+
+    pub fn resize_filter(mut self, filter: FilterType) -> Self {
+        self.resize_filter = filter;
+        self
+    }
+
+    pub fn render(&self) -> Result<String, GaudiError> {
+        self.validate()?;
+        let mappers = ColorMappers::new(self.color_distance, &self.palette, &self.ansi256_palette);
+        if !self.frames.is_empty() {
+            return Ok(self.render_animation(&mappers));
+        }
+        if self.snippet_widths.is_empty() {
+            return Ok(self.render_at_width(None, &mappers));
+        }
+
+        let mut widths = self.snippet_widths.clone();
+        widths.sort_unstable_by(|a, b| b.cmp(a));
+        widths.dedup();
+        let variants: Vec<(u32, String)> = widths.iter()
+            .map(|columns| (*columns, self.render_at_width(Some(*columns), &mappers)))
+            .collect();
+        Ok(match variants.as_slice() {
+            [(_, snippet)] => snippet.clone(),
+            _ => capture_to_string(&|f| self.emitter.syntax().write_width_dispatch(&variants, f)),
+        })
+    }
+
+    /// The output for the image scaled to the given number of columns, or as the options say
+    fn render_at_width(&self, columns: Option<u32>, mappers: &ColorMappers) -> String {
+        let mut output = match (self.render_graphics(columns), self.output_format) {
+            (None, _) => self.render_cells(columns, mappers),
+            (Some((graphics, _)), OutputFormat::Raw) => graphics,
+            (Some((graphics, check)), OutputFormat::Snippet) => {
+                let fallback = self.render_cells(columns, mappers);
+                capture_to_string(&|f| self.emitter.syntax().write_terminal_dispatch(check, &graphics, &fallback, f))
+            }
+        };
+        if self.output_format == OutputFormat::Raw && self.reset_at_end {
+            output.push_str(RESET_AT_END);
+        }
+        output
+    }
+
+    /// The image in the graphics protocol, and how snippets find out whether the terminal supports
+    /// it, or None for [Protocol::Cells]
+    fn render_graphics(&self, columns: Option<u32>) -> Option<(String, TerminalCheck)> {
+        let image = || self.prepared_image(&self.image, self.cell_size, columns);
+        // the cells the image covers, so that the terminal shows it at the size it has
+        let cells = |image: &DynamicImage| (image.width().div_ceil(self.cell_size.0), image.height().div_ceil(self.cell_size.1));
+        let (graphics, check) = match self.protocol {
+            Protocol::Cells => return None,
+            Protocol::Sixel => (
+                sixel::encode(&image(), self.color_distance, self.dither),
+                TerminalCheck::DeviceAttribute(sixel::DEVICE_ATTRIBUTE),
+            ),
+            Protocol::Kitty => {
+                let image = image();
+                (kitty::encode(&image, cells(&image)), kitty::TERMINAL_CHECK)
+            }
+            Protocol::ITerm2 => {
+                let image = image();
+                (iterm2::encode(&image, cells(&image)), iterm2::TERMINAL_CHECK)
+            }
+        };
+        // the line break after the image is meant for the multiplexer
+        let graphics = match self.passthrough {
+            Passthrough::None => graphics,
+            Passthrough::Tmux => passthrough::wrap_for_tmux(graphics.trim_end_matches('\n')) + "\n",
+            Passthrough::Screen => passthrough::wrap_for_screen(graphics.trim_end_matches('\n')) + "\n",
+        };
+        Some((graphics, check))
+    }
+
+    /// The animation in the colour mode of the renderer, or in all of them behind the detection
+    /// of the terminal for [ColorMode::Auto]
+    fn render_animation(&self, mappers: &ColorMappers) -> String {
+        let syntax = self.emitter.syntax();
+        match self.color_mode {
+            ColorMode::Auto => {
+                let variants: Vec<(&str, String)> = AUTO_VARIANTS.iter()
+                    .map(|(name, color_mode)| {
+                        (*name, self.animation(mappers, &|snippet| snippet.spans_for_color_mode(*color_mode)))
+                    })
+                    .collect();
+                let plain = self.animation(mappers, &|snippet| (snippet.plain_spans(), ColorEncoding::Extended));
+                capture_to_string(&|f| {
+                    f.write_str(syntax.auto_detection_preamble())?;
+                    syntax.write_snippet_dispatch(&variants, &plain, f)
+                })
+            }
+            color_mode => self.animation(mappers, &|snippet| snippet.spans_for_color_mode(color_mode)),
+        }
+    }
+
+    /// The snippet that plays the frames, with the cells of each as `spans` draws them. Every
+    /// frame is drawn over the one before it: the cursor goes back up to the top of the image, so
+    /// it is started with as many line breaks as the image has lines. Frames after the first only
+    /// draw the cells that changed, where that is cheaper. The first one is drawn in full, as
+    /// there is nothing on the screen in the first round.
+    fn animation(&self, mappers: &ColorMappers, spans: &FrameSpans<'_>) -> String {
+        let frames: Vec<(Vec<ANSIGenericString<'static, str>>, ColorEncoding)> = self.frames.iter()
+            .map(|frame| {
+                let image = self.prepared_image(&frame.image, self.glyph_mode.pixels_per_cell(), None);
+                spans(&self.cells_snippet(&image, mappers))
+            })
+            .collect();
+        let cells: Vec<_> = frames.iter().map(|(spans, _)| delta::cells(spans)).collect();
+        let rows = cells[0].len();
+        let up = match rows {
+            0 => String::new(),
+            rows => format!("\x1b[{}A", rows),
+        };
+
+        let played: Vec<(String, Duration)> = frames.iter().zip(&self.frames).enumerate()
+            .map(|(index, ((spans, encoding), frame))| {
+                let full = up.clone() + &capture_to_string(&|f| write_with_minimal_control_sequences(spans, *encoding, f));
+                if index == 0 {
+                    return (full, effective_delay(frame.delay));
+                }
+                let delta = capture_to_string(&|f| delta::write_delta(&cells[index - 1], &cells[index], *encoding, f));
+                let cheaper = delta.len() as f32 <= full.len() as f32 * delta::FULL_REDRAW_THRESHOLD;
+                (if cheaper { delta } else { full }, effective_delay(frame.delay))
+            })
+            .collect();
+        let start = ANIMATION_START.to_string() + &"\n".repeat(rows);
+
+        capture_to_string(&|f| self.emitter.syntax().write_animation(&start, &played, ANIMATION_END, self.repeat, f))
+    }
+
+    fn render_cells(&self, columns: Option<u32>, mappers: &ColorMappers) -> String {
+        let image = self.prepared_image(&self.image, self.glyph_mode.pixels_per_cell(), columns);
+        let snippet = self.cells_snippet(&image, mappers);
+        match self.output_format {
+            OutputFormat::Snippet => snippet.to_string(),
+            OutputFormat::Raw => snippet.raw_escapes(),
+        }
+    }
+
+    fn cells_snippet<'a>(&'a self, image: &'a DynamicImage, mappers: &'a ColorMappers) -> ImageEmittingSnippet<'a> {
+        ImageEmittingSnippet {
+            image,
+            color_mode: self.color_mode,
+            mappers,
+            bright_strategy: self.bright_strategy,
+            dither: self.dither,
+            glyph_mode: &self.glyph_mode,
+            alignment: self.alignment,
+            emitter: self.emitter,
+        }
+    }
+
+    fn validate(&self) -> Result<(), GaudiError> {
+        if self.repeat == Repeat::Times(0) {
+            return Err(GaudiError::invalid_option("loop", "must be greater than 0"));
+        }
+        if !self.frames.is_empty() {
+            if self.frames.iter().any(|frame| frame.image.dimensions() != self.image.dimensions()) {
+                return Err(GaudiError::invalid_option("animation", "all frames need the same size"));
+            }
+            if self.output_format == OutputFormat::Raw {
+                return Err(GaudiError::invalid_option("output-format", "animations are only played by snippets"));
+            }
+            if self.protocol != Protocol::Cells {
+                return Err(GaudiError::invalid_option("protocol", "animations are only played with cells"));
+            }
+            if !self.snippet_widths.is_empty() {
+                return Err(GaudiError::invalid_option("fit-widths", "can't be combined with animations"));
+            }
+        }
+        if self.resize_to_width == Some(0) {
+            return Err(GaudiError::invalid_option("resize-to-width", "must be greater than 0"));
+        }
+        if let Some((columns, rows)) = self.fit {
+            if columns == 0 || rows == 0 {
+                return Err(GaudiError::invalid_option("fit", "needs at least one column and one row"));
+            }
+            if self.resize_to_width.is_some() {
+                return Err(GaudiError::invalid_option("fit", "can't be combined with resize-to-width"));
+            }
+        }
+        if !self.snippet_widths.is_empty() {
+            if self.snippet_widths.contains(&0) {
+                return Err(GaudiError::invalid_option("fit-widths", "must be greater than 0"));
+            }
+            if self.output_format == OutputFormat::Raw {
+                return Err(GaudiError::invalid_option("fit-widths", "only applies to snippets, not to raw output"));
+            }
+            if self.fit.is_some() || self.resize_to_width.is_some() {
+                return Err(GaudiError::invalid_option("fit-widths", "can't be combined with fit or resize-to-width"));
+            }
+        }
+        if self.passthrough == Passthrough::Screen && matches!(self.protocol, Protocol::Sixel | Protocol::Kitty) {
+            return Err(GaudiError::invalid_option("passthrough", "screen only passes iterm2 images on"));
+        }
+        if self.alignment.width == Some(0) {
+            return Err(GaudiError::invalid_option("align-width", "must be greater than 0"));
+        }
+        if let GlyphMode::Ascii(ramp) = &self.glyph_mode {
+            if ramp.characters.is_empty() {
+                return Err(GaudiError::invalid_option("ascii-ramp", "needs at least one character"));
+            }
+            if ramp.characters.iter().any(|c| c.is_control()) {
+                return Err(GaudiError::invalid_option("ascii-ramp", "must not contain control characters"));
+            }
+        }
+
+        Ok(())
+    }
+
+    /// The image scaled to the given number of columns, or as the options say, for cells of
+    /// `pixels_per_cell`
+    fn prepared_image<'a>(&self, image: &'a DynamicImage, pixels_per_cell: (u32, u32), columns: Option<u32>) -> Cow<'a, DynamicImage> {
+        let (cell_width, cell_height) = pixels_per_cell;
+        match (columns, self.resize_to_width, self.fit) {
+            (Some(columns), _, _) => Cow::Owned(self.resized_to_width(image, columns * cell_width)),
+            (None, Some(resize_to_width), _) => Cow::Owned(self.resized_to_width(image, resize_to_width)),
+            (None, None, Some((columns, rows))) => {
+                // resize() keeps the aspect ratio within these bounds, which saturate for a side
+                // that doesn't limit the image
+                Cow::Owned(image.resize(columns.saturating_mul(cell_width), rows.saturating_mul(cell_height), self.resize_filter))
+            },
+            (None, None, None) => Cow::Borrowed(image),
+        }
+    }
+
+    fn resized_to_width(&self, image: &DynamicImage, width: u32) -> DynamicImage {
+        let factor = width as f32 / image.width() as f32;
+        let new_height = (image.height() as f32 * factor) as u32;
+        image.resize(width, new_height, self.resize_filter)
+    }
+}
@@ -0,0 +1,201 @@
+use std::fmt::Formatter;
+use std::time::Duration;
+use crate::animation::{seconds, Repeat};
+use crate::renderer::Emitter;
+use crate::{bash_syntax, fish_syntax, posix_syntax, pwsh_syntax, zsh_syntax};
+
+/// How a snippet is written for one shell: printing escape sequences verbatim, finding out what
+/// the terminal supports and picking the variant of the image that suits it.
+pub trait ShellSyntax {
+    /// Writes a statement that prints `escapes` verbatim, without a trailing newline
+    fn write_print(&self, escapes: &str, f: &mut Formatter) -> std::fmt::Result;
+
+    /// Statements that set the variable `gaudi_mode` to the colour mode the terminal supports, or
+    /// to `plain` if colours are disabled or would end up somewhere else than on a terminal
+    fn auto_detection_preamble(&self) -> &'static str;
+
+    /// Writes statements that print the escapes of the variant `gaudi_mode` names, or `fallback`
+    /// for any other value, and then drop the variables of the preamble
+    fn write_dispatch(&self, variants: &[(&str, String)], fallback: &str, f: &mut Formatter) -> std::fmt::Result;
+
+    /// Like [ShellSyntax::write_dispatch], but `variants` and `fallback` are whole snippets to run
+    /// rather than escapes to print
+    fn write_snippet_dispatch(&self, variants: &[(&str, String)], fallback: &str, f: &mut Formatter) -> std::fmt::Result;
+
+    /// Writes statements that find out the width of the terminal in columns and run the widest of
+    /// `variants` that fits, or the last one if none does. `variants` are whole snippets, widest
+    /// first, by their width in columns.
+    fn write_width_dispatch(&self, variants: &[(u32, String)], f: &mut Formatter) -> std::fmt::Result;
+
+    /// Writes statements that print `escapes` if `check` finds the terminal supports them, or run
+    /// the snippet `fallback` if it doesn't
+    fn write_terminal_dispatch(
+        &self,
+        check: TerminalCheck,
+        escapes: &str,
+        fallback: &str,
+        f: &mut Formatter,
+    ) -> std::fmt::Result;
+
+    /// Writes statements that print `start`, then play the frames `repeat` times over, each for
+    /// its delay, and print `end` when they are done. Ctrl-C stops the animation, and `end` is
+    /// printed all the same.
+    fn write_animation(
+        &self,
+        start: &str,
+        frames: &[(String, Duration)],
+        end: &str,
+        repeat: Repeat,
+        f: &mut Formatter,
+    ) -> std::fmt::Result;
+}
+
+/// How a snippet finds out whether the terminal can show a graphics protocol
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TerminalCheck {
+    /// The terminal lists the attribute in its primary device attributes (DA1). Fails if the
+    /// terminal doesn't answer within a second.
+    DeviceAttribute(u32),
+    /// stdout is a terminal and one of the environment variables has the given value, or any value
+    /// but the empty one for None
+    Environment(&'static [(&'static str, Option<&'static str>)]),
+}
+
+impl Emitter {
+    pub fn syntax(self) -> &'static dyn ShellSyntax {
+        match self {
+            Emitter::Bash => &bash_syntax::BashSyntax,
+            Emitter::Posix => &posix_syntax::PosixSyntax,
+            Emitter::Zsh => &zsh_syntax::ZshSyntax,
+            Emitter::Fish => &fish_syntax::FishSyntax,
+            Emitter::Pwsh => &pwsh_syntax::PwshSyntax,
+        }
+    }
+}
+
+/// Writes the conditionals of [ShellSyntax::write_width_dispatch] and
+/// [ShellSyntax::write_snippet_dispatch]: each of `variants` but the last runs its snippet if the
+/// condition for its key holds, the last one runs otherwise. `keywords` are the openings of the
+/// first and of the following conditional branches, with `{}` for the condition, the opening of
+/// the unconditional branch and the end of the chain.
+pub(crate) fn write_chain<K: Copy>(
+    variants: &[(K, String)],
+    keywords: [&str; 4],
+    condition: &dyn Fn(K) -> String,
+    f: &mut Formatter,
+) -> std::fmt::Result {
+    let [first, following, last, end] = keywords;
+    for (index, (key, snippet)) in variants.iter().enumerate() {
+        let opening = match index {
+            _ if index + 1 == variants.len() => last.to_string(),
+            0 => first.replace("{}", &condition(*key)),
+            _ => following.replace("{}", &condition(*key)),
+        };
+        f.write_str(&opening)?;
+        f.write_str("\n")?;
+        f.write_str(snippet)?;
+        if !snippet.ends_with('\n') {
+            f.write_str("\n")?;
+        }
+    }
+    f.write_str(end)?;
+    f.write_str("\n")
+}
+
+/// [ShellSyntax::write_terminal_dispatch] for the shells that understand POSIX sh, running
+/// [posix_syntax::DEVICE_ATTRIBUTES_QUERY] for the device attributes
+pub(crate) fn write_sh_terminal_dispatch(
+    syntax: &dyn ShellSyntax,
+    check: TerminalCheck,
+    escapes: &str,
+    fallback: &str,
+    f: &mut Formatter,
+) -> std::fmt::Result {
+    let end = match check {
+        TerminalCheck::DeviceAttribute(attribute) => {
+            f.write_str(posix_syntax::DEVICE_ATTRIBUTES_QUERY)?;
+            // the attributes follow `?` and are separated by `;`, the answer ends with `c`
+            write!(f, "case \"$gaudi_da1\" in\n    *[?\\;]{}[\\;c]*)\n        ", attribute)?;
+            syntax.write_print(escapes, f)?;
+            f.write_str("\n        ;;\n    *)\n")?;
+            "        ;;\nesac\nunset gaudi_da1 gaudi_stty\n"
+        }
+        TerminalCheck::Environment(variables) => {
+            let conditions: Vec<String> = variables.iter()
+                .map(|(name, value)| match value {
+                    Some(value) => format!("[ \"${{{}-}}\" = \"{}\" ]", name, value),
+                    None => format!("[ -n \"${{{}-}}\" ]", name),
+                })
+                .collect();
+            write!(f, "if [ -t 1 ] && {{ {}; }}; then\n    ", conditions.join(" || "))?;
+            syntax.write_print(escapes, f)?;
+            f.write_str("\nelse\n")?;
+            "fi\n"
+        }
+    };
+    f.write_str(fallback)?;
+    if !fallback.ends_with('\n') {
+        f.write_str("\n")?;
+    }
+    f.write_str(end)
+}
+
+/// [ShellSyntax::write_animation] for the shells that understand POSIX sh. `sleep` has to take
+/// fractions of seconds, which GNU coreutils, busybox and the BSDs all do. `list_traps` is the
+/// command whose output sets the INT trap of the snippet's shell again, so that a shell or script
+/// that sources the snippet keeps its own. Shells that don't list the traps of the parent in a
+/// command substitution, like dash, reset INT to the default instead.
+pub(crate) fn write_sh_animation(
+    syntax: &dyn ShellSyntax,
+    list_traps: &str,
+    start: &str,
+    frames: &[(String, Duration)],
+    end: &str,
+    repeat: Repeat,
+    f: &mut Formatter,
+) -> std::fmt::Result {
+    syntax.write_print(start, f)?;
+    // Ctrl-C ends sleep, and the trap keeps it from ending the shell, so that `end` is printed
+    write!(f, "\ngaudi_interrupted=\ngaudi_trap=$({})\ntrap 'gaudi_interrupted=1' INT\n", list_traps)?;
+    match repeat {
+        Repeat::Times(times) => write!(
+            f,
+            "gaudi_loop=0\nwhile [ -z \"$gaudi_interrupted\" ] && [ \"$gaudi_loop\" -lt {} ]; do\n    gaudi_loop=$((gaudi_loop + 1))\n",
+            times,
+        )?,
+        Repeat::Forever => f.write_str("while [ -z \"$gaudi_interrupted\" ]; do\n")?,
+    }
+    for (escapes, delay) in frames {
+        f.write_str("    ")?;
+        syntax.write_print(escapes, f)?;
+        write!(f, "\n    sleep {} && [ -z \"$gaudi_interrupted\" ] || break\n", seconds(*delay))?;
+    }
+    f.write_str("done\ntrap - INT\neval \"$gaudi_trap\"\n")?;
+    syntax.write_print(end, f)?;
+    f.write_str("\nunset gaudi_interrupted gaudi_loop gaudi_trap\n")
+}
+
+/// The branches of [ShellSyntax::write_snippet_dispatch] for [write_chain], with the fallback last
+pub(crate) fn mode_chain<'a>(variants: &[(&'a str, String)], fallback: &str) -> Vec<(&'a str, String)> {
+    let mut chain = variants.to_vec();
+    chain.push(("", fallback.to_string()));
+    chain
+}
+
+/// The `case` statement of [ShellSyntax::write_dispatch], for the shells that have one
+pub(crate) fn write_case_dispatch(
+    syntax: &dyn ShellSyntax,
+    variants: &[(&str, String)],
+    fallback: &str,
+    f: &mut Formatter,
+) -> std::fmt::Result {
+    f.write_str("case \"$gaudi_mode\" in\n")?;
+    for (name, escapes) in variants {
+        write!(f, "    {})\n        ", name)?;
+        syntax.write_print(escapes, f)?;
+        f.write_str("\n        ;;\n")?;
+    }
+    f.write_str("    *)\n        ")?;
+    syntax.write_print(fallback, f)?;
+    f.write_str("\n        ;;\nesac\nunset gaudi_mode gaudi_colors\n")
+}
@@ -0,0 +1,140 @@
+use std::collections::HashSet;
+use std::fmt::Write;
+use ansi_term::Colour;
+use image::{DynamicImage, RgbaImage};
+use crate::color_distance::ColorDistance;
+use crate::colormath::PaletteMatcher;
+use crate::dither::Dither;
+use crate::render::is_transparent;
+
+/// The number of colour registers the image is quantised to. 256 is what xterm offers for
+/// `-ti vt340` with `numColorRegisters` raised, and what most other sixel terminals support.
+pub const MAX_COLORS: usize = 256;
+
+/// The attribute terminals list in their primary device attributes (DA1) if they can show sixels.
+pub const DEVICE_ATTRIBUTE: u32 = 4;
+
+/// Encodes the image as a DEC sixel stream, with its colours quantised to at most [MAX_COLORS]
+/// registers. Transparent pixels are not drawn, so that the background of the terminal shows
+/// through. Ends with a line break, like the other output.
+pub fn encode(image: &DynamicImage, distance: ColorDistance, dither: Dither) -> String {
+    let palette = quantize(&image.to_rgba8(), MAX_COLORS);
+    let colours: Vec<Colour> = palette.iter().map(|&(r, g, b)| Colour::RGB(r, g, b)).collect();
+    let matcher = PaletteMatcher::with_rgb(&colours, &palette, distance);
+    let image = dither.apply(image, &matcher).to_rgba8();
+    let (width, height) = image.dimensions();
+    let registers: Vec<Option<usize>> = image.pixels()
+        .map(|pixel| (!is_transparent(pixel)).then(|| matcher.closest_index((pixel[0], pixel[1], pixel[2]))))
+        .collect();
+
+    // P2 = 1: pixels that aren't drawn keep their background. The raster attributes ask for
+    // square pixels.
+    let mut out = format!("\x1bP0;1;0q\"1;1;{};{}", width, height);
+    for (register, (r, g, b)) in palette.iter().enumerate() {
+        write!(out, "#{};2;{};{};{}", register, percent(*r), percent(*g), percent(*b)).unwrap();
+    }
+
+    let mut sixels = vec![0u8; width as usize];
+    for band_top in (0..height).step_by(6) {
+        let band_height = (height - band_top).min(6);
+        let mut first_in_band = true;
+        for register in 0..palette.len() {
+            for (x, sixel) in sixels.iter_mut().enumerate() {
+                *sixel = (0..band_height)
+                    .filter(|dy| registers[((band_top + dy) * width) as usize + x] == Some(register))
+                    .fold(0, |bits, dy| bits | 1 << dy);
+            }
+            let Some(last) = sixels.iter().rposition(|sixel| *sixel != 0) else {
+                continue;
+            };
+            if !first_in_band {
+                // back to the start of the band, to draw the next colour over it
+                out.push('$');
+            }
+            first_in_band = false;
+            write!(out, "#{}", register).unwrap();
+            write_run_length_encoded(&sixels[..=last], &mut out);
+        }
+        out.push('-');
+    }
+
+    out.push_str("\x1b\\\n");
+    out
+}
+
+/// Writes the sixels as characters, with runs of more than three of them as `!<count><sixel>`
+fn write_run_length_encoded(sixels: &[u8], out: &mut String) {
+    let mut rest = sixels;
+    while let Some(&sixel) = rest.first() {
+        let run = rest.iter().take_while(|other| **other == sixel).count();
+        let char = (sixel + 63) as char;
+        if run > 3 {
+            write!(out, "!{}{}", run, char).unwrap();
+        } else {
+            out.extend(std::iter::repeat_n(char, run));
+        }
+        rest = &rest[run..];
+    }
+}
+
+/// Sixel colour registers take percentages
+fn percent(value: u8) -> u32 {
+    (value as u32 * 100 + 127) / 255
+}
+
+/// Up to `max_colors` colours that stand for the opaque pixels of the image: the colours
+/// themselves if there are few enough, the averages of median cut boxes otherwise.
+pub fn quantize(image: &RgbaImage, max_colors: usize) -> Vec<(u8, u8, u8)> {
+    let pixels: Vec<[u8; 3]> = image.pixels()
+        .filter(|pixel| !is_transparent(pixel))
+        .map(|pixel| [pixel[0], pixel[1], pixel[2]])
+        .collect();
+    let distinct: HashSet<[u8; 3]> = pixels.iter().copied().collect();
+    if distinct.is_empty() {
+        return vec![(0, 0, 0)];
+    }
+    if distinct.len() <= max_colors {
+        let mut colors: Vec<(u8, u8, u8)> = distinct.into_iter().map(|[r, g, b]| (r, g, b)).collect();
+        colors.sort_unstable();
+        return colors;
+    }
+
+    // each box with the channel it is widest along, and how wide
+    let mut boxes = vec![with_widest_channel(pixels)];
+    while boxes.len() < max_colors {
+        // split the widest box at the median of its widest channel
+        let Some(index) = (0..boxes.len()).filter(|index| boxes[*index].2 > 0).max_by_key(|index| boxes[*index].2) else {
+            break;
+        };
+        let (mut lower, channel, _) = boxes.swap_remove(index);
+        lower.sort_unstable_by_key(|pixel| pixel[channel]);
+        let upper = lower.split_off(lower.len() / 2);
+        boxes.push(with_widest_channel(lower));
+        boxes.push(with_widest_channel(upper));
+    }
+
+    boxes.iter().map(|(pixels, _, _)| average(pixels)).collect()
+}
+
+fn with_widest_channel(pixels: Vec<[u8; 3]>) -> (Vec<[u8; 3]>, usize, u8) {
+    let (channel, range) = widest_channel(&pixels);
+    (pixels, channel, range)
+}
+
+fn widest_channel(pixels: &[[u8; 3]]) -> (usize, u8) {
+    (0..3)
+        .map(|channel| {
+            let values = pixels.iter().map(|pixel| pixel[channel]);
+            (channel, values.clone().max().unwrap() - values.min().unwrap())
+        })
+        .max_by_key(|(_, range)| *range)
+        .unwrap()
+}
+
+fn average(pixels: &[[u8; 3]]) -> (u8, u8, u8) {
+    let sum = pixels.iter().fold([0u64; 3], |sum, pixel| {
+        [sum[0] + pixel[0] as u64, sum[1] + pixel[1] as u64, sum[2] + pixel[2] as u64]
+    });
+    let count = pixels.len() as u64;
+    ((sum[0] / count) as u8, (sum[1] / count) as u8, (sum[2] / count) as u8)
+}
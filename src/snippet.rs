@@ -0,0 +1,179 @@
+use std::borrow::Cow;
+use std::fmt::{Display, Formatter};
+use ansi_term::{ANSIGenericString, Colour};
+use image::{DynamicImage, Rgba};
+use crate::bash_syntax;
+use crate::bash_syntax::ColorEncoding;
+use crate::colormath::{grayscale_image, CellColorMapper, ColorMappers};
+use crate::dither::Dither;
+use crate::renderer::{BrightStrategy, ColorMode, Emitter};
+use crate::glyphs::{AsciiRamp, GlyphMode};
+use crate::render::{to_monochrome, Alignment};
+use crate::terminal;
+
+pub struct ImageEmittingSnippet<'a> {
+    pub image: &'a DynamicImage,
+    pub color_mode: ColorMode,
+    pub mappers: &'a ColorMappers,
+    pub bright_strategy: BrightStrategy,
+    pub dither: Dither,
+    pub glyph_mode: &'a GlyphMode,
+    pub alignment: Alignment,
+    pub emitter: Emitter,
+}
+impl Display for ImageEmittingSnippet<'_> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        let syntax = self.emitter.syntax();
+        match self.color_mode {
+            ColorMode::Auto => {
+                let variants: Vec<(&str, String)> = AUTO_VARIANTS.iter()
+                    .map(|(name, color_mode)| (*name, self.escapes_for_color_mode(*color_mode)))
+                    .collect();
+                f.write_str(syntax.auto_detection_preamble())?;
+                syntax.write_dispatch(&variants, &self.plain_escapes(), f)
+            }
+            color_mode => syntax.write_print(&self.escapes_for_color_mode(color_mode), f),
+        }
+    }
+}
+
+/// The variants of the auto colour mode, by the value of `$gaudi_mode` that selects them. Any
+/// other value selects uncoloured ASCII art.
+pub const AUTO_VARIANTS: [(&str, ColorMode); 5] = [
+    ("truecolor", ColorMode::TrueColor),
+    ("256", ColorMode::Ansi256),
+    ("8", ColorMode::Ansi8),
+    ("ansi", ColorMode::Ansi),
+    ("mono", ColorMode::Mono),
+];
+
+impl ImageEmittingSnippet<'_> {
+    /// The escape sequences of the image without a shell around them, in the colour mode of the
+    /// terminal in the environment for [ColorMode::Auto]
+    pub fn raw_escapes(&self) -> String {
+        let (spans, encoding) = self.raw_spans();
+        escapes_for_spans(&spans, encoding)
+    }
+
+    /// The cells of [ImageEmittingSnippet::raw_escapes], and how their colours are written
+    fn raw_spans(&self) -> (Vec<ANSIGenericString<'static, str>>, ColorEncoding) {
+        match self.color_mode {
+            ColorMode::Auto => match terminal::color_mode_from_environment() {
+                Some(color_mode) => self.spans_for_color_mode(color_mode),
+                None => (self.plain_spans(), ColorEncoding::Extended),
+            },
+            color_mode => self.spans_for_color_mode(color_mode),
+        }
+    }
+
+    /// The image in the given colour mode, as it is sent to the terminal
+    fn escapes_for_color_mode(&self, color_mode: ColorMode) -> String {
+        let (spans, encoding) = self.spans_for_color_mode(color_mode);
+        escapes_for_spans(&spans, encoding)
+    }
+
+    /// The cells of the image in the given colour mode, and how their colours are written
+    pub fn spans_for_color_mode(&self, color_mode: ColorMode) -> (Vec<ANSIGenericString<'static, str>>, ColorEncoding) {
+        let mappers = self.mappers;
+        match color_mode {
+            ColorMode::TrueColor => (
+                self.glyph_mode.render(self.image, &self.alignment, &CellColorMapper::uniform(&|p| mappers.truecolor(p))),
+                ColorEncoding::Extended,
+            ),
+            ColorMode::Ansi => {
+                let image = self.dither.apply(self.image, mappers.ansi_matcher());
+                let ansi16 = |p: &_| mappers.ansi(p);
+                let ansi8 = |p: &_| mappers.ansi8(p);
+                let mapper = CellColorMapper {
+                    foreground: &ansi16,
+                    // bright backgrounds can't be reached through bold
+                    background: match self.bright_strategy {
+                        BrightStrategy::Aixterm => &ansi16,
+                        BrightStrategy::Bold => &ansi8,
+                    },
+                };
+                (self.glyph_mode.render(&image, &self.alignment, &mapper), ColorEncoding::Ansi16(self.bright_strategy))
+            },
+            ColorMode::Ansi256 => {
+                let image = self.dither.apply(self.image, mappers.ansi256_matcher());
+                (
+                    self.glyph_mode.render(&image, &self.alignment, &CellColorMapper::uniform(&|p| mappers.ansi256(p))),
+                    ColorEncoding::Extended,
+                )
+            },
+            ColorMode::Grayscale => {
+                let gray = grayscale_image(self.image);
+                let image = self.dither.apply(&gray, mappers.grayscale_matcher());
+                (
+                    self.glyph_mode.render(&image, &self.alignment, &CellColorMapper::uniform(&|p| mappers.grayscale(p))),
+                    ColorEncoding::Extended,
+                )
+            },
+            ColorMode::Mono => {
+                let image = match self.glyph_mode {
+                    // these pick their own lit pixels by luminance
+                    GlyphMode::Braille(_) | GlyphMode::Ascii(_) => Cow::Borrowed(self.image),
+                    _ => Cow::Owned(lit_pixels(&self.dither.apply(&grayscale_image(self.image), mappers.mono_matcher()))),
+                };
+                let spans = self.glyph_mode.render(&image, &self.alignment, &CellColorMapper::uniform(&|_| Colour::White));
+                (to_monochrome(spans), ColorEncoding::Extended)
+            },
+            ColorMode::Ansi8 => {
+                let image = self.dither.apply(self.image, mappers.ansi8_matcher());
+                (
+                    self.glyph_mode.render(&image, &self.alignment, &CellColorMapper::uniform(&|p| mappers.ansi8(p))),
+                    ColorEncoding::Extended,
+                )
+            },
+            ColorMode::Auto => unreachable!("auto is resolved to the other modes in the snippet"),
+        }
+    }
+
+    /// Uncoloured ASCII art, for when colours are disabled: with the ramp of the ASCII glyph mode if
+    /// that is the one in use, the default ramp otherwise
+    fn plain_escapes(&self) -> String {
+        escapes_for_spans(&self.plain_spans(), ColorEncoding::Extended)
+    }
+
+    /// The cells of [ImageEmittingSnippet::plain_escapes]
+    pub fn plain_spans(&self) -> Vec<ANSIGenericString<'static, str>> {
+        let ramp = match self.glyph_mode {
+            GlyphMode::Ascii(ramp) => AsciiRamp { colored: false, ..ramp.clone() },
+            _ => AsciiRamp::default(),
+        };
+        GlyphMode::Ascii(ramp).render(self.image, &self.alignment, &CellColorMapper::uniform(&|_| Colour::White))
+    }
+}
+
+fn escapes_for_spans(spans: &[ANSIGenericString<'static, str>], encoding: ColorEncoding) -> String {
+    capture_to_string(&|f| bash_syntax::write_with_minimal_control_sequences(spans, encoding, f))
+}
+
+/// Turns the pixels of an image matched against black and white into transparent ones where they
+/// are black, so that only the lit ones are drawn
+fn lit_pixels(image: &DynamicImage) -> DynamicImage {
+    let mut lit = image.to_rgba8();
+    for pixel in lit.pixels_mut() {
+        if pixel[0] < 128 {
+            *pixel = Rgba([0, 0, 0, 0]);
+        }
+    }
+    DynamicImage::ImageRgba8(lit)
+}
+
+pub fn capture_to_string(formats: &dyn Fn(&mut Formatter) -> std::fmt::Result) -> String {
+    let displayable = Displayable {
+        formats,
+    };
+
+    format!("{}", displayable)
+}
+
+struct Displayable<'a> {
+    formats: &'a dyn Fn(&mut Formatter) -> std::fmt::Result,
+}
+impl Display for Displayable<'_> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        (self.formats)(f)
+    }
+}
@@ -0,0 +1,86 @@
+use crate::renderer::{ColorMode, Passthrough};
+
+/// The colour mode the terminal of this process supports, going by the environment the way the
+/// auto snippet does, but without asking tput. None if colours are disabled.
+///
+/// Unlike the snippet, this doesn't check whether stdout is a terminal, as raw output is mostly
+/// redirected into a file to be shown on this terminal later.
+pub fn color_mode_from_environment() -> Option<ColorMode> {
+    let variable = |name| std::env::var(name).unwrap_or_default();
+    let term = variable("TERM");
+
+    if !variable("NO_COLOR").is_empty() || term == "dumb" {
+        None
+    } else if matches!(variable("COLORTERM").as_str(), "truecolor" | "24bit") || term.ends_with("-direct") {
+        Some(ColorMode::TrueColor)
+    } else if term == "linux" {
+        Some(ColorMode::Ansi8)
+    } else if term.ends_with("-256color") {
+        Some(ColorMode::Ansi256)
+    } else {
+        Some(ColorMode::Ansi)
+    }
+}
+
+/// The terminal multiplexer this process runs in, going by the variables tmux and screen set
+pub fn passthrough_from_environment() -> Passthrough {
+    let is_set = |name| std::env::var_os(name).is_some_and(|value| !value.is_empty());
+    if is_set("TMUX") {
+        Passthrough::Tmux
+    } else if is_set("STY") {
+        Passthrough::Screen
+    } else {
+        Passthrough::None
+    }
+}
+
+/// The size of the terminal in columns and rows, each None where it can't be found out. `$COLUMNS`
+/// and `$LINES` take precedence where they are set, the terminal driver is asked otherwise; either
+/// variable still counts where there is no terminal to ask, as when the output is piped.
+pub fn window_size() -> (Option<u32>, Option<u32>) {
+    let from_environment = |name| std::env::var(name).ok()?.parse::<u32>().ok().filter(|value| *value > 0);
+    match (from_environment("COLUMNS"), from_environment("LINES")) {
+        (Some(columns), Some(rows)) => (Some(columns), Some(rows)),
+        (columns, rows) => match window_size_from_driver() {
+            Some(size) => (Some(columns.unwrap_or(size.columns)), Some(rows.unwrap_or(size.rows))),
+            None => (columns, rows),
+        },
+    }
+}
+
+/// The size of a cell of the terminal in pixels, if the terminal driver knows the size of the
+/// window in pixels. Not every terminal fills that in.
+pub fn cell_size() -> Option<(u32, u32)> {
+    let size = window_size_from_driver()?;
+    let (width, height) = (size.width / size.columns, size.height / size.rows);
+    (width > 0 && height > 0).then_some((width, height))
+}
+
+struct WindowSize {
+    columns: u32,
+    rows: u32,
+    /// In pixels, 0 where unknown
+    width: u32,
+    height: u32,
+}
+
+#[cfg(unix)]
+fn window_size_from_driver() -> Option<WindowSize> {
+    // stdout is redirected more often than not, so the other standard streams are asked, too
+    [libc::STDOUT_FILENO, libc::STDERR_FILENO, libc::STDIN_FILENO].into_iter().find_map(|fd| {
+        let mut size = libc::winsize { ws_row: 0, ws_col: 0, ws_xpixel: 0, ws_ypixel: 0 };
+        // SAFETY: TIOCGWINSZ writes nothing but the winsize it is pointed at
+        let result = unsafe { libc::ioctl(fd, libc::TIOCGWINSZ, &mut size) };
+        (result == 0 && size.ws_col > 0 && size.ws_row > 0).then_some(WindowSize {
+            columns: size.ws_col as u32,
+            rows: size.ws_row as u32,
+            width: size.ws_xpixel as u32,
+            height: size.ws_ypixel as u32,
+        })
+    })
+}
+
+#[cfg(not(unix))]
+fn window_size_from_driver() -> Option<WindowSize> {
+    None
+}
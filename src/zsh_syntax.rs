@@ -0,0 +1,109 @@
+use std::fmt::Formatter;
+use std::time::Duration;
+use crate::animation::Repeat;
+use crate::shell_syntax::{mode_chain, write_case_dispatch, write_chain, write_sh_animation, write_sh_terminal_dispatch, ShellSyntax, TerminalCheck};
+
+/// The zsh equivalent of [crate::bash_syntax::AUTO_DETECTION_PREAMBLE], which asks the
+/// `zsh/terminfo` module rather than tput for the number of colours.
+pub const AUTO_DETECTION_PREAMBLE: &str = r#"gaudi_mode=ansi
+if [[ -n "${NO_COLOR-}" || "${TERM-}" == "dumb" || ! -t 1 ]]; then
+    gaudi_mode=plain
+elif [[ "${COLORTERM-}" == "truecolor" || "${COLORTERM-}" == "24bit" || "${TERM-}" == *-direct ]]; then
+    gaudi_mode=truecolor
+elif [[ "${TERM-}" == "linux" ]]; then
+    gaudi_mode=8
+else
+    if zmodload zsh/terminfo 2>/dev/null; then
+        gaudi_colors="${terminfo[colors]-}"
+    elif (( $+commands[tput] )); then
+        gaudi_colors="$(tput colors 2>/dev/null)"
+    else
+        case "${TERM-}" in
+            *-256color) gaudi_colors=256 ;;
+            *) gaudi_colors=8 ;;
+        esac
+    fi
+    if [[ "$gaudi_colors" == <-> ]]; then
+        if (( gaudi_colors >= 16777216 )); then
+            gaudi_mode=truecolor
+        elif (( gaudi_colors >= 256 )); then
+            gaudi_mode=256
+        elif (( gaudi_colors < 8 )); then
+            gaudi_mode=mono
+        fi
+    else
+        gaudi_mode=mono
+    fi
+fi
+"#;
+
+/// The zsh equivalent of [crate::bash_syntax::WIDTH_DETECTION_PREAMBLE]. zsh keeps `$COLUMNS` up to
+/// date itself, so it is only a fallback for when tput is missing.
+pub const WIDTH_DETECTION_PREAMBLE: &str = r#"gaudi_columns="$(tput cols 2>/dev/null)"
+if [[ "$gaudi_columns" != <-> ]]; then
+    gaudi_columns="${COLUMNS:-80}"
+fi
+"#;
+
+/// Escapes `payload` for `$'...'` quotes. Anything outside of ASCII is left as it is, zsh passes
+/// the bytes on whatever the locale.
+pub fn escape_for_dollar_quotes(payload: &str) -> String {
+    let mut out = String::with_capacity(payload.len());
+
+    for char in payload.chars() {
+        match char {
+            '\\' => out.push_str("\\\\"),
+            '\'' => out.push_str("\\'"),
+            '\u{1b}' => out.push_str("\\e"),
+            '\n' => out.push_str("\\n"),
+            _ if char.is_ascii_control() => out.push_str(&format!("\\x{:02x}", char as u32)),
+            _ => out.push(char),
+        }
+    }
+
+    out
+}
+
+pub struct ZshSyntax;
+impl ShellSyntax for ZshSyntax {
+    fn write_print(&self, escapes: &str, f: &mut Formatter) -> std::fmt::Result {
+        write!(f, "print -r -n -- $'{}'", escape_for_dollar_quotes(escapes))
+    }
+
+    fn auto_detection_preamble(&self) -> &'static str {
+        AUTO_DETECTION_PREAMBLE
+    }
+
+    fn write_dispatch(&self, variants: &[(&str, String)], fallback: &str, f: &mut Formatter) -> std::fmt::Result {
+        write_case_dispatch(self, variants, fallback, f)
+    }
+
+    fn write_snippet_dispatch(&self, variants: &[(&str, String)], fallback: &str, f: &mut Formatter) -> std::fmt::Result {
+        write_chain(
+            &mode_chain(variants, fallback),
+            ["if {}; then", "elif {}; then", "else", "fi"],
+            &|mode| format!("[[ \"$gaudi_mode\" == \"{}\" ]]", mode),
+            f,
+        )?;
+        f.write_str("unset gaudi_mode gaudi_colors\n")
+    }
+
+    fn write_width_dispatch(&self, variants: &[(u32, String)], f: &mut Formatter) -> std::fmt::Result {
+        f.write_str(WIDTH_DETECTION_PREAMBLE)?;
+        write_chain(
+            variants,
+            ["if {}; then", "elif {}; then", "else", "fi"],
+            &|columns| format!("(( gaudi_columns >= {} ))", columns),
+            f,
+        )?;
+        f.write_str("unset gaudi_columns\n")
+    }
+
+    fn write_terminal_dispatch(&self, check: TerminalCheck, escapes: &str, fallback: &str, f: &mut Formatter) -> std::fmt::Result {
+        write_sh_terminal_dispatch(self, check, escapes, fallback, f)
+    }
+
+    fn write_animation(&self, start: &str, frames: &[(String, Duration)], end: &str, repeat: Repeat, f: &mut Formatter) -> std::fmt::Result {
+        write_sh_animation(self, "trap", start, frames, end, repeat, f)
+    }
+}
@@ -0,0 +1,77 @@
+//! Checks where half-block images with an odd number of pixel rows end up, and the indent of
+//! --align-width.
+
+use image::{DynamicImage, Rgba, RgbaImage};
+use gaudi::{Alignment, ColorMode, HorizontalAlignment, OutputFormat, Renderer, VerticalAlignment};
+
+const IMAGE_WIDTH: u32 = 3;
+
+/// The raw output for an opaque image of 3x5 pixels
+fn rendered(alignment: Alignment) -> String {
+    let image = RgbaImage::from_pixel(IMAGE_WIDTH, 5, Rgba([200, 30, 40, 255]));
+    Renderer::new(DynamicImage::ImageRgba8(image))
+        .color_mode(ColorMode::TrueColor)
+        .alignment(alignment)
+        .output_format(OutputFormat::Raw)
+        .render()
+        .unwrap()
+}
+
+/// [rendered] line by line, without control sequences
+fn rendered_lines(alignment: Alignment) -> Vec<String> {
+    rendered(alignment).lines().map(without_control_sequences).collect()
+}
+
+fn without_control_sequences(line: &str) -> String {
+    let mut visible = String::new();
+    let mut in_sequence = false;
+    for char in line.chars() {
+        match char {
+            '\x1b' => in_sequence = true,
+            'm' if in_sequence => in_sequence = false,
+            _ if in_sequence => {}
+            _ => visible.push(char),
+        }
+    }
+
+    visible
+}
+
+fn aligned(vertical: VerticalAlignment) -> Alignment {
+    Alignment { vertical, ..Alignment::default() }
+}
+
+#[test]
+fn top_leaves_the_spare_half_row_at_the_bottom() {
+    let lines = rendered_lines(aligned(VerticalAlignment::Top));
+    assert_eq!(lines, ["▄▄▄", "▄▄▄", "▀▀▀"]);
+}
+
+#[test]
+fn bottom_leaves_the_spare_half_row_at_the_top() {
+    let lines = rendered_lines(aligned(VerticalAlignment::Bottom));
+    assert_eq!(lines, ["▄▄▄", "▄▄▄", "▄▄▄"]);
+    // only the first row is drawn on the terminal background
+    let backgrounds: Vec<bool> = rendered(aligned(VerticalAlignment::Bottom)).lines().map(|line| line.contains("\x1b[48;2;")).collect();
+    assert_eq!(backgrounds, [false, true, true]);
+}
+
+#[test]
+fn center_leaves_the_spare_half_row_at_the_bottom_like_top() {
+    assert_eq!(rendered_lines(aligned(VerticalAlignment::Center)), rendered_lines(aligned(VerticalAlignment::Top)));
+}
+
+#[test]
+fn align_width_indents_by_the_remaining_columns() {
+    let align_width = 10;
+    for (horizontal, indent) in [
+        (HorizontalAlignment::Left, 0),
+        (HorizontalAlignment::Center, (align_width - IMAGE_WIDTH) / 2),
+        (HorizontalAlignment::Right, align_width - IMAGE_WIDTH),
+    ] {
+        let alignment = Alignment { vertical: VerticalAlignment::Bottom, horizontal, width: Some(align_width) };
+        for line in rendered_lines(alignment) {
+            assert_eq!(line, format!("{}▄▄▄", " ".repeat(indent as usize)), "{:?}", horizontal);
+        }
+    }
+}
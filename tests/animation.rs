@@ -0,0 +1,327 @@
+//! Writes an animated GIF, reads its frames back, and plays the snippet in bash and dash to check
+//! that replaying the changes between frames leaves the screen as drawing each frame in full would,
+//! and that the cursor is restored, also on Ctrl-C.
+
+mod common;
+
+use std::collections::HashMap;
+use std::path::Path;
+use std::process::{Command, Stdio};
+use std::time::{Duration, Instant};
+use image::{Delay, DynamicImage, Rgba, RgbaImage};
+use image::codecs::gif::{GifEncoder, Repeat as GifRepeat};
+use gaudi::{ColorMode, Emitter, Frame, OutputFormat, Renderer, Repeat};
+use common::{find_program, run_snippet, shell_command, Terminal};
+
+/// A bar that moves to the right
+fn frame_image(index: u32) -> RgbaImage {
+    RgbaImage::from_fn(12, 8, |x, _| match x / 3 == index {
+        true => Rgba([255, 255, 255, 255]),
+        false => Rgba([0, 0, 0, 255]),
+    })
+}
+
+const DELAYS_MS: [u32; 3] = [50, 200, 0];
+
+fn write_gif(path: &Path) {
+    let file = std::fs::File::create(path).unwrap();
+    let mut encoder = GifEncoder::new(file);
+    encoder.set_repeat(GifRepeat::Infinite).unwrap();
+    let frames = DELAYS_MS.iter().enumerate().map(|(index, delay)| {
+        image::Frame::from_parts(frame_image(index as u32), 0, 0, Delay::from_numer_denom_ms(*delay, 1))
+    });
+    encoder.encode_frames(frames).unwrap();
+}
+
+fn frames() -> Vec<Frame> {
+    DELAYS_MS.iter().enumerate()
+        .map(|(index, delay)| Frame {
+            image: DynamicImage::ImageRgba8(frame_image(index as u32)),
+            delay: Duration::from_millis(*delay as u64),
+        })
+        .collect()
+}
+
+#[test]
+fn frames_are_read_with_their_delays() {
+    let path = std::env::temp_dir().join(format!("gaudi-animation-{}.gif", std::process::id()));
+    write_gif(&path);
+    let loaded = gaudi::load_frames(&path).unwrap();
+    std::fs::remove_file(&path).unwrap();
+
+    assert_eq!(loaded.len(), DELAYS_MS.len());
+    for (index, frame) in loaded.iter().enumerate() {
+        assert_eq!(frame.delay, Duration::from_millis(DELAYS_MS[index] as u64), "frame {}", index);
+        assert_eq!(frame.image.to_rgba8(), frame_image(index as u32), "frame {}", index);
+    }
+}
+
+#[test]
+fn still_images_are_a_single_frame() {
+    let path = std::env::temp_dir().join(format!("gaudi-still-{}.png", std::process::id()));
+    frame_image(0).save(&path).unwrap();
+    let loaded = gaudi::load_frames(&path).unwrap();
+    std::fs::remove_file(&path).unwrap();
+
+    assert_eq!(loaded.len(), 1);
+    assert_eq!(loaded[0].image.to_rgba8(), frame_image(0));
+}
+
+/// What a terminal shows after the escapes: the character and the colours of every cell drawn
+#[derive(Debug, Default)]
+struct Screen {
+    cells: HashMap<(usize, usize), (char, Sgr)>,
+    /// Row and column
+    cursor: (usize, usize),
+    sgr: Sgr,
+}
+
+#[derive(Debug, Clone, Default, PartialEq)]
+struct Sgr {
+    bold: bool,
+    foreground: Option<String>,
+    background: Option<String>,
+}
+
+impl Screen {
+    fn feed(&mut self, escapes: &str) {
+        let mut chars = escapes.chars();
+        while let Some(c) = chars.next() {
+            match c {
+                '\x1b' => {
+                    assert_eq!(chars.next(), Some('['), "CSI");
+                    let mut parameters = String::new();
+                    let command = loop {
+                        match chars.next().expect("final byte") {
+                            c if c.is_ascii_alphabetic() => break c,
+                            c => parameters.push(c),
+                        }
+                    };
+                    let count = parameters.parse::<usize>().unwrap_or(1);
+                    match command {
+                        'A' => self.cursor.0 = self.cursor.0.checked_sub(count).expect("cursor above the image"),
+                        'B' => self.cursor.0 += count,
+                        'C' => self.cursor.1 += count,
+                        'm' => self.select_graphic_rendition(&parameters),
+                        'l' | 'h' => assert_eq!(parameters, "?25", "only the cursor is shown and hidden"),
+                        _ => panic!("unexpected CSI {}{}", parameters, command),
+                    }
+                }
+                // the terminal driver turns it into CR LF
+                '\n' => self.cursor = (self.cursor.0 + 1, 0),
+                '\r' => self.cursor.1 = 0,
+                _ => {
+                    self.cells.insert(self.cursor, (c, self.sgr.clone()));
+                    self.cursor.1 += 1;
+                }
+            }
+        }
+    }
+
+    fn select_graphic_rendition(&mut self, parameters: &str) {
+        let codes: Vec<&str> = parameters.split(';').collect();
+        let mut index = 0;
+        while index < codes.len() {
+            match codes[index].parse::<u32>().unwrap_or(0) {
+                0 => self.sgr = Sgr::default(),
+                1 => self.sgr.bold = true,
+                code @ (38 | 48) => {
+                    let length = if codes[index + 1] == "5" { 2 } else { 4 };
+                    let colour = Some(codes[index + 1..=index + length].join(";"));
+                    if code == 38 {
+                        self.sgr.foreground = colour;
+                    } else {
+                        self.sgr.background = colour;
+                    }
+                    index += length;
+                }
+                code @ (30..=37 | 90..=97) => self.sgr.foreground = Some(code.to_string()),
+                code @ (40..=47 | 100..=107) => self.sgr.background = Some(code.to_string()),
+                code => panic!("unexpected SGR {}", code),
+            }
+            index += 1;
+        }
+    }
+}
+
+#[test]
+fn snippet_sleeps_for_the_delays() {
+    let snippet = Renderer::animated(frames())
+        .color_mode(ColorMode::Mono)
+        .repeat(Repeat::Times(2))
+        .render()
+        .unwrap();
+
+    let path = std::env::var("PATH").unwrap();
+    let started = Instant::now();
+    if run_snippet(Emitter::Bash, &snippet, &[("PATH", &path)], Terminal::None).is_none() {
+        return eprintln!("snippet_sleeps_for_the_delays: skipped, bash is not installed");
+    }
+    // 50 ms, 200 ms and 100 ms for the frame without a delay, twice
+    assert!(started.elapsed() >= Duration::from_millis(700), "{:?}", started.elapsed());
+}
+
+/// A gradient with a square that moves over it, stays, and moves again, then all of it inverted
+fn changing_frames() -> Vec<RgbaImage> {
+    let with_square = |left: u32| RgbaImage::from_fn(24, 16, move |x, y| {
+        match (left..left + 4).contains(&x) && (5..9).contains(&y) {
+            true => Rgba([255, 255, 0, 255]),
+            false => Rgba([(x * 10) as u8, (y * 15) as u8, 128, 255]),
+        }
+    });
+    let mut inverted = with_square(6);
+    image::imageops::invert(&mut inverted);
+    vec![with_square(0), with_square(3), with_square(3), with_square(6), inverted]
+}
+
+#[test]
+fn replayed_screens_match_the_full_frames() {
+    let marker_dir = std::env::temp_dir().join(format!("gaudi-sleep-{}", std::process::id()));
+    std::fs::create_dir_all(&marker_dir).unwrap();
+    // instead of sleeping, marks the end of each frame in the output
+    let fake_sleep = marker_dir.join("sleep");
+    std::fs::write(&fake_sleep, "#!/bin/sh\nprintf '\\007'\n").unwrap();
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        std::fs::set_permissions(&fake_sleep, std::fs::Permissions::from_mode(0o755)).unwrap();
+    }
+
+    let images = changing_frames();
+    let full_frames: Vec<String> = images.iter()
+        .map(|image| {
+            Renderer::new(DynamicImage::ImageRgba8(image.clone()))
+                .color_mode(ColorMode::TrueColor)
+                .output_format(OutputFormat::Raw)
+                .render()
+                .unwrap()
+        })
+        .collect();
+    let frames: Vec<Frame> = images.into_iter()
+        .map(|image| Frame { image: DynamicImage::ImageRgba8(image), delay: Duration::from_millis(40) })
+        .collect();
+
+    for (shell, emitter) in [("bash", Emitter::Bash), ("dash", Emitter::Posix)] {
+        let snippet = Renderer::animated(frames.clone())
+            .color_mode(ColorMode::TrueColor)
+            .repeat(Repeat::Times(2))
+            .emitter(emitter)
+            .render()
+            .unwrap();
+        let Some(output) = run_snippet(emitter, &snippet, &[("PATH", &marker_dir.display().to_string())], Terminal::None) else {
+            eprintln!("replayed_screens_match_the_full_frames: skipped for {}, it is not installed", shell);
+            continue;
+        };
+
+        // only the cells that changed are drawn, where that is cheaper
+        let naive: usize = full_frames.iter().map(String::len).sum::<usize>() * 2;
+        assert!(output.len() * 3 < naive * 2, "{}: {} bytes, {} in full frames", shell, output.len(), naive);
+
+        let played: Vec<&str> = output.split('\x07').collect();
+        assert_eq!(played.len(), 2 * full_frames.len() + 1, "{}", shell);
+        let mut screen = Screen::default();
+        for (index, escapes) in played[..played.len() - 1].iter().enumerate() {
+            screen.feed(escapes);
+            let mut expected = Screen::default();
+            expected.feed(&full_frames[index % full_frames.len()]);
+            assert_eq!(screen.cells, expected.cells, "{}: frame {}", shell, index);
+            assert_eq!(screen.cursor, expected.cursor, "{}: frame {}", shell, index);
+        }
+        screen.feed(played[played.len() - 1]);
+        assert_eq!(screen.sgr, Sgr::default(), "{}", shell);
+    }
+    std::fs::remove_dir_all(&marker_dir).unwrap();
+}
+
+#[cfg(unix)]
+#[test]
+fn ctrl_c_ends_the_animation_and_shows_the_cursor() {
+    use std::os::unix::process::CommandExt;
+
+    for emitter in [Emitter::Bash, Emitter::Posix, Emitter::Fish] {
+        let (program, arguments, _) = shell_command(emitter);
+        let Some(shell) = find_program(program) else {
+            eprintln!("ctrl_c_ends_the_animation_and_shows_the_cursor: skipped for {}, it is not installed", program);
+            continue;
+        };
+        let snippet = Renderer::animated(frames())
+            .color_mode(ColorMode::Mono)
+            .repeat(Repeat::Forever)
+            .emitter(emitter)
+            .render()
+            .unwrap();
+        let child = Command::new(shell)
+            .args(arguments)
+            .arg("-c")
+            .arg(&snippet)
+            .env("LC_ALL", "C.UTF-8")
+            .stdout(Stdio::piped())
+            // a process group of its own, like a job on a terminal, so that sleep gets the signal as well
+            .process_group(0)
+            .spawn()
+            .unwrap();
+        std::thread::sleep(Duration::from_millis(500));
+        let killed = Command::new("kill").arg("-INT").arg("--").arg(format!("-{}", child.id())).status().unwrap();
+        assert!(killed.success());
+
+        let output = child.wait_with_output().unwrap();
+        assert!(output.status.success(), "{}", program);
+        let output = String::from_utf8(output.stdout).unwrap();
+        assert!(output.starts_with("\x1b[?25l"), "{}: {:?}", program, output);
+        // the end is printed once, right after the last frame
+        assert!(output.ends_with("\x1b[0m\x1b[?25h"), "{}: {:?}", program, output);
+        assert_eq!(output.matches("\x1b[?25h").count(), 1, "{}: {:?}", program, output);
+    }
+}
+
+/// The animation of the auto colour mode, played once
+fn auto_animation() -> String {
+    Renderer::animated(frames())
+        .color_mode(ColorMode::Auto)
+        .repeat(Repeat::Times(1))
+        .render()
+        .unwrap()
+}
+
+#[test]
+fn auto_animations_detect_the_terminal_they_play_on() {
+    let path = std::env::var("PATH").unwrap();
+    let environment = [("PATH", path.as_str()), ("TERM", "xterm"), ("COLORTERM", "truecolor")];
+    let Some(piped) = run_snippet(Emitter::Bash, &auto_animation(), &environment, Terminal::None) else {
+        return eprintln!("auto_animations_detect_the_terminal_they_play_on: skipped, bash is not installed");
+    };
+    assert!(!piped.contains("38;") && !piped.contains("48;"), "{:?}", piped);
+    assert!(piped.contains('#') || piped.contains('@'), "{:?}", piped);
+
+    let Some(truecolor) = run_snippet(Emitter::Bash, &auto_animation(), &environment, Terminal::Pseudo) else {
+        return eprintln!("auto_animations_detect_the_terminal_they_play_on: skipped, script is not installed");
+    };
+    assert!(truecolor.contains("38;2;"), "{:?}", truecolor);
+
+    let no_color_environment = [environment.as_slice(), &[("NO_COLOR", "1")]].concat();
+    let Some(no_color) = run_snippet(Emitter::Bash, &auto_animation(), &no_color_environment, Terminal::Pseudo) else {
+        return eprintln!("auto_animations_detect_the_terminal_they_play_on: skipped, script is not installed");
+    };
+    assert!(!no_color.contains("38;") && !no_color.contains("48;"), "{:?}", no_color);
+}
+
+#[test]
+fn snippet_restores_the_int_trap_of_the_shell_that_sources_it() {
+    let snippet = |emitter| Renderer::animated(frames()).color_mode(ColorMode::Mono).emitter(emitter).render().unwrap();
+    let path = std::env::var("PATH").unwrap();
+    let environment = [("PATH", path.as_str())];
+
+    let sourced = format!("trap 'echo outer' INT\n{}trap -p INT\n", snippet(Emitter::Bash));
+    let Some(output) = run_snippet(Emitter::Bash, &sourced, &environment, Terminal::None) else {
+        return eprintln!("snippet_restores_the_int_trap_of_the_shell_that_sources_it: skipped, bash is not installed");
+    };
+    assert!(output.ends_with("\x1b[?25htrap -- 'echo outer' SIGINT\n"), "{:?}", output);
+
+    // without a trap before, none is left behind
+    for emitter in [Emitter::Bash, Emitter::Posix] {
+        let Some(output) = run_snippet(emitter, &format!("{}trap\n", snippet(emitter)), &environment, Terminal::None) else {
+            return eprintln!("snippet_restores_the_int_trap_of_the_shell_that_sources_it: skipped, dash is not installed");
+        };
+        assert!(output.ends_with("\x1b[?25h"), "{:?}: {:?}", emitter, output);
+    }
+}
@@ -0,0 +1,136 @@
+//! Runs the snippet of the auto colour mode under bash, with controlled environment variables, and
+//! checks which variant it prints. The variants that need stdout to be a terminal are run through
+//! `script`, which provides a pseudo terminal; they are skipped where `script` is not installed,
+//! other than on CI.
+
+mod common;
+
+use image::{DynamicImage, Rgba, RgbaImage};
+use gaudi::{ColorMode, Emitter, Renderer};
+use common::{directory_of, run_snippet, Terminal};
+
+fn snippet() -> String {
+    let image = RgbaImage::from_fn(6, 4, |x, y| match (x + y) % 3 {
+        0 => Rgba([200, 30, 40, 255]),
+        1 => Rgba([20, 180, 60, 255]),
+        _ => Rgba([240, 240, 240, 255]),
+    });
+    Renderer::new(DynamicImage::ImageRgba8(image))
+        .color_mode(ColorMode::Auto)
+        .render()
+        .unwrap()
+}
+
+/// Runs the snippet under bash with `env`, and a PATH that has tput unless `without_tput`. None
+/// if the test can't run here.
+fn run(env: &[(&str, &str)], on_terminal: bool, without_tput: bool) -> Option<String> {
+    let path = match without_tput {
+        true => "/nonexistent".to_string(),
+        false => directory_of("tput")?,
+    };
+    let env = [&[("PATH", path.as_str())], env].concat();
+    let terminal = if on_terminal { Terminal::Pseudo } else { Terminal::None };
+    run_snippet(Emitter::Bash, &snippet(), &env, terminal)
+}
+
+fn assert_plain(output: &str) {
+    assert!(!output.contains('\x1b'), "expected no escape sequences in {:?}", output);
+    assert!(output.is_ascii(), "expected plain ASCII in {:?}", output);
+    assert!(!output.trim().is_empty());
+}
+
+fn skipped(test_name: &str) {
+    eprintln!("{}: skipped, bash, tput or script is not installed", test_name);
+}
+
+#[test]
+fn no_color_gives_plain_ascii() {
+    let env = [("TERM", "xterm-256color"), ("COLORTERM", "truecolor"), ("NO_COLOR", "1")];
+    match run(&env, true, false) {
+        Some(output) => assert_plain(&output),
+        None => skipped("no_color_gives_plain_ascii"),
+    }
+}
+
+#[test]
+fn dumb_terminal_gives_plain_ascii() {
+    match run(&[("TERM", "dumb")], true, false) {
+        Some(output) => assert_plain(&output),
+        None => skipped("dumb_terminal_gives_plain_ascii"),
+    }
+}
+
+#[test]
+fn output_that_is_no_terminal_gives_plain_ascii() {
+    let env = [("TERM", "xterm-256color"), ("COLORTERM", "truecolor")];
+    match run(&env, false, false) {
+        Some(output) => assert_plain(&output),
+        None => skipped("output_that_is_no_terminal_gives_plain_ascii"),
+    }
+}
+
+#[test]
+fn colorterm_gives_truecolor() {
+    let env = [("TERM", "xterm"), ("COLORTERM", "24bit")];
+    match run(&env, true, false) {
+        Some(output) => assert!(output.contains("38;2;"), "{:?}", output),
+        None => skipped("colorterm_gives_truecolor"),
+    }
+}
+
+#[test]
+fn direct_terminfo_gives_truecolor() {
+    match run(&[("TERM", "xterm-direct")], true, false) {
+        Some(output) => assert!(output.contains("38;2;"), "{:?}", output),
+        None => skipped("direct_terminfo_gives_truecolor"),
+    }
+}
+
+#[test]
+fn tput_256_colors_gives_256_colors() {
+    match run(&[("TERM", "xterm-256color")], true, false) {
+        Some(output) => {
+            assert!(output.contains("38;5;"), "{:?}", output);
+            assert!(!output.contains("38;2;"), "{:?}", output);
+        }
+        None => skipped("tput_256_colors_gives_256_colors"),
+    }
+}
+
+#[test]
+fn linux_console_gives_8_colors() {
+    match run(&[("TERM", "linux")], true, false) {
+        Some(output) => {
+            assert!(output.contains('\x1b'), "{:?}", output);
+            assert!(!output.contains(";5;") && !output.contains(";2;"), "{:?}", output);
+            assert!(!output.contains("\x1b[9") && !output.contains(";9"), "{:?}", output);
+        }
+        None => skipped("linux_console_gives_8_colors"),
+    }
+}
+
+#[test]
+fn missing_tput_falls_back_to_term() {
+    match run(&[("TERM", "screen-256color")], true, true) {
+        Some(output) => assert!(output.contains("38;5;"), "{:?}", output),
+        None => skipped("missing_tput_falls_back_to_term"),
+    }
+    match run(&[("TERM", "xterm")], true, true) {
+        Some(output) => {
+            assert!(output.contains('\x1b'), "{:?}", output);
+            assert!(!output.contains(";5;") && !output.contains(";2;"), "{:?}", output);
+        }
+        None => skipped("missing_tput_falls_back_to_term"),
+    }
+}
+
+#[test]
+fn terminal_without_colors_gives_mono() {
+    match run(&[("TERM", "vt100")], true, false) {
+        Some(output) => {
+            assert!(!output.contains('\x1b'), "{:?}", output);
+            assert!(output.contains('█') || output.contains('▀') || output.contains('▄'), "{:?}", output);
+        }
+        None => skipped("terminal_without_colors_gives_mono"),
+    }
+}
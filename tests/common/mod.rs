@@ -0,0 +1,155 @@
+//! What the integration tests share: finding programs, running snippets in their shells, test
+//! images and a base64 decoder for the payloads of the graphics protocols.
+
+// every test crate has its own copy of this module and uses only some of it
+#![allow(dead_code)]
+
+use std::io::Write;
+use std::path::PathBuf;
+use std::process::{Command, Stdio};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use image::{DynamicImage, Rgba, RgbaImage};
+use gaudi::{ColorMode, Emitter, GlyphMode, Renderer};
+
+/// The program on the PATH, None where it isn't installed so that the test is skipped. On CI, where
+/// the workflow installs everything the tests run, a missing program fails the test instead.
+pub fn find_program(name: &str) -> Option<PathBuf> {
+    let program = std::env::split_paths(&std::env::var_os("PATH")?)
+        .map(|dir| dir.join(name))
+        .find(|path| path.is_file());
+    if program.is_none() && std::env::var_os("CI").is_some() {
+        panic!("{} is not installed, which the tests need on CI", name);
+    }
+    program
+}
+
+/// The directory of `program`, to put on the PATH of a snippet
+pub fn directory_of(program: &str) -> Option<String> {
+    find_program(program).map(|path| path.parent().unwrap().display().to_string())
+}
+
+/// Where the output of a snippet goes
+#[derive(Debug, Clone, Copy)]
+pub enum Terminal<'a> {
+    /// Redirected, so that the snippet doesn't see a terminal
+    None,
+    /// A pseudo terminal from `script`
+    Pseudo,
+    /// A pseudo terminal from `script` that types in the answer once the snippet asks for it
+    Answering(&'a [u8]),
+}
+
+/// The program and the arguments that run a script without reading any user configuration, and
+/// the extension the script needs
+pub fn shell_command(emitter: Emitter) -> (&'static str, &'static [&'static str], &'static str) {
+    match emitter {
+        Emitter::Bash => ("bash", &["--norc", "--noprofile"], "sh"),
+        Emitter::Posix => ("dash", &[], "sh"),
+        Emitter::Zsh => ("zsh", &["-f"], "zsh"),
+        Emitter::Fish => ("fish", &["--no-config"], "fish"),
+        Emitter::Pwsh => ("pwsh", &["-NoLogo", "-NoProfile", "-NonInteractive", "-File"], "ps1"),
+    }
+}
+
+/// Runs `snippet` in the shell of `emitter`, with nothing but a UTF-8 locale, a scratch home, a
+/// PATH without programs and `env` in the environment. None if the shell, or `script` for a
+/// terminal, isn't installed.
+pub fn run_snippet(emitter: Emitter, snippet: &str, env: &[(&str, &str)], terminal: Terminal) -> Option<String> {
+    static RUNS: AtomicUsize = AtomicUsize::new(0);
+
+    let (program, arguments, extension) = shell_command(emitter);
+    let shell = find_program(program)?;
+    let home = std::env::temp_dir().join(format!("gaudi-home-{}-{}", std::process::id(), RUNS.fetch_add(1, Ordering::Relaxed)));
+    std::fs::create_dir_all(&home).unwrap();
+    let path = home.join(format!("snippet.{}", extension));
+    std::fs::write(&path, snippet).unwrap();
+
+    let mut command = match terminal {
+        Terminal::None => {
+            let mut command = Command::new(&shell);
+            command.args(arguments).arg(&path).env_clear();
+            command
+        }
+        Terminal::Pseudo | Terminal::Answering(_) => {
+            let Some(script) = find_program("script") else {
+                std::fs::remove_dir_all(&home).unwrap();
+                return None;
+            };
+            let mut command = Command::new(script);
+            let command_line = [shell.display().to_string()].into_iter()
+                .chain(arguments.iter().map(|argument| argument.to_string()))
+                .chain([path.display().to_string()])
+                .collect::<Vec<String>>()
+                .join(" ");
+            command.arg("-q").arg("-e").arg("-c").arg(command_line).arg("/dev/null");
+            command.env_clear().env("SHELL", "/bin/sh");
+            command
+        }
+    };
+    command.env("PATH", "/nonexistent")
+        .env("HOME", &home)
+        .env("LC_ALL", "C.UTF-8")
+        .envs(env.iter().copied());
+
+    let output = match terminal {
+        Terminal::Answering(answer) => {
+            let mut child = command.stdin(Stdio::piped()).stdout(Stdio::piped()).spawn().unwrap();
+            // give the snippet time to switch off echo before answering
+            std::thread::sleep(std::time::Duration::from_millis(300));
+            let mut stdin = child.stdin.take().unwrap();
+            stdin.write_all(answer).unwrap();
+            // closing stdin would end the input of the terminal, so it stays open until the snippet is done
+            let output = child.wait_with_output().unwrap();
+            drop(stdin);
+            output
+        }
+        _ => command.output().unwrap(),
+    };
+    std::fs::remove_dir_all(&home).unwrap();
+    assert!(output.status.success(), "snippet failed: {}", String::from_utf8_lossy(&output.stderr));
+    Some(String::from_utf8(output.stdout).unwrap())
+}
+
+/// Two flat colours, a gradient and transparent pixels, for comparing the output of the emitters
+pub fn pattern() -> DynamicImage {
+    DynamicImage::ImageRgba8(RgbaImage::from_fn(12, 9, |x, y| match (x + 2 * y) % 4 {
+        0 => Rgba([200, 30, 40, 255]),
+        1 => Rgba([20, 180, 60, 255]),
+        2 => Rgba([(x * 20) as u8, (y * 25) as u8, 120, 255]),
+        _ => Rgba([0, 0, 0, 0]),
+    }))
+}
+
+/// The snippet of [pattern]
+pub fn render_pattern(color_mode: ColorMode, glyph_mode: GlyphMode, emitter: Emitter) -> String {
+    Renderer::new(pattern())
+        .color_mode(color_mode)
+        .glyph_mode(glyph_mode)
+        .emitter(emitter)
+        .render()
+        .unwrap()
+}
+
+/// Noise, so that PNGs of it don't compress to next to nothing, transparent where `transparent`
+pub fn noise(width: u32, height: u32, transparent: impl Fn(u32, u32) -> bool) -> RgbaImage {
+    RgbaImage::from_fn(width, height, |x, y| {
+        let hash = (x * 7919 + y * 104729).wrapping_mul(2654435761);
+        Rgba([hash as u8, (hash >> 8) as u8, (hash >> 16) as u8, if transparent(x, y) { 0 } else { 255 }])
+    })
+}
+
+pub fn base64_decode(payload: &str) -> Vec<u8> {
+    const ALPHABET: &str = "ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+    let values: Vec<u32> = payload.trim_end_matches('=')
+        .chars()
+        .map(|c| ALPHABET.find(c).expect("base64 character") as u32)
+        .collect();
+    let mut bytes = Vec::new();
+    for group in values.chunks(4) {
+        let bits = group.iter().enumerate().fold(0, |bits, (index, value)| bits | value << (18 - 6 * index));
+        for index in 0..group.len() - 1 {
+            bytes.push((bits >> (16 - 8 * index)) as u8);
+        }
+    }
+    bytes
+}
@@ -0,0 +1,75 @@
+//! Runs the gaudi binary on broken input and invalid options, and checks the documented exit code
+//! and the one-line message for each kind of error.
+
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use image::{Rgba, RgbaImage};
+
+/// A file in the temporary directory, removed again when dropped
+struct TempFile(PathBuf);
+
+impl TempFile {
+    fn new(name: &str, content: &[u8]) -> Self {
+        let path = std::env::temp_dir().join(format!("gaudi-exit-{}-{}", std::process::id(), name));
+        std::fs::write(&path, content).unwrap();
+        TempFile(path)
+    }
+
+    fn png(name: &str) -> Self {
+        let path = std::env::temp_dir().join(format!("gaudi-exit-{}-{}", std::process::id(), name));
+        RgbaImage::from_fn(16, 16, |x, y| Rgba([(x * 16) as u8, (y * 16) as u8, 128, 255])).save(&path).unwrap();
+        TempFile(path)
+    }
+}
+
+impl Drop for TempFile {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_file(&self.0);
+    }
+}
+
+/// The exit code and what was written to stderr
+fn gaudi(arguments: &[&str], input: &Path) -> (i32, String) {
+    let output = Command::new(env!("CARGO_BIN_EXE_gaudi")).args(arguments).arg(input).output().unwrap();
+    assert!(output.stdout.is_empty(), "{:?}", String::from_utf8_lossy(&output.stdout));
+    (output.status.code().unwrap(), String::from_utf8(output.stderr).unwrap())
+}
+
+fn assert_one_line(stderr: &str, starts_with: &str) {
+    assert!(stderr.starts_with(starts_with), "{:?}", stderr);
+    assert_eq!(stderr.lines().count(), 1, "{:?}", stderr);
+}
+
+#[test]
+fn missing_file_is_an_io_error() {
+    let path = std::env::temp_dir().join(format!("gaudi-exit-{}-missing.png", std::process::id()));
+    let (code, stderr) = gaudi(&[], &path);
+    assert_eq!(code, 3);
+    assert_one_line(&stderr, &format!("gaudi: could not open {}: ", path.display()));
+}
+
+#[test]
+fn file_that_is_no_image_is_an_unsupported_format() {
+    let file = TempFile::new("text.png", b"not an image at all\n");
+    let (code, stderr) = gaudi(&[], &file.0);
+    assert_eq!(code, 4);
+    assert_eq!(stderr, format!("gaudi: {}: unsupported image format\n", file.0.display()));
+}
+
+#[test]
+fn truncated_png_is_a_decode_error() {
+    let png = TempFile::png("complete.png");
+    let content = std::fs::read(&png.0).unwrap();
+    let truncated = TempFile::new("truncated.png", &content[..content.len() / 2]);
+    let (code, stderr) = gaudi(&[], &truncated.0);
+    assert_eq!(code, 5);
+    assert_one_line(&stderr, &format!("gaudi: {}: failed to decode image data: ", truncated.0.display()));
+}
+
+#[test]
+fn invalid_option_exits_like_a_usage_error() {
+    let png = TempFile::png("fit.png");
+    let (code, stderr) = gaudi(&["--fit", "0x3"], &png.0);
+    assert_eq!(code, 2);
+    assert_eq!(stderr, "gaudi: invalid value for fit: needs at least one column and one row\n");
+}
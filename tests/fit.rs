@@ -0,0 +1,102 @@
+//! Checks that `fit` keeps the image within the given cells for every glyph mode, and that a
+//! snippet with several widths prints the one that suits the terminal.
+
+mod common;
+
+use image::{DynamicImage, Rgba, RgbaImage};
+use gaudi::{ColorMode, Emitter, GlyphMode, OutputFormat, Renderer};
+use gaudi::glyphs::{AsciiRamp, DotThreshold};
+use common::{run_snippet, Terminal};
+
+fn image(width: u32, height: u32) -> DynamicImage {
+    DynamicImage::ImageRgba8(RgbaImage::from_fn(width, height, |x, y| match (x / 3 + y / 3) % 2 {
+        0 => Rgba([240, 240, 240, 255]),
+        _ => Rgba([30, 30, 30, 255]),
+    }))
+}
+
+/// Columns and rows of uncoloured output
+fn cells(output: &str) -> (usize, usize) {
+    let lines: Vec<&str> = output.lines().collect();
+    (lines.iter().map(|line| line.chars().count()).max().unwrap_or(0), lines.len())
+}
+
+#[test]
+fn fit_stays_within_the_cells_for_every_glyph_mode() {
+    let glyph_modes = [
+        GlyphMode::HalfBlock,
+        GlyphMode::Quadrant,
+        GlyphMode::Sextant,
+        GlyphMode::Octant,
+        GlyphMode::Braille(DotThreshold::Fixed(128)),
+        GlyphMode::Ascii(AsciiRamp::default()),
+    ];
+    for glyph_mode in glyph_modes {
+        for (width, height, fill) in [(400, 100, "columns"), (100, 400, "rows")] {
+            let output = Renderer::new(image(width, height))
+                .color_mode(ColorMode::Mono)
+                .glyph_mode(glyph_mode.clone())
+                .output_format(OutputFormat::Raw)
+                .fit(Some((30, 10)))
+                .render()
+                .unwrap();
+            let (columns, rows) = cells(&output);
+            assert!(columns <= 30 && rows <= 10, "{:?}: {}x{}", glyph_mode, columns, rows);
+            // one side is filled completely, the other one keeps the aspect ratio
+            match fill {
+                "columns" => assert_eq!(columns, 30, "{:?}", glyph_mode),
+                _ => assert_eq!(rows, 10, "{:?}", glyph_mode),
+            }
+        }
+    }
+}
+
+#[test]
+fn snippet_prints_the_widest_width_that_fits() {
+    let snippet = Renderer::new(image(60, 30))
+        .color_mode(ColorMode::Mono)
+        .snippet_widths(vec![10, 40, 20])
+        .render()
+        .unwrap();
+
+    for (terminal_columns, expected_columns) in [(100, 40), (39, 20), (20, 20), (12, 10), (5, 10)] {
+        // without tput, the snippet goes by $COLUMNS
+        let Some(output) = run_snippet(Emitter::Bash, &snippet, &[("COLUMNS", &terminal_columns.to_string())], Terminal::None) else {
+            return eprintln!("snippet_prints_the_widest_width_that_fits: skipped, bash is not installed");
+        };
+        let (columns, _) = cells(&output);
+        assert_eq!(columns, expected_columns, "on a terminal {} columns wide", terminal_columns);
+    }
+}
+
+/// Runs the gaudi binary with `--fit terminal` on a checkerboard, with its output piped so that
+/// there is no terminal to ask for its size
+fn fit_terminal(environment: &[(&str, &str)]) -> std::process::Output {
+    let path = std::env::temp_dir().join(format!("gaudi-fit-terminal-{}-{}.png", std::process::id(), environment.len()));
+    image(60, 30).save(&path).unwrap();
+    let output = std::process::Command::new(env!("CARGO_BIN_EXE_gaudi"))
+        .args(["--fit", "terminal", "--color-mode", "mono", "--output-format", "raw"])
+        .arg(&path)
+        .env_remove("COLUMNS")
+        .env_remove("LINES")
+        .envs(environment.iter().copied())
+        .output()
+        .unwrap();
+    std::fs::remove_file(&path).unwrap();
+    output
+}
+
+#[test]
+fn fit_terminal_goes_by_columns_alone_without_a_terminal() {
+    let output = fit_terminal(&[("COLUMNS", "20")]);
+    assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+    assert_eq!(cells(&String::from_utf8(output.stdout).unwrap()), (20, 5));
+
+    let output = fit_terminal(&[("LINES", "6")]);
+    assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+    // one row is left for the prompt
+    assert_eq!(cells(&String::from_utf8(output.stdout).unwrap()).1, 5);
+
+    let output = fit_terminal(&[]);
+    assert_eq!(output.status.code(), Some(2));
+}
@@ -0,0 +1,117 @@
+//! Decodes the iTerm2 inline images again to check them against the image, unwraps them from the
+//! multiplexer passthrough, and runs the snippet behind `script` inside and outside of iTerm2.
+
+mod common;
+
+use std::collections::HashMap;
+use image::{DynamicImage, RgbaImage};
+use gaudi::{ColorMode, Emitter, GaudiError, OutputFormat, Passthrough, Protocol, Renderer};
+use common::{base64_decode, noise, run_snippet, Terminal};
+
+/// Noise, so that the PNG is larger than what screen passes on at once
+fn image() -> RgbaImage {
+    noise(40, 30, |_, y| y < 4)
+}
+
+fn render(passthrough: Passthrough) -> Result<String, GaudiError> {
+    Renderer::new(DynamicImage::ImageRgba8(image()))
+        .protocol(Protocol::ITerm2)
+        .output_format(OutputFormat::Raw)
+        .cell_size(Some((10, 20)))
+        .passthrough(passthrough)
+        .render()
+}
+
+/// The arguments of the inline image, and its file
+fn decode(stream: &str) -> (HashMap<String, String>, Vec<u8>) {
+    let body = stream.strip_prefix("\x1b]1337;File=").expect("OSC 1337");
+    let body = body.strip_suffix("\x07\n").expect("BEL");
+    let (arguments, payload) = body.split_once(':').unwrap();
+    let arguments = arguments.split(';')
+        .map(|argument| {
+            let (key, value) = argument.split_once('=').unwrap();
+            (key.to_string(), value.to_string())
+        })
+        .collect();
+    (arguments, base64_decode(payload))
+}
+
+#[test]
+fn inline_image_decodes_to_the_image() {
+    let (arguments, png) = decode(&render(Passthrough::None).unwrap());
+    for (key, value) in [("inline", "1"), ("width", "4"), ("height", "2"), ("preserveAspectRatio", "1")] {
+        assert_eq!(arguments.get(key).map(String::as_str), Some(value), "{}", key);
+    }
+    assert_eq!(arguments["size"], png.len().to_string());
+    assert_eq!(image::load_from_memory(&png).unwrap().to_rgba8(), image());
+}
+
+#[test]
+fn passthrough_unwraps_to_the_inline_image() {
+    let plain = render(Passthrough::None).unwrap();
+
+    let tmux = render(Passthrough::Tmux).unwrap();
+    let inner = tmux.strip_prefix("\x1bPtmux;").and_then(|rest| rest.strip_suffix("\x1b\\\n")).expect("tmux DCS");
+    assert_eq!(inner.replace("\x1b\x1b", "\x1b") + "\n", plain);
+
+    let screen = render(Passthrough::Screen).unwrap();
+    let pieces: Vec<&str> = screen.strip_suffix('\n').unwrap().split_terminator("\x1b\\").collect();
+    assert!(pieces.len() > 1, "split into pieces");
+    let mut unwrapped = String::new();
+    for piece in pieces {
+        let piece = piece.strip_prefix("\x1bP").expect("screen DCS");
+        assert!(piece.len() <= 768, "piece of {} bytes", piece.len());
+        unwrapped.push_str(piece);
+    }
+    assert_eq!(unwrapped + "\n", plain);
+}
+
+#[test]
+fn screen_is_refused_for_protocols_with_string_terminators() {
+    let result = Renderer::new(DynamicImage::ImageRgba8(image()))
+        .protocol(Protocol::Kitty)
+        .passthrough(Passthrough::Screen)
+        .render();
+    assert!(matches!(result, Err(GaudiError::InvalidOption { .. })), "{:?}", result);
+}
+
+/// Runs the snippet of the auto colour mode on a truecolor pseudo terminal with `variable` set
+fn run_auto_snippet(variable: (&str, &str)) -> Option<String> {
+    let snippet = Renderer::new(DynamicImage::ImageRgba8(image()))
+        .protocol(Protocol::ITerm2)
+        .color_mode(ColorMode::Auto)
+        .render()
+        .unwrap();
+    let environment = [("TERM", "xterm-256color"), ("COLORTERM", "truecolor"), variable];
+    run_snippet(Emitter::Bash, &snippet, &environment, Terminal::Pseudo)
+}
+
+#[test]
+fn snippet_prints_inline_images_inside_iterm2() {
+    let environments = [
+        ("TERM_PROGRAM", "iTerm.app"),
+        ("TERM_PROGRAM", "WezTerm"),
+        // within tmux
+        ("LC_TERMINAL", "iTerm2"),
+    ];
+    for variable in environments {
+        match run_auto_snippet(variable) {
+            Some(output) => {
+                assert!(output.contains("\x1b]1337;File=inline=1;"), "{:?}", output);
+                assert!(!output.contains('▀') && !output.contains('▄'), "{:?}", output);
+            }
+            None => return eprintln!("snippet_prints_inline_images_inside_iterm2: skipped, bash or script is not installed"),
+        }
+    }
+}
+
+#[test]
+fn snippet_falls_back_to_cells_elsewhere() {
+    match run_auto_snippet(("TERM_PROGRAM", "Apple_Terminal")) {
+        Some(output) => {
+            assert!(!output.contains("\x1b]1337"), "{:?}", output);
+            assert!(output.contains("\x1b[38;2;") || output.contains("\x1b[48;2;"), "{:?}", output);
+        }
+        None => eprintln!("snippet_falls_back_to_cells_elsewhere: skipped, bash or script is not installed"),
+    }
+}
@@ -0,0 +1,104 @@
+//! Decodes the kitty graphics output again to check it against the image, and runs the snippet
+//! behind `script` inside and outside of kitty.
+
+mod common;
+
+use image::{DynamicImage, RgbaImage};
+use gaudi::{ColorMode, Emitter, OutputFormat, Protocol, Renderer};
+use common::{base64_decode, noise, run_snippet, Terminal};
+
+/// Noise, so that the PNG doesn't compress into a single chunk
+fn image() -> RgbaImage {
+    noise(64, 48, |x, _| x < 8)
+}
+
+/// The control data of the first escape sequence, and the payload of all of them
+fn decode(stream: &str) -> (String, Vec<u8>) {
+    let body = stream.strip_suffix('\n').expect("line break");
+    let sequences: Vec<&str> = body.split_terminator("\x1b\\").collect();
+    let mut control = String::new();
+    let mut payload = String::new();
+    for (index, sequence) in sequences.iter().enumerate() {
+        let (keys, chunk) = sequence.strip_prefix("\x1b_G").expect("APC introducer").split_once(';').unwrap();
+        assert!(chunk.len() <= 4096, "chunk of {} bytes", chunk.len());
+        let more = if index + 1 < sequences.len() { "m=1" } else { "m=0" };
+        assert!(keys.split(',').any(|key| key == more), "{:?} in chunk {}", keys, index);
+        if index == 0 {
+            control = keys.to_string();
+        } else {
+            assert_eq!(keys, more, "only the first chunk has the control data");
+        }
+        payload.push_str(chunk);
+    }
+    (control, base64_decode(&payload))
+}
+
+#[test]
+fn payload_decodes_to_the_image() {
+    let image = image();
+    let stream = Renderer::new(DynamicImage::ImageRgba8(image.clone()))
+        .protocol(Protocol::Kitty)
+        .output_format(OutputFormat::Raw)
+        .cell_size(Some((10, 20)))
+        .render()
+        .unwrap();
+
+    assert!(stream.matches("\x1b_G").count() > 1, "the payload is split into chunks");
+    let (control, png) = decode(&stream);
+    for key in ["a=T", "f=100", "q=2", "c=7", "r=3"] {
+        assert!(control.split(',').any(|other| other == key), "{} in {:?}", key, control);
+    }
+    let decoded = image::load_from_memory(&png).unwrap().to_rgba8();
+    assert_eq!(decoded, image);
+}
+
+#[test]
+fn cells_follow_the_resize_options() {
+    let stream = Renderer::new(DynamicImage::ImageRgba8(image()))
+        .protocol(Protocol::Kitty)
+        .output_format(OutputFormat::Raw)
+        .cell_size(Some((8, 16)))
+        .fit(Some((4, 10)))
+        .render()
+        .unwrap();
+
+    let (control, png) = decode(&stream);
+    assert!(control.contains("c=4,r=2"), "{:?}", control);
+    let decoded = image::load_from_memory(&png).unwrap();
+    assert_eq!((decoded.width(), decoded.height()), (32, 24));
+}
+
+/// The snippet of the auto colour mode, which prints the kitty graphics only inside kitty
+fn snippet() -> String {
+    Renderer::new(DynamicImage::ImageRgba8(image()))
+        .protocol(Protocol::Kitty)
+        .color_mode(ColorMode::Auto)
+        .render()
+        .unwrap()
+}
+
+#[test]
+fn snippet_prints_kitty_graphics_inside_kitty() {
+    for variable in [("TERM", "xterm-kitty"), ("KITTY_WINDOW_ID", "1")] {
+        match run_snippet(Emitter::Bash, &snippet(), &[("COLORTERM", "truecolor"), variable], Terminal::Pseudo) {
+            Some(output) => {
+                assert!(output.contains("\x1b_Ga=T,f=100"), "{:?}", output);
+                assert!(!output.contains('▀') && !output.contains('▄'), "{:?}", output);
+            }
+            None => return eprintln!("snippet_prints_kitty_graphics_inside_kitty: skipped, bash or script is not installed"),
+        }
+    }
+}
+
+#[test]
+fn snippet_falls_back_to_cells_elsewhere() {
+    let environment = [("COLORTERM", "truecolor"), ("TERM", "xterm-256color")];
+    match run_snippet(Emitter::Bash, &snippet(), &environment, Terminal::Pseudo) {
+        Some(output) => {
+            assert!(!output.contains("\x1b_G"), "{:?}", output);
+            // the truecolor variant of the auto snippet
+            assert!(output.contains("\x1b[38;2;") || output.contains("\x1b[48;2;"), "{:?}", output);
+        }
+        None => eprintln!("snippet_falls_back_to_cells_elsewhere: skipped, bash or script is not installed"),
+    }
+}
@@ -0,0 +1,80 @@
+//! Runs the snippets of `--shell posix` under dash and checks that they print exactly what the bash
+//! snippets print under bash. Skipped where dash or bash is not installed, other than on CI.
+
+mod common;
+
+use gaudi::{ColorMode, Emitter, GlyphMode};
+use gaudi::glyphs::AsciiRamp;
+use common::{directory_of, find_program, render_pattern, run_snippet, Terminal};
+
+/// Runs `snippet` with a PATH that has tput, where it is installed, and `env`. None if the shell,
+/// or `script` on a terminal, isn't installed.
+fn run(emitter: Emitter, snippet: &str, env: &[(&str, &str)], terminal: Terminal) -> Option<String> {
+    let path = directory_of("tput").unwrap_or_else(|| "/nonexistent".to_string());
+    run_snippet(emitter, snippet, &[&[("PATH", path.as_str())], env].concat(), terminal)
+}
+
+fn assert_same_output_as_bash(name: &str, color_mode: ColorMode, glyph_mode: GlyphMode) {
+    let bash = run(Emitter::Bash, &render_pattern(color_mode, glyph_mode.clone(), Emitter::Bash), &[], Terminal::None);
+    let dash = run(Emitter::Posix, &render_pattern(color_mode, glyph_mode, Emitter::Posix), &[], Terminal::None);
+    match (bash, dash) {
+        (Some(bash), Some(dash)) => {
+            assert!(!dash.is_empty());
+            assert_eq!(dash, bash);
+        }
+        _ => eprintln!("{}: skipped, bash or dash is not installed", name),
+    }
+}
+
+#[test]
+fn truecolor_half_blocks() {
+    assert_same_output_as_bash("truecolor", ColorMode::TrueColor, GlyphMode::HalfBlock);
+}
+
+#[test]
+fn ansi_256_quadrants() {
+    assert_same_output_as_bash("256", ColorMode::Ansi256, GlyphMode::Quadrant);
+}
+
+#[test]
+fn ansi_sextants_outside_of_the_basic_multilingual_plane() {
+    assert_same_output_as_bash("sextant", ColorMode::Ansi, GlyphMode::Sextant);
+}
+
+#[test]
+fn mono_half_blocks() {
+    assert_same_output_as_bash("mono", ColorMode::Mono, GlyphMode::HalfBlock);
+}
+
+#[test]
+fn ascii_ramp_with_characters_special_to_printf_and_sh() {
+    let ramp = AsciiRamp::new(" %\\'\"$`#", true);
+    assert_same_output_as_bash("ascii", ColorMode::TrueColor, GlyphMode::Ascii(ramp));
+}
+
+#[test]
+fn auto_detection_under_dash() {
+    let snippet = render_pattern(ColorMode::Auto, GlyphMode::HalfBlock, Emitter::Posix);
+
+    match run(Emitter::Posix, &snippet, &[("TERM", "xterm-256color")], Terminal::None) {
+        Some(output) => {
+            assert!(output.is_ascii() && !output.contains('\x1b'), "{:?}", output);
+        }
+        None => eprintln!("auto_detection_under_dash: skipped, dash is not installed"),
+    }
+    if find_program("tput").is_none() {
+        return eprintln!("auto_detection_under_dash: skipped on a terminal, tput is not installed");
+    }
+    match run(Emitter::Posix, &snippet, &[("TERM", "xterm-256color")], Terminal::Pseudo) {
+        Some(output) => {
+            assert!(output.contains("38;5;") && !output.contains("38;2;"), "{:?}", output);
+        }
+        None => eprintln!("auto_detection_under_dash: skipped, dash or script is not installed"),
+    }
+    match run(Emitter::Posix, &snippet, &[("TERM", "linux")], Terminal::Pseudo) {
+        Some(output) => {
+            assert!(output.contains('\x1b') && !output.contains(";5;") && !output.contains(";2;"), "{:?}", output);
+        }
+        None => eprintln!("auto_detection_under_dash: skipped, dash or script is not installed"),
+    }
+}
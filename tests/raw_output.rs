@@ -0,0 +1,36 @@
+//! Checks that raw output is the escape stream the bash snippet prints, without the snippet around it.
+
+mod common;
+
+use image::{DynamicImage, Rgba, RgbaImage};
+use gaudi::{ColorMode, Emitter, GlyphMode, OutputFormat, Renderer};
+use common::{run_snippet, Terminal};
+
+fn renderer() -> Renderer {
+    let image = RgbaImage::from_fn(8, 6, |x, y| match (x + y) % 3 {
+        0 => Rgba([200, 30, 40, 255]),
+        1 => Rgba([20, 180, 60, 255]),
+        _ => Rgba([0, 0, 0, 0]),
+    });
+    Renderer::new(DynamicImage::ImageRgba8(image))
+        .color_mode(ColorMode::Ansi256)
+        .glyph_mode(GlyphMode::Sextant)
+}
+
+#[test]
+fn raw_output_is_what_the_snippet_prints() {
+    let Some(printed) = run_snippet(Emitter::Bash, &renderer().render().unwrap(), &[], Terminal::None) else {
+        return eprintln!("raw_output_is_what_the_snippet_prints: skipped, bash is not installed");
+    };
+
+    let raw = renderer().output_format(OutputFormat::Raw).render().unwrap();
+    assert!(raw.contains("\x1b[38;5;"), "{:?}", raw);
+    assert_eq!(raw, printed);
+}
+
+#[test]
+fn reset_at_end_appends_reset_and_line_clear() {
+    let raw = renderer().output_format(OutputFormat::Raw).render().unwrap();
+    let reset = renderer().output_format(OutputFormat::Raw).reset_at_end(true).render().unwrap();
+    assert_eq!(reset, format!("{}\x1b[0m\x1b[K", raw));
+}
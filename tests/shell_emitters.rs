@@ -0,0 +1,67 @@
+//! Runs the snippets of the zsh, fish and PowerShell emitters in their shells and checks that they
+//! print exactly what the bash snippets print under bash. Each shell is skipped where it is not
+//! installed, other than on CI.
+
+mod common;
+
+use gaudi::{ColorMode, Emitter, GlyphMode};
+use gaudi::glyphs::AsciiRamp;
+use common::{render_pattern, run_snippet, shell_command, Terminal};
+
+fn assert_same_output_as_bash(emitter: Emitter, color_mode: ColorMode, glyph_mode: GlyphMode) {
+    let name = format!("{:?}-{:?}", emitter, color_mode).to_lowercase();
+    let bash = run_snippet(Emitter::Bash, &render_pattern(color_mode, glyph_mode.clone(), Emitter::Bash), &[], Terminal::None);
+    let other = run_snippet(emitter, &render_pattern(color_mode, glyph_mode, emitter), &[], Terminal::None);
+    match (bash, other) {
+        (Some(bash), Some(other)) => {
+            assert!(!other.is_empty());
+            assert_eq!(other, bash);
+        }
+        _ => eprintln!("{}: skipped, bash or {} is not installed", name, shell_command(emitter).0),
+    }
+}
+
+fn assert_all_modes_same_as_bash(emitter: Emitter) {
+    assert_same_output_as_bash(emitter, ColorMode::TrueColor, GlyphMode::HalfBlock);
+    assert_same_output_as_bash(emitter, ColorMode::Ansi256, GlyphMode::Quadrant);
+    assert_same_output_as_bash(emitter, ColorMode::Ansi, GlyphMode::Sextant);
+    assert_same_output_as_bash(emitter, ColorMode::Mono, GlyphMode::HalfBlock);
+    let ramp = AsciiRamp::new(" %\\'\"$`#", true);
+    assert_same_output_as_bash(emitter, ColorMode::Ansi8, GlyphMode::Ascii(ramp));
+}
+
+/// The auto snippet prints uncoloured ASCII art when its output is not a terminal
+fn assert_auto_is_plain_when_redirected(emitter: Emitter) {
+    let name = format!("{:?}-auto", emitter).to_lowercase();
+    match run_snippet(emitter, &render_pattern(ColorMode::Auto, GlyphMode::HalfBlock, emitter), &[], Terminal::None) {
+        Some(output) => {
+            assert!(output.is_ascii() && !output.contains('\x1b'), "{:?}", output);
+            assert!(!output.trim().is_empty());
+        }
+        None => eprintln!("{}: skipped, {} is not installed", name, shell_command(emitter).0),
+    }
+}
+
+#[test]
+fn zsh_prints_what_bash_prints() {
+    assert_all_modes_same_as_bash(Emitter::Zsh);
+    assert_auto_is_plain_when_redirected(Emitter::Zsh);
+}
+
+#[test]
+fn fish_prints_what_bash_prints() {
+    assert_all_modes_same_as_bash(Emitter::Fish);
+    assert_auto_is_plain_when_redirected(Emitter::Fish);
+}
+
+#[test]
+fn pwsh_prints_what_bash_prints() {
+    assert_all_modes_same_as_bash(Emitter::Pwsh);
+    assert_auto_is_plain_when_redirected(Emitter::Pwsh);
+}
+
+#[test]
+fn bash_and_posix_auto_are_plain_when_redirected() {
+    assert_auto_is_plain_when_redirected(Emitter::Bash);
+    assert_auto_is_plain_when_redirected(Emitter::Posix);
+}
@@ -0,0 +1,166 @@
+//! Decodes the sixel output again to check it against the image, and runs the sixel snippet behind
+//! `script` with and without a terminal answer that lists sixel support.
+
+mod common;
+
+use std::collections::HashMap;
+use image::{DynamicImage, Rgba, RgbaImage};
+use gaudi::{ColorMode, Emitter, OutputFormat, Protocol, Renderer};
+use common::{directory_of, find_program, run_snippet, Terminal};
+
+fn image() -> RgbaImage {
+    RgbaImage::from_fn(23, 14, |x, y| match (x / 4 + y / 3) % 4 {
+        0 => Rgba([255, 0, 0, 255]),
+        1 => Rgba([0, 128, 255, 255]),
+        2 => Rgba([255, 255, 255, 255]),
+        _ => Rgba([0, 0, 0, 0]),
+    })
+}
+
+/// The colours of drawn pixels as percentages, by position
+type Pixels = HashMap<(u32, u32), (u32, u32, u32)>;
+
+/// The pixels a sixel stream draws, and the size from its raster attributes
+fn decode(stream: &str) -> (Pixels, (u32, u32)) {
+    let body = stream.strip_prefix("\x1bP0;1;0q").expect("sixel introducer");
+    let body = body.strip_suffix("\x1b\\\n").expect("string terminator");
+    let mut chars = body.chars().peekable();
+
+    let mut registers = HashMap::new();
+    let mut pixels = HashMap::new();
+    let (mut size, mut register, mut x, mut band) = ((0, 0), 0, 0, 0);
+    while let Some(c) = chars.next() {
+        match c {
+            '"' => {
+                let mut parameters = [0; 4];
+                for (index, parameter) in parameters.iter_mut().enumerate() {
+                    *parameter = number(&mut chars);
+                    if index < 3 {
+                        assert_eq!(chars.next(), Some(';'));
+                    }
+                }
+                assert_eq!(&parameters[..2], &[1, 1], "square pixels");
+                size = (parameters[2], parameters[3]);
+            }
+            '#' => {
+                register = number(&mut chars);
+                if chars.peek() == Some(&';') {
+                    let mut parameters = [0; 4];
+                    for parameter in parameters.iter_mut() {
+                        assert_eq!(chars.next(), Some(';'));
+                        *parameter = number(&mut chars);
+                    }
+                    assert_eq!(parameters[0], 2, "RGB colour");
+                    registers.insert(register, (parameters[1], parameters[2], parameters[3]));
+                }
+            }
+            '$' => x = 0,
+            '-' => {
+                x = 0;
+                band += 1;
+            }
+            '!' => {
+                let count = number(&mut chars);
+                let sixel = chars.next().unwrap();
+                for _ in 0..count {
+                    draw(&mut pixels, registers[&register], sixel, x, band);
+                    x += 1;
+                }
+            }
+            '?'..='~' => {
+                draw(&mut pixels, registers[&register], c, x, band);
+                x += 1;
+            }
+            _ => panic!("unexpected {:?}", c),
+        }
+    }
+    (pixels, size)
+}
+
+fn number(chars: &mut std::iter::Peekable<std::str::Chars>) -> u32 {
+    let mut value = 0;
+    while let Some(digit) = chars.peek().and_then(|c| c.to_digit(10)) {
+        value = value * 10 + digit;
+        chars.next();
+    }
+    value
+}
+
+fn draw(pixels: &mut Pixels, color: (u32, u32, u32), sixel: char, x: u32, band: u32) {
+    let bits = sixel as u32 - 63;
+    for dy in 0..6 {
+        if bits & (1 << dy) != 0 {
+            pixels.insert((x, band * 6 + dy), color);
+        }
+    }
+}
+
+fn percent(value: u8) -> u32 {
+    (value as u32 * 100 + 127) / 255
+}
+
+#[test]
+fn sixels_decode_to_the_image() {
+    let image = image();
+    let stream = Renderer::new(DynamicImage::ImageRgba8(image.clone()))
+        .protocol(Protocol::Sixel)
+        .output_format(OutputFormat::Raw)
+        .render()
+        .unwrap();
+    // runs of the same sixel are compressed
+    assert!(stream.contains('!'), "{:?}", stream);
+
+    let (pixels, size) = decode(&stream);
+    assert_eq!(size, image.dimensions());
+    for (x, y, pixel) in image.enumerate_pixels() {
+        let expected = (pixel[3] > 0).then(|| (percent(pixel[0]), percent(pixel[1]), percent(pixel[2])));
+        assert_eq!(pixels.get(&(x, y)).copied(), expected, "at {}x{}", x, y);
+    }
+}
+
+#[test]
+fn colours_are_quantised_to_256_registers() {
+    let gradient = RgbaImage::from_fn(64, 64, |x, y| Rgba([(x * 4) as u8, (y * 4) as u8, ((x + y) * 2) as u8, 255]));
+    let stream = Renderer::new(DynamicImage::ImageRgba8(gradient))
+        .protocol(Protocol::Sixel)
+        .output_format(OutputFormat::Raw)
+        .render()
+        .unwrap();
+
+    let (pixels, _) = decode(&stream);
+    assert_eq!(pixels.len(), 64 * 64);
+    assert!(stream.contains("#255;2;") && !stream.contains("#256;2;"));
+}
+
+/// Runs the snippet on a pseudo terminal, with `answer` typed in as the terminal's response
+fn run_snippet_answering(answer: &[u8]) -> Option<String> {
+    find_program("dd")?;
+    let snippet = Renderer::new(DynamicImage::ImageRgba8(image()))
+        .protocol(Protocol::Sixel)
+        .color_mode(ColorMode::TrueColor)
+        .render()
+        .unwrap();
+    run_snippet(Emitter::Bash, &snippet, &[("PATH", &directory_of("stty")?)], Terminal::Answering(answer))
+}
+
+#[test]
+fn snippet_prints_sixels_where_the_terminal_supports_them() {
+    match run_snippet_answering(b"\x1b[?62;4;22c") {
+        Some(output) => {
+            assert!(output.contains("\x1bP0;1;0q"), "{:?}", output);
+            assert!(!output.contains('▀') && !output.contains('▄'), "{:?}", output);
+        }
+        None => eprintln!("snippet_prints_sixels_where_the_terminal_supports_them: skipped, bash, stty, dd or script is not installed"),
+    }
+}
+
+#[test]
+fn snippet_falls_back_to_cells_elsewhere() {
+    match run_snippet_answering(b"\x1b[?62;22;44c") {
+        Some(output) => {
+            assert!(!output.contains("\x1bP"), "{:?}", output);
+            assert!(output.contains('▀') || output.contains('▄'), "{:?}", output);
+        }
+        None => eprintln!("snippet_falls_back_to_cells_elsewhere: skipped, bash, stty, dd or script is not installed"),
+    }
+}